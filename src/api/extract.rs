@@ -0,0 +1,235 @@
+use std::{fmt, str::FromStr};
+
+use alloy::primitives::Address;
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Path, Query, Request},
+    http::{header::AUTHORIZATION, request::Parts},
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    config,
+    error::Error,
+    wallet::policy::{self, ADMIN_OPERATOR},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumStatus {
+    /// Mixed-case input whose casing matches EIP-55.
+    Valid,
+    /// All-lowercase or all-uppercase input, which carries no checksum.
+    Missing,
+    /// Mixed-case input whose casing does not match EIP-55.
+    Invalid,
+}
+
+#[derive(Debug, Clone)]
+pub struct AddressCheck {
+    pub address: Address,
+    pub checksum: ChecksumStatus,
+}
+
+/// Checks that `input` is a `0x`-prefixed 20 byte hex address and reports
+/// whether its casing is a correct EIP-55 checksum.
+pub fn check_address(input: &str) -> Result<AddressCheck, String> {
+    let hex = input
+        .strip_prefix("0x")
+        .ok_or_else(|| String::from("Address must start with 0x"))?;
+
+    if hex.len() != 40 {
+        return Err(format!(
+            "Address must be 40 hex characters, got {}",
+            hex.len()
+        ));
+    }
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(String::from("Address contains non-hex characters"));
+    }
+
+    let address = Address::from_str(input).map_err(|e| e.to_string())?;
+
+    let has_lower = hex.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = hex.chars().any(|c| c.is_ascii_uppercase());
+    let checksum = if !(has_lower && has_upper) {
+        ChecksumStatus::Missing
+    } else if Address::parse_checksummed(input, None).is_ok() {
+        ChecksumStatus::Valid
+    } else {
+        ChecksumStatus::Invalid
+    };
+
+    Ok(AddressCheck { address, checksum })
+}
+
+/// An address accepted at the API boundary.
+///
+/// Mixed-case input must carry a correct EIP-55 checksum; single-case input
+/// is accepted as is. Displays and serializes in checksummed form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidAddress(pub Address);
+
+impl FromStr for ValidAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let check = check_address(s).map_err(|e| Error::InvalidInput(format!("{}: {}", e, s)))?;
+        if check.checksum == ChecksumStatus::Invalid {
+            return Err(Error::InvalidInput(format!(
+                "Address has an invalid EIP-55 checksum: {}",
+                s
+            )));
+        }
+        Ok(Self(check.address))
+    }
+}
+
+impl fmt::Display for ValidAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.to_checksum(None))
+    }
+}
+
+impl<'de> Deserialize<'de> for ValidAddress {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for ValidAddress {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Extracts a single address path parameter, e.g. `/balance/:address`.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ValidAddress {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(address) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| Error::InvalidInput(e.body_text()))?;
+        address.parse()
+    }
+}
+
+/// `axum::Json` with rejections reported through [`Error`].
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(req, state)
+            .await
+            .map_err(|e| Error::InvalidInput(e.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// `axum::extract::Query` with rejections reported through [`Error`].
+pub struct ApiQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| Error::InvalidInput(e.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// `axum::extract::Path` with rejections reported through [`Error`].
+pub struct ApiPath<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| Error::InvalidInput(e.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// Guard for admin routes. Requires `Authorization: Bearer <ADMIN_TOKEN>`.
+pub struct AdminAuth;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminAuth {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let expected = config::admin_token()
+            .ok_or_else(|| Error::Unauthorized(String::from("Admin API is disabled")))?;
+
+        let provided = bearer_token(parts)?;
+        if !tokens_match(provided, expected) {
+            return Err(Error::Unauthorized(String::from("Invalid bearer token")));
+        }
+
+        Ok(AdminAuth)
+    }
+}
+
+/// Guard for withdrawal routes, carrying the name of the operator calling.
+/// Accepts the token of one of `OPERATORS`, or `ADMIN_TOKEN` as operator
+/// `admin`.
+pub struct OperatorAuth(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for OperatorAuth {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let operators = policy::operators_from_config()?;
+        if config::admin_token().is_none() && operators.is_empty() {
+            return Err(Error::Unauthorized(String::from("Admin API is disabled")));
+        }
+
+        let provided = bearer_token(parts)?;
+        if config::admin_token().is_some_and(|expected| tokens_match(provided, expected)) {
+            return Ok(Self(String::from(ADMIN_OPERATOR)));
+        }
+        operators
+            .into_iter()
+            .find(|operator| tokens_match(provided, &operator.token))
+            .map(|operator| Self(operator.name))
+            .ok_or_else(|| Error::Unauthorized(String::from("Invalid bearer token")))
+    }
+}
+
+fn bearer_token(parts: &Parts) -> Result<&str, Error> {
+    parts
+        .headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| Error::Unauthorized(String::from("Missing bearer token")))
+}
+
+fn tokens_match(provided: &str, expected: &str) -> bool {
+    ring::constant_time::verify_slices_are_equal(provided.as_bytes(), expected.as_bytes()).is_ok()
+}
@@ -1,17 +1,38 @@
+pub mod extract;
+#[cfg(unix)]
+pub mod unlock;
+
 use std::sync::Arc;
 
-use alloy::{primitives::U256, providers::RootProvider, pubsub::PubSubFrontend};
+use alloy::primitives::{Address, Bytes, U256};
+use alloy_dyn_abi::TypedData;
 use axum::{
-    extract::{FromRef, Path, State},
-    http::StatusCode,
+    extract::State,
     routing::{get, post},
     Json, Router,
 };
+use extract::{
+    check_address, AdminAuth, ApiJson, ApiPath, ApiQuery, ChecksumStatus, OperatorAuth,
+    ValidAddress,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    config::{self, secrets::Secret},
+    error::{Error, Result},
     pubsub::ChainEvent,
-    wallet::usdt::contract::{get_balance, get_receive_logs, TransferLog},
+    wallet::{
+        accounts::Account,
+        blacklist::FrozenAddress,
+        database::AddressRecord,
+        discovery::DiscoveryReport,
+        fees::{FeeEstimate, Urgency},
+        nonces::NonceState,
+        policy::{AuditEntry, NewWithdrawal, WithdrawalRequest, WithdrawalStatus},
+        signing::{self, SignedMessage},
+        tracker::{TrackedTransaction, TxStatus},
+        usdt::contract::{get_balance, get_receive_logs, TransferLog},
+    },
     EthServWallet,
 };
 
@@ -19,121 +40,586 @@ use crate::{
 pub struct AddressResponse {
     success: bool,
     address: String,
-    error: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct BalanceResponse {
     success: bool,
     balance: String,
-    error: Option<String>,
 }
 
 async fn get_balance_controller(
     State(wallet): State<Arc<EthServWallet>>,
-    Path(address): Path<String>,
-) -> (StatusCode, Json<BalanceResponse>) {
+    owner: ValidAddress,
+) -> Result<Json<BalanceResponse>> {
     println!("Getting balance");
-    let response = match get_balance(wallet.provider.clone(), address.as_str()).await {
-        Ok(balance) => (
-            StatusCode::OK,
-            Json(BalanceResponse {
-                success: true,
-                balance: balance.to_string(),
-                error: None,
-            }),
-        ),
-        Err(_e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(BalanceResponse {
-                success: false,
-                balance: String::from("0"),
-                error: Some(format!("{:?}", _e)),
-            }),
-        ),
-    };
-    response
+    let balance = get_balance(wallet.provider.clone(), owner.0)
+        .await
+        .map_err(Error::rpc)?;
+
+    Ok(Json(BalanceResponse {
+        success: true,
+        balance: balance.to_string(),
+    }))
+}
+
+async fn get_blacklist_status(
+    State(wallet): State<Arc<EthServWallet>>,
+    address: ValidAddress,
+) -> Result<Json<BlacklistStatusResponse>> {
+    let blacklisted = wallet.is_blacklisted(address.0).await?;
+
+    Ok(Json(BlacklistStatusResponse {
+        success: true,
+        address,
+        blacklisted,
+    }))
 }
 
 async fn get_new_address(
     State(wallet): State<Arc<EthServWallet>>,
-) -> (StatusCode, Json<AddressResponse>) {
+    ApiQuery(query): ApiQuery<AccountQuery>,
+) -> Result<Json<AddressResponse>> {
     println!("Getting new address");
-    let response = match wallet.reveal_next_address() {
-        Ok(address) => (
-            StatusCode::OK,
-            Json(AddressResponse {
-                success: true,
-                address,
-                error: None,
-            }),
-        ),
-        Err(_e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(AddressResponse {
-                success: false,
-                address: String::new(),
-                error: Some(String::from("Error getting new address")),
-            }),
-        ),
-    };
-    response
+    let address = wallet.reveal_next_address(query.account.as_deref())?;
+
+    Ok(Json(AddressResponse {
+        success: true,
+        address,
+    }))
+}
+
+async fn post_new_address(
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiJson(req): ApiJson<NewAddressRequest>,
+) -> Result<Json<IdempotentAddressResponse>> {
+    println!("Getting new address for key {}", req.idempotency_key);
+    if req.idempotency_key.is_empty() {
+        return Err(Error::InvalidInput(String::from(
+            "idempotency_key must not be empty",
+        )));
+    }
+
+    let (record, created) = wallet.reveal_address_idempotent(
+        &req.idempotency_key,
+        req.account.as_deref(),
+        req.customer_id.as_deref(),
+        req.label.as_deref(),
+    )?;
+
+    Ok(Json(IdempotentAddressResponse {
+        success: true,
+        created,
+        address: record,
+    }))
+}
+
+/// Upper bound on the number of addresses issued by one batch request.
+const MAX_ADDRESS_BATCH: u32 = 1000;
+
+async fn create_addresses(
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiJson(req): ApiJson<CreateAddressesRequest>,
+) -> Result<Json<CreateAddressesResponse>> {
+    println!("Creating {} addresses", req.count);
+    if req.count == 0 || req.count > MAX_ADDRESS_BATCH {
+        return Err(Error::InvalidInput(format!(
+            "count must be between 1 and {}",
+            MAX_ADDRESS_BATCH
+        )));
+    }
+
+    let addresses = wallet.reveal_addresses(
+        req.count,
+        req.account.as_deref(),
+        req.customer_id.as_deref(),
+        req.label.as_deref(),
+    )?;
+
+    Ok(Json(CreateAddressesResponse {
+        success: true,
+        addresses,
+    }))
+}
+
+async fn get_accounts(State(wallet): State<Arc<EthServWallet>>) -> Json<AccountsResponse> {
+    Json(AccountsResponse {
+        success: true,
+        accounts: wallet.accounts().to_vec(),
+    })
 }
 
 async fn get_address_deposits(
     State(wallet): State<Arc<EthServWallet>>,
-    Json(tx): Json<AddressDepositsRequest>,
-) -> (StatusCode, Json<AddressDepositsResponse>) {
+    ApiJson(tx): ApiJson<AddressDepositsRequest>,
+) -> Result<Json<AddressDepositsResponse>> {
     println!("Get address req");
 
+    if let (Some(start), Some(end)) = (tx.start_block, tx.end_block) {
+        if start > end {
+            return Err(Error::InvalidInput(String::from(
+                "start_block must not be greater than end_block",
+            )));
+        }
+    }
+
     let transfer_logs =
-        get_receive_logs(&wallet.provider, tx.start_block, tx.end_block, tx.address)
+        get_receive_logs(&wallet.provider, tx.start_block, tx.end_block, tx.address.0)
             .await
-            .unwrap();
+            .map_err(Error::rpc)?;
 
-    (
-        StatusCode::OK,
-        Json(AddressDepositsResponse {
-            deposits: transfer_logs,
-        }),
-    )
+    Ok(Json(AddressDepositsResponse {
+        success: true,
+        deposits: transfer_logs,
+    }))
 }
 
 async fn test_pub_deposits(
     State(wallet): State<Arc<EthServWallet>>,
-    Json(tx): Json<TestPubTxRequest>,
-) -> (StatusCode, Json<TestPubTxResponse>) {
+    ApiJson(tx): ApiJson<TestPubTxRequest>,
+) -> Result<Json<TestPubTxResponse>> {
     println!("Testing public transaction");
 
     for action in tx.actions {
         println!("Action: {:?}", action);
-        let chain_event = ChainEvent::NewDeposit { deposit: action };
-        if let Err(e) = wallet.publish_chainevent(chain_event) {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(TestPubTxResponse {
-                    success: false,
-                    error: Some(format!("Error publishing chain event: {:?}", e)),
-                }),
-            );
-        }
+        let chain_event = ChainEvent::NewDeposit {
+            deposit: action,
+            customer_id: None,
+            label: None,
+            sender_blacklisted: None,
+            recipient_blacklisted: None,
+        };
+        wallet.publish_chainevent(chain_event)?;
     }
-    (
-        StatusCode::OK,
-        Json(TestPubTxResponse {
+
+    Ok(Json(TestPubTxResponse { success: true }))
+}
+
+async fn validate_address(
+    ApiJson(req): ApiJson<ValidateAddressRequest>,
+) -> Json<ValidateAddressResponse> {
+    let response = match check_address(req.address.trim()) {
+        Ok(check) => ValidateAddressResponse {
             success: true,
+            valid: check.checksum != ChecksumStatus::Invalid,
+            address: Some(check.address.to_checksum(None)),
+            checksum: Some(check.checksum),
             error: None,
-        }),
-    )
+        },
+        Err(e) => ValidateAddressResponse {
+            success: true,
+            valid: false,
+            address: None,
+            checksum: None,
+            error: Some(e),
+        },
+    };
+    Json(response)
+}
+
+async fn import_mnemonic(
+    _admin: AdminAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiJson(req): ApiJson<ImportMnemonicRequest>,
+) -> Result<Json<ImportMnemonicResponse>> {
+    println!("Importing mnemonic");
+    let restored = wallet.import_mnemonic(
+        &req.password,
+        &req.phrase,
+        req.passphrase.as_deref().map(String::as_str),
+        req.rescan_count.unwrap_or(0),
+    )?;
+
+    Ok(Json(ImportMnemonicResponse {
+        success: true,
+        restored,
+    }))
+}
+
+async fn discover_addresses(
+    _admin: AdminAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiJson(req): ApiJson<DiscoverAddressesRequest>,
+) -> Result<Json<DiscoverAddressesResponse>> {
+    println!("Discovering addresses");
+    let accounts = wallet
+        .discover_addresses(
+            req.account.as_deref(),
+            req.gap_limit.unwrap_or_else(config::discovery_gap_limit),
+            req.from_block.or(config::discovery_from_block()),
+        )
+        .await?;
+
+    Ok(Json(DiscoverAddressesResponse {
+        success: true,
+        accounts,
+    }))
+}
+
+async fn sign_message(
+    _admin: AdminAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiJson(req): ApiJson<SignMessageRequest>,
+) -> Result<Json<SignatureResponse>> {
+    println!("Signing message for {}", req.address);
+    let message = req.encoding.decode(&req.message)?;
+    let signed = wallet.sign_message(req.address.0, &message)?;
+
+    Ok(Json(SignatureResponse {
+        success: true,
+        signed,
+    }))
+}
+
+async fn sign_typed_data(
+    _admin: AdminAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiJson(req): ApiJson<SignTypedDataRequest>,
+) -> Result<Json<SignatureResponse>> {
+    println!("Signing typed data for {}", req.address);
+    let signed = wallet.sign_typed_data(req.address.0, &req.typed_data)?;
+
+    Ok(Json(SignatureResponse {
+        success: true,
+        signed,
+    }))
+}
+
+async fn verify_signature(
+    _admin: AdminAuth,
+    ApiJson(req): ApiJson<VerifySignatureRequest>,
+) -> Result<Json<VerifySignatureResponse>> {
+    let signer = match (&req.message, &req.typed_data) {
+        (Some(message), None) => {
+            signing::recover_message_signer(&req.encoding.decode(message)?, &req.signature)?
+        }
+        (None, Some(typed_data)) => signing::recover_typed_data_signer(typed_data, &req.signature)?,
+        _ => {
+            return Err(Error::InvalidInput(String::from(
+                "Exactly one of message and typed_data is required",
+            )))
+        }
+    };
+
+    Ok(Json(VerifySignatureResponse {
+        success: true,
+        signer,
+        valid: req.address.map(|address| address.0 == signer),
+    }))
+}
+
+async fn get_fees(
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiQuery(query): ApiQuery<FeesQuery>,
+) -> Result<Json<FeesResponse>> {
+    println!("Estimating fees");
+    let estimate = wallet.estimate_fees(query.urgency).await?;
+
+    Ok(Json(FeesResponse {
+        success: true,
+        estimate,
+    }))
+}
+
+async fn get_nonces(
+    _admin: AdminAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    address: ValidAddress,
+) -> Result<Json<NonceStateResponse>> {
+    let state = wallet.nonce_state(address.0)?;
+
+    Ok(Json(NonceStateResponse {
+        success: true,
+        state,
+    }))
+}
+
+async fn reconcile_nonces(
+    _admin: AdminAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiJson(req): ApiJson<ReconcileNoncesRequest>,
+) -> Result<Json<ReconcileNoncesResponse>> {
+    println!("Reconciling nonces");
+    let addresses = wallet
+        .reconcile_nonces(req.address.map(|address| address.0))
+        .await?;
+
+    Ok(Json(ReconcileNoncesResponse {
+        success: true,
+        addresses,
+    }))
+}
+
+async fn create_withdrawal(
+    OperatorAuth(operator): OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiJson(req): ApiJson<CreateWithdrawalRequest>,
+) -> Result<Json<WithdrawalRequestResponse>> {
+    let amount = U256::from_str_radix(&req.amount, 10)
+        .ok()
+        .filter(|amount| !amount.is_zero())
+        .ok_or_else(|| {
+            Error::InvalidInput(String::from(
+                "Amount must be a positive integer in USDT base units",
+            ))
+        })?;
+    println!(
+        "{} requests a withdrawal of {} from {} to {}",
+        operator, amount, req.from, req.to
+    );
+    let (withdrawal, transaction) = wallet
+        .request_withdrawal(NewWithdrawal {
+            from: req.from.0,
+            to: req.to.0,
+            amount,
+            urgency: req.urgency,
+            requested_by: operator,
+        })
+        .await?;
+
+    Ok(Json(WithdrawalRequestResponse {
+        success: true,
+        withdrawal,
+        transaction,
+    }))
+}
+
+async fn list_withdrawals(
+    _operator: OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiQuery(query): ApiQuery<WithdrawalsQuery>,
+) -> Result<Json<TransactionsResponse>> {
+    let transactions = wallet.transactions(query.status, list_limit(query.limit)?)?;
+
+    Ok(Json(TransactionsResponse {
+        success: true,
+        transactions,
+    }))
+}
+
+async fn get_withdrawal(
+    _operator: OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiPath(id): ApiPath<i64>,
+) -> Result<Json<TransactionResponse>> {
+    let transaction = wallet.transaction(id)?;
+
+    Ok(Json(TransactionResponse {
+        success: true,
+        transaction,
+    }))
+}
+
+async fn speed_up_withdrawal(
+    OperatorAuth(operator): OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiPath(id): ApiPath<i64>,
+    ApiJson(req): ApiJson<ReplaceTransactionRequest>,
+) -> Result<Json<TransactionResponse>> {
+    println!("{} speeds up transaction {}", operator, id);
+    let transaction = wallet
+        .speed_up_transaction(id, &operator, req.urgency)
+        .await?;
+
+    Ok(Json(TransactionResponse {
+        success: true,
+        transaction,
+    }))
+}
+
+async fn cancel_withdrawal(
+    OperatorAuth(operator): OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiPath(id): ApiPath<i64>,
+    ApiJson(req): ApiJson<ReplaceTransactionRequest>,
+) -> Result<Json<TransactionResponse>> {
+    println!("{} cancels transaction {}", operator, id);
+    let transaction = wallet
+        .cancel_transaction(id, &operator, req.urgency)
+        .await?;
+
+    Ok(Json(TransactionResponse {
+        success: true,
+        transaction,
+    }))
+}
+
+async fn list_withdrawal_requests(
+    _operator: OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiQuery(query): ApiQuery<WithdrawalRequestsQuery>,
+) -> Result<Json<WithdrawalRequestsResponse>> {
+    let withdrawals = wallet.withdrawal_requests(query.status, list_limit(query.limit)?)?;
+
+    Ok(Json(WithdrawalRequestsResponse {
+        success: true,
+        withdrawals,
+    }))
+}
+
+async fn get_withdrawal_request(
+    _operator: OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiPath(id): ApiPath<i64>,
+) -> Result<Json<WithdrawalRequestResponse>> {
+    let withdrawal = wallet.withdrawal_request(id)?;
+    let transaction = withdrawal
+        .transaction_id
+        .map(|id| wallet.transaction(id))
+        .transpose()?;
+
+    Ok(Json(WithdrawalRequestResponse {
+        success: true,
+        withdrawal,
+        transaction,
+    }))
+}
+
+async fn approve_withdrawal_request(
+    OperatorAuth(operator): OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiPath(id): ApiPath<i64>,
+    ApiJson(req): ApiJson<WithdrawalDecisionRequest>,
+) -> Result<Json<WithdrawalRequestResponse>> {
+    println!("{} approves withdrawal request {}", operator, id);
+    let (withdrawal, transaction) = wallet
+        .approve_withdrawal(id, &operator, req.comment.as_deref())
+        .await?;
+
+    Ok(Json(WithdrawalRequestResponse {
+        success: true,
+        withdrawal,
+        transaction,
+    }))
+}
+
+async fn send_withdrawal_request(
+    OperatorAuth(operator): OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiPath(id): ApiPath<i64>,
+) -> Result<Json<WithdrawalRequestResponse>> {
+    println!("{} sends withdrawal request {}", operator, id);
+    let (withdrawal, transaction) = wallet.send_withdrawal(id, &operator).await?;
+
+    Ok(Json(WithdrawalRequestResponse {
+        success: true,
+        withdrawal,
+        transaction,
+    }))
+}
+
+async fn reject_withdrawal_request(
+    OperatorAuth(operator): OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiPath(id): ApiPath<i64>,
+    ApiJson(req): ApiJson<WithdrawalDecisionRequest>,
+) -> Result<Json<WithdrawalRequestResponse>> {
+    println!("{} rejects withdrawal request {}", operator, id);
+    let withdrawal = wallet.reject_withdrawal(id, &operator, req.comment.as_deref())?;
+
+    Ok(Json(WithdrawalRequestResponse {
+        success: true,
+        withdrawal,
+        transaction: None,
+    }))
+}
+
+async fn get_audit_log(
+    _operator: OperatorAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+    ApiQuery(query): ApiQuery<AuditLogQuery>,
+) -> Result<Json<AuditLogResponse>> {
+    let entries = wallet.audit_log(query.withdrawal_id, list_limit(query.limit)?)?;
+
+    Ok(Json(AuditLogResponse {
+        success: true,
+        entries,
+    }))
+}
+
+async fn get_frozen_addresses(
+    _admin: AdminAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+) -> Result<Json<FrozenAddressesResponse>> {
+    let addresses = wallet.frozen_addresses()?;
+
+    Ok(Json(FrozenAddressesResponse {
+        success: true,
+        addresses,
+    }))
+}
+
+async fn scan_blacklist(
+    _admin: AdminAuth,
+    State(wallet): State<Arc<EthServWallet>>,
+) -> Result<Json<FrozenAddressesResponse>> {
+    println!("Checking our addresses against the USDT blacklist");
+    let addresses = wallet.scan_blacklist().await?;
+
+    Ok(Json(FrozenAddressesResponse {
+        success: true,
+        addresses,
+    }))
+}
+
+/// Checks the `limit` of a listing, 100 by default.
+fn list_limit(limit: Option<u32>) -> Result<u32> {
+    let limit = limit.unwrap_or(100);
+    if limit == 0 || limit > MAX_LISTED {
+        return Err(Error::InvalidInput(format!(
+            "Limit must be between 1 and {}",
+            MAX_LISTED
+        )));
+    }
+    Ok(limit)
 }
 
 // Create router
 pub fn create_router(wallet: Arc<EthServWallet>) -> Router {
     Router::new()
         .route("/balance/:address", get(get_balance_controller))
-        .route("/new-address", get(get_new_address))
+        .route("/blacklist/:address", get(get_blacklist_status))
+        .route("/new-address", get(get_new_address).post(post_new_address))
+        .route("/addresses", post(create_addresses))
+        .route("/accounts", get(get_accounts))
+        .route("/fees", get(get_fees))
         .route("/address-deposits", post(get_address_deposits))
+        .route("/validate-address", post(validate_address))
         .route("/test/pub-deposits", post(test_pub_deposits))
+        .route("/admin/import-mnemonic", post(import_mnemonic))
+        .route("/admin/discover-addresses", post(discover_addresses))
+        .route("/admin/sign-message", post(sign_message))
+        .route("/admin/sign-typed-data", post(sign_typed_data))
+        .route("/admin/verify-signature", post(verify_signature))
+        .route("/admin/nonces/:address", get(get_nonces))
+        .route(
+            "/admin/withdrawals",
+            get(list_withdrawals).post(create_withdrawal),
+        )
+        .route("/admin/withdrawals/:id", get(get_withdrawal))
+        .route("/admin/withdrawals/:id/speed-up", post(speed_up_withdrawal))
+        .route("/admin/withdrawals/:id/cancel", post(cancel_withdrawal))
+        .route("/admin/withdrawal-requests", get(list_withdrawal_requests))
+        .route(
+            "/admin/withdrawal-requests/:id",
+            get(get_withdrawal_request),
+        )
+        .route(
+            "/admin/withdrawal-requests/:id/approve",
+            post(approve_withdrawal_request),
+        )
+        .route(
+            "/admin/withdrawal-requests/:id/send",
+            post(send_withdrawal_request),
+        )
+        .route(
+            "/admin/withdrawal-requests/:id/reject",
+            post(reject_withdrawal_request),
+        )
+        .route("/admin/audit-log", get(get_audit_log))
+        .route("/admin/frozen-addresses", get(get_frozen_addresses))
+        .route("/admin/scan-blacklist", post(scan_blacklist))
+        .route("/admin/reconcile-nonces", post(reconcile_nonces))
         .with_state(wallet)
 }
 
@@ -145,17 +631,283 @@ struct TestPubTxRequest {
 #[derive(Serialize)]
 struct TestPubTxResponse {
     success: bool,
-    error: Option<String>,
+}
+
+/// Selects the derivation account; the default one when omitted.
+#[derive(Deserialize)]
+struct AccountQuery {
+    account: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NewAddressRequest {
+    idempotency_key: String,
+    account: Option<String>,
+    customer_id: Option<String>,
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+struct IdempotentAddressResponse {
+    success: bool,
+    /// `false` when the address was issued by an earlier request with the same key.
+    created: bool,
+    address: AddressRecord,
+}
+
+#[derive(Deserialize)]
+struct CreateAddressesRequest {
+    count: u32,
+    account: Option<String>,
+    customer_id: Option<String>,
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CreateAddressesResponse {
+    success: bool,
+    addresses: Vec<AddressRecord>,
+}
+
+#[derive(Serialize)]
+struct AccountsResponse {
+    success: bool,
+    accounts: Vec<Account>,
 }
 
 #[derive(Deserialize)]
 struct AddressDepositsRequest {
-    address: String,
+    address: ValidAddress,
     start_block: Option<u64>,
     end_block: Option<u64>,
 }
 
 #[derive(Serialize)]
 struct AddressDepositsResponse {
+    success: bool,
     deposits: Vec<TransferLog>,
 }
+
+#[derive(Deserialize)]
+struct ValidateAddressRequest {
+    address: String,
+}
+
+#[derive(Serialize)]
+struct ValidateAddressResponse {
+    success: bool,
+    valid: bool,
+    address: Option<String>,
+    checksum: Option<ChecksumStatus>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ImportMnemonicRequest {
+    phrase: Secret,
+    /// Password to encrypt the mnemonic with.
+    password: Secret,
+    /// BIP-39 passphrase of the seed, if it has one. Stored encrypted next
+    /// to the mnemonic.
+    passphrase: Option<Secret>,
+    rescan_count: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct DiscoverAddressesRequest {
+    /// Account to scan; every account when omitted.
+    account: Option<String>,
+    gap_limit: Option<u32>,
+    from_block: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct DiscoverAddressesResponse {
+    success: bool,
+    accounts: Vec<DiscoveryReport>,
+}
+
+/// How the `message` of a signing request is encoded.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+enum MessageEncoding {
+    #[default]
+    Utf8,
+    /// `0x`-prefixed hex, for binary messages.
+    Hex,
+}
+
+impl MessageEncoding {
+    fn decode(&self, message: &str) -> Result<Vec<u8>> {
+        match self {
+            MessageEncoding::Utf8 => Ok(message.as_bytes().to_vec()),
+            MessageEncoding::Hex => message
+                .strip_prefix("0x")
+                .and_then(|hex| hex::decode(hex).ok())
+                .ok_or_else(|| Error::InvalidInput(String::from("Message is not 0x-prefixed hex"))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SignMessageRequest {
+    address: ValidAddress,
+    message: String,
+    #[serde(default)]
+    encoding: MessageEncoding,
+}
+
+#[derive(Deserialize)]
+struct SignTypedDataRequest {
+    address: ValidAddress,
+    typed_data: TypedData,
+}
+
+#[derive(Serialize)]
+struct SignatureResponse {
+    success: bool,
+    #[serde(flatten)]
+    signed: SignedMessage,
+}
+
+#[derive(Deserialize)]
+struct VerifySignatureRequest {
+    message: Option<String>,
+    #[serde(default)]
+    encoding: MessageEncoding,
+    typed_data: Option<TypedData>,
+    signature: Bytes,
+    /// Expected signer. When given, the response says whether it matches.
+    address: Option<ValidAddress>,
+}
+
+#[derive(Serialize)]
+struct VerifySignatureResponse {
+    success: bool,
+    signer: Address,
+    valid: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct FeesQuery {
+    /// Configured urgency when omitted.
+    urgency: Option<Urgency>,
+}
+
+#[derive(Serialize)]
+struct FeesResponse {
+    success: bool,
+    #[serde(flatten)]
+    estimate: FeeEstimate,
+}
+
+/// Upper bound on the `limit` of withdrawal and audit log listings.
+const MAX_LISTED: u32 = 1000;
+
+#[derive(Deserialize)]
+struct CreateWithdrawalRequest {
+    from: ValidAddress,
+    to: ValidAddress,
+    /// USDT base units as a decimal string.
+    amount: String,
+    urgency: Option<Urgency>,
+}
+
+#[derive(Deserialize)]
+struct WithdrawalsQuery {
+    status: Option<TxStatus>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ReplaceTransactionRequest {
+    urgency: Option<Urgency>,
+}
+
+#[derive(Serialize)]
+struct TransactionResponse {
+    success: bool,
+    transaction: TrackedTransaction,
+}
+
+#[derive(Serialize)]
+struct TransactionsResponse {
+    success: bool,
+    transactions: Vec<TrackedTransaction>,
+}
+
+#[derive(Serialize)]
+struct WithdrawalRequestResponse {
+    success: bool,
+    withdrawal: WithdrawalRequest,
+    /// The transaction sending it, once approved and sent.
+    transaction: Option<TrackedTransaction>,
+}
+
+#[derive(Deserialize)]
+struct WithdrawalRequestsQuery {
+    status: Option<WithdrawalStatus>,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct WithdrawalRequestsResponse {
+    success: bool,
+    withdrawals: Vec<WithdrawalRequest>,
+}
+
+#[derive(Deserialize)]
+struct WithdrawalDecisionRequest {
+    comment: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AuditLogQuery {
+    /// Only entries about this withdrawal request.
+    withdrawal_id: Option<i64>,
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct AuditLogResponse {
+    success: bool,
+    entries: Vec<AuditEntry>,
+}
+
+#[derive(Serialize)]
+struct BlacklistStatusResponse {
+    success: bool,
+    address: ValidAddress,
+    blacklisted: bool,
+}
+
+#[derive(Serialize)]
+struct FrozenAddressesResponse {
+    success: bool,
+    addresses: Vec<FrozenAddress>,
+}
+
+#[derive(Serialize)]
+struct NonceStateResponse {
+    success: bool,
+    #[serde(flatten)]
+    state: NonceState,
+}
+
+#[derive(Deserialize)]
+struct ReconcileNoncesRequest {
+    /// Address to reconcile; every address with nonce state when omitted.
+    address: Option<ValidAddress>,
+}
+
+#[derive(Serialize)]
+struct ReconcileNoncesResponse {
+    success: bool,
+    addresses: Vec<NonceState>,
+}
+
+#[derive(Serialize)]
+struct ImportMnemonicResponse {
+    success: bool,
+    restored: u32,
+}
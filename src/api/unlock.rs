@@ -0,0 +1,142 @@
+//! Local control socket of a running service, used to unlock a wallet that
+//! was started without its password and to lock it again.
+//!
+//! One request per connection, `unlock <password>\n` or `lock\n`, answered
+//! with `ok\n` or `error <message>\n`. An unlock request may be followed by
+//! `passphrase <BIP-39 passphrase>\n`; clients close their side after the
+//! request. The socket is only accessible to the owner of the data directory.
+
+use std::{fs, path::Path, sync::Arc};
+
+use anyhow::{anyhow, Context, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+use zeroize::Zeroizing;
+
+use crate::{config::secrets::Secret, error::Error, EthServWallet};
+
+/// Longest request accepted, to bound what a client can make us buffer.
+const MAX_REQUEST_LEN: u64 = 4096;
+
+/// Accepts unlock requests for `wallet` on `path` until the process exits.
+pub async fn serve(path: &Path, wallet: Arc<EthServWallet>) -> Result<()> {
+    // Left over from a previous run. The wallet lock guarantees no other
+    // process is serving this wallet.
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    println!("Control socket listening on {}", path.display());
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let wallet = wallet.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, wallet).await {
+                println!("Control socket request failed: {:?}", e);
+            }
+        });
+    }
+}
+
+async fn handle(stream: UnixStream, wallet: Arc<EthServWallet>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_LEN));
+    let mut line = Zeroizing::new(String::new());
+    reader.read_line(&mut line).await?;
+
+    let request = line.trim_end_matches(['\r', '\n']);
+    let result = match request.split_once(' ').unwrap_or((request, "")) {
+        ("unlock", password) => {
+            let password = Zeroizing::new(password.to_string());
+            let mut next = Zeroizing::new(String::new());
+            reader.read_line(&mut next).await?;
+            match read_passphrase(&next) {
+                Ok(passphrase) => {
+                    // Key derivation takes a while, keep it off the async workers
+                    tokio::task::spawn_blocking(move || {
+                        wallet.unlock(&password, passphrase.as_deref().map(String::as_str))
+                    })
+                    .await?
+                }
+                Err(e) => Err(e),
+            }
+        }
+        ("lock", "") => wallet.lock(),
+        _ => Err(Error::InvalidInput(String::from("Unknown request")).into()),
+    };
+
+    let reply = match result {
+        Ok(()) => String::from("ok\n"),
+        Err(e) => format!("error {}\n", Error::from(e).public_message()),
+    };
+    writer.write_all(reply.as_bytes()).await?;
+    Ok(())
+}
+
+/// Parses the optional line following an unlock request.
+fn read_passphrase(line: &str) -> Result<Option<Secret>> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if line.is_empty() {
+        return Ok(None);
+    }
+    match line.strip_prefix("passphrase ") {
+        Some(passphrase) if !passphrase.is_empty() => {
+            Ok(Some(Zeroizing::new(passphrase.to_string())))
+        }
+        Some(_) => Ok(None),
+        None => Err(Error::InvalidInput(String::from("Unknown request")).into()),
+    }
+}
+
+/// Asks the service listening on `path` to unlock its wallet, deriving its
+/// keys with the BIP-39 `passphrase` if given.
+pub async fn send_unlock(
+    path: &Path,
+    password: &Secret,
+    passphrase: Option<&Secret>,
+) -> Result<()> {
+    let mut request = Zeroizing::new(format!("unlock {}\n", password.as_str()));
+    if let Some(passphrase) = passphrase {
+        request.push_str("passphrase ");
+        request.push_str(passphrase);
+        request.push('\n');
+    }
+    send_request(path, &request).await
+}
+
+/// Asks the service listening on `path` to wipe its wallet's keys.
+pub async fn send_lock(path: &Path) -> Result<()> {
+    send_request(path, "lock\n").await
+}
+
+async fn send_request(path: &Path, request: &str) -> Result<()> {
+    let mut stream = UnixStream::connect(path).await.with_context(|| {
+        format!(
+            "Failed to connect to {}, is the service running?",
+            path.display()
+        )
+    })?;
+
+    stream.write_all(request.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).await?;
+    match reply.trim_end() {
+        "ok" => Ok(()),
+        reply => Err(anyhow!(
+            "{}",
+            reply
+                .strip_prefix("error ")
+                .unwrap_or("No reply from the service")
+        )),
+    }
+}
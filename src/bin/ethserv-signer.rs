@@ -0,0 +1,70 @@
+//! Offline signer. Loads the encrypted mnemonic and signs an unsigned bundle
+//! written by `ethserv prepare` on the online host:
+//!
+//!     [WALLET_ID=...] [DATA_DIR=...] ethserv-signer <unsigned.json> <signed.json>
+//!
+//! The password is read from `WALLET_PW_FILE` or `WALLET_PW`, or prompted
+//! for. A BIP-39 passphrase is stored next to the mnemonic, or read from
+//! `WALLET_PASSPHRASE_FILE` or `WALLET_PASSPHRASE` for wallets without one.
+//! Needs no network access and no other configuration.
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use ethserv::{
+    config::secrets,
+    wallet::{
+        mnemonic::{MnemonicStorage, PassphraseStorage},
+        offline::{OfflineSigner, UnsignedBundle},
+        paths::WalletPaths,
+    },
+};
+
+fn main() -> Result<()> {
+    secrets::take_env_secrets();
+    let args: Vec<String> = std::env::args().collect();
+    let [_, unsigned_path, signed_path] = args.as_slice() else {
+        return Err(anyhow!(
+            "Usage: ethserv-signer <unsigned.json> <signed.json>"
+        ));
+    };
+
+    let password_file = std::env::var("WALLET_PW_FILE").ok();
+    let password = secrets::password_from(password_file.as_deref())?
+        .ok_or_else(|| anyhow!("Set WALLET_PW_FILE or WALLET_PW, or run on a terminal"))?;
+    let passphrase_file = std::env::var("WALLET_PASSPHRASE_FILE").ok();
+    let passphrase = secrets::passphrase_from(passphrase_file.as_deref())?;
+    let wallet_id = std::env::var("WALLET_ID").ok();
+    let data_dir = std::env::var("DATA_DIR").unwrap_or_else(|_| String::from("pers"));
+    let paths = WalletPaths::resolve(Path::new(&data_dir), wallet_id.as_deref(), &password)?;
+    let mnemonic = MnemonicStorage::new(paths.mnemonic_path).load_mnemonic(&password)?;
+    let passphrase = PassphraseStorage::new(paths.passphrase_path)
+        .load_or(&password, passphrase.as_deref().map(String::as_str))?;
+
+    let bundle = UnsignedBundle::load(unsigned_path)?;
+    for tx in &bundle.transactions {
+        println!(
+            "chain {} nonce {}: {} ({}) -> {} value {} gas limit {} {:?}",
+            tx.chain_id,
+            tx.nonce,
+            tx.from,
+            tx.derivation_path,
+            tx.to,
+            tx.value,
+            tx.gas_limit,
+            tx.gas
+        );
+    }
+
+    let signed = OfflineSigner::new(&mnemonic, passphrase.as_deref().map(String::as_str))?
+        .sign_bundle(&bundle)?;
+    signed.save(signed_path)?;
+
+    println!(
+        "Signed {} transactions into {}",
+        signed.transactions.len(),
+        signed_path
+    );
+    Ok(())
+}
@@ -2,7 +2,7 @@ use alloy::primitives::Address;
 use config::{Config, ConfigError};
 use lazy_static::lazy_static;
 use serde::Deserialize;
-use std::{env, str::FromStr};
+use std::{env, str::FromStr, time::Duration};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Environment {
@@ -37,8 +37,78 @@ pub struct Settings {
     pub publisher_bind_address: String,
     #[serde(deserialize_with = "deserialize_address")]
     pub usdt_contract_address: Address,
-    pub wallet_pw: String,
+    /// File holding the wallet password. Takes precedence over `WALLET_PW`,
+    /// which is not part of the settings so it is never kept around.
+    pub wallet_pw_file: Option<String>,
+    /// File holding the BIP-39 passphrase. Takes precedence over
+    /// `WALLET_PASSPHRASE`. Without either the seed has no passphrase.
+    pub wallet_passphrase_file: Option<String>,
+    /// Number of words of mnemonics created by `ethserv init`: 12, 18 or 24.
+    /// Defaults to 12.
+    pub mnemonic_words: Option<usize>,
+    /// Stable name for the wallet files. When unset, file names are derived
+    /// from the password (or xpub).
+    pub wallet_id: Option<String>,
+    /// Directory holding the wallet files. Defaults to `pers` in the working
+    /// directory.
+    pub data_dir: Option<String>,
+    /// Path prefix of the `default` account.
     pub derivation_path: String,
+    /// Further derivation accounts as `name=template` pairs, e.g.
+    /// `hot=m/44'/60'/1'/0/{index},gas=m/44'/60'/2'/0/{index}`.
+    pub accounts: Option<String>,
+    /// Consecutive unused addresses after which address discovery stops.
+    /// Defaults to 20, as in BIP-44.
+    pub discovery_gap_limit: Option<u32>,
+    /// Block to search for USDT transfers from during address discovery,
+    /// e.g. the block the wallet was created in. Defaults to the block the
+    /// mainnet USDT contract was deployed in; set it on other chains.
+    pub discovery_from_block: Option<u64>,
+    /// Most blocks one USDT log query of address discovery or the blacklist
+    /// sync spans. Defaults to 10,000, which most hosted nodes accept.
+    pub discovery_log_range: Option<u64>,
+    /// How urgently transactions are priced: `slow`, `normal` or `fast`.
+    /// Defaults to `normal`.
+    pub fee_urgency: Option<String>,
+    /// Hard cap on the max fee (or legacy gas price) per gas, in gwei.
+    /// Defaults to 200.
+    pub max_fee_per_gas_gwei: Option<u64>,
+    /// Hard cap on the priority fee per gas, in gwei. Defaults to 5.
+    pub max_priority_fee_per_gas_gwei: Option<u64>,
+    /// Blocks on top of a withdrawal's block before it counts as
+    /// confirmed. Defaults to 12.
+    pub withdrawal_confirmations: Option<u64>,
+    /// Seconds between receipt polls of outbound transactions. Defaults to
+    /// 15.
+    pub tx_poll_interval: Option<u64>,
+    /// Seconds after which a nonce reserved for a transaction that was never
+    /// reported as sent is handed out again. Defaults to an hour, so
+    /// transactions signed offline are not overtaken.
+    pub nonce_reservation_timeout: Option<u64>,
+    /// Largest single withdrawal, in USDT base units. Unlimited when unset.
+    pub withdrawal_max_amount: Option<u64>,
+    /// Largest total of the withdrawals requested in any 24 hours, in USDT
+    /// base units. Unlimited when unset.
+    pub withdrawal_daily_limit: Option<u64>,
+    /// Withdrawals above this many USDT base units wait for approvals
+    /// before they are sent. None do when unset.
+    pub withdrawal_approval_threshold: Option<u64>,
+    /// Approvals, by operators other than the requester, that a withdrawal
+    /// above the threshold needs. Defaults to 2.
+    pub withdrawal_required_approvals: Option<u32>,
+    /// Comma separated addresses withdrawals may go to. Any address when
+    /// unset.
+    pub withdrawal_allowlist: Option<String>,
+    /// Comma separated addresses withdrawals must never go to.
+    pub withdrawal_denylist: Option<String>,
+    /// Operators allowed to request and approve withdrawals, as comma
+    /// separated `name=token` pairs. `ADMIN_TOKEN` acts as operator `admin`.
+    pub operators: Option<String>,
+    /// Account-level xpub. When set the service runs watch-only and never
+    /// loads the mnemonic.
+    pub xpub: Option<String>,
+    /// Bearer token for `/admin` routes. Admin routes are disabled when unset.
+    pub admin_token: Option<String>,
 }
 
 impl Settings {
@@ -97,8 +167,24 @@ pub fn usdt_contract_address() -> Address {
     SETTINGS.usdt_contract_address
 }
 
-pub fn wallet_pw() -> &'static str {
-    &SETTINGS.wallet_pw
+pub fn wallet_pw_file() -> Option<&'static str> {
+    SETTINGS.wallet_pw_file.as_deref()
+}
+
+pub fn wallet_passphrase_file() -> Option<&'static str> {
+    SETTINGS.wallet_passphrase_file.as_deref()
+}
+
+pub fn mnemonic_words() -> usize {
+    SETTINGS.mnemonic_words.unwrap_or(12)
+}
+
+pub fn wallet_id() -> Option<&'static str> {
+    SETTINGS.wallet_id.as_deref()
+}
+
+pub fn data_dir() -> &'static str {
+    SETTINGS.data_dir.as_deref().unwrap_or("pers")
 }
 
 pub fn port() -> u16 {
@@ -112,3 +198,79 @@ pub fn publisher_bind_address() -> &'static str {
 pub fn derivation_path() -> &'static str {
     &SETTINGS.derivation_path
 }
+
+pub fn accounts() -> Option<&'static str> {
+    SETTINGS.accounts.as_deref()
+}
+
+pub fn discovery_gap_limit() -> u32 {
+    SETTINGS.discovery_gap_limit.unwrap_or(20)
+}
+
+pub fn discovery_from_block() -> Option<u64> {
+    SETTINGS.discovery_from_block
+}
+
+pub fn discovery_log_range() -> u64 {
+    SETTINGS.discovery_log_range.unwrap_or(10_000).max(1)
+}
+
+pub fn fee_urgency() -> Option<&'static str> {
+    SETTINGS.fee_urgency.as_deref()
+}
+
+pub fn max_fee_per_gas_gwei() -> u64 {
+    SETTINGS.max_fee_per_gas_gwei.unwrap_or(200)
+}
+
+pub fn max_priority_fee_per_gas_gwei() -> u64 {
+    SETTINGS.max_priority_fee_per_gas_gwei.unwrap_or(5)
+}
+
+pub fn withdrawal_confirmations() -> u64 {
+    SETTINGS.withdrawal_confirmations.unwrap_or(12)
+}
+
+pub fn tx_poll_interval() -> Duration {
+    Duration::from_secs(SETTINGS.tx_poll_interval.unwrap_or(15))
+}
+
+pub fn nonce_reservation_timeout() -> Duration {
+    Duration::from_secs(SETTINGS.nonce_reservation_timeout.unwrap_or(3600))
+}
+
+pub fn withdrawal_max_amount() -> Option<u64> {
+    SETTINGS.withdrawal_max_amount
+}
+
+pub fn withdrawal_daily_limit() -> Option<u64> {
+    SETTINGS.withdrawal_daily_limit
+}
+
+pub fn withdrawal_approval_threshold() -> Option<u64> {
+    SETTINGS.withdrawal_approval_threshold
+}
+
+pub fn withdrawal_required_approvals() -> u32 {
+    SETTINGS.withdrawal_required_approvals.unwrap_or(2)
+}
+
+pub fn withdrawal_allowlist() -> Option<&'static str> {
+    SETTINGS.withdrawal_allowlist.as_deref()
+}
+
+pub fn withdrawal_denylist() -> Option<&'static str> {
+    SETTINGS.withdrawal_denylist.as_deref()
+}
+
+pub fn operators() -> Option<&'static str> {
+    SETTINGS.operators.as_deref()
+}
+
+pub fn xpub() -> Option<&'static str> {
+    SETTINGS.xpub.as_deref()
+}
+
+pub fn admin_token() -> Option<&'static str> {
+    SETTINGS.admin_token.as_deref()
+}
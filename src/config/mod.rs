@@ -1,3 +1,4 @@
 mod constants;
+pub mod secrets;
 
 pub use constants::*;
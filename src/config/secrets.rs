@@ -0,0 +1,141 @@
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    path::Path,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use zeroize::Zeroizing;
+
+use super::constants::{self, SETTINGS};
+
+/// A password or phrase that is wiped from memory when dropped.
+pub type Secret = Zeroizing<String>;
+
+/// Environment variables that may hold a secret.
+const SECRET_VARS: [&str; 2] = ["WALLET_PW", "WALLET_PASSPHRASE"];
+
+/// Secrets moved out of the environment by [`take_env_secrets`], until read.
+static ENV_SECRETS: Mutex<Vec<(&str, Secret)>> = Mutex::new(Vec::new());
+
+/// Loads the settings, whose `.env` file may set a secret, and moves the
+/// secrets out of the environment, see [`take_env_secrets`].
+///
+/// Changing the environment is unsound while other threads may read it, so
+/// this must run before any thread is started, the async runtime's included.
+pub fn init() {
+    lazy_static::initialize(&SETTINGS);
+    take_env_secrets();
+}
+
+/// Removes `WALLET_PW` and `WALLET_PASSPHRASE` from the environment so child
+/// processes do not inherit them, keeping their values for
+/// [`wallet_password`] and [`wallet_passphrase`]. Like [`init`], for tools
+/// that run without the service settings, and under the same constraint.
+pub fn take_env_secrets() {
+    let mut taken = ENV_SECRETS.lock().unwrap();
+    for key in SECRET_VARS {
+        if let Ok(value) = env::var(key) {
+            taken.push((key, Zeroizing::new(value)));
+        }
+        env::remove_var(key);
+    }
+}
+
+/// Returns the wallet password, or `None` if there is no way to get one.
+///
+/// Tries `WALLET_PW_FILE`, then the `WALLET_PW` environment variable as
+/// taken by [`init`], then prompts if stdin is a terminal. `WALLET_PW` can be
+/// read once.
+pub fn wallet_password() -> Result<Option<Secret>> {
+    password_from(constants::wallet_pw_file())
+}
+
+/// Like [`wallet_password`], with the password file given explicitly. For
+/// tools that run without the service settings.
+pub fn password_from(file: Option<&str>) -> Result<Option<Secret>> {
+    if let Some(path) = file {
+        return read_password_file(Path::new(path)).map(Some);
+    }
+    if let Some(password) = take_env("WALLET_PW") {
+        return Ok(Some(password));
+    }
+    if io::stdin().is_terminal() {
+        return read_secret("Wallet password:").map(Some);
+    }
+    Ok(None)
+}
+
+/// Returns the configured BIP-39 passphrase of the wallet's seed, or `None`
+/// if it has none. Needed to create or import a seed with a passphrase, or
+/// for wallets that have none stored, see [`PassphraseStorage`].
+///
+/// [`PassphraseStorage`]: crate::wallet::mnemonic::PassphraseStorage
+///
+/// Tries `WALLET_PASSPHRASE_FILE`, then the `WALLET_PASSPHRASE` environment
+/// variable as taken by [`init`], which can be read once. Never prompts:
+/// most seeds have no passphrase. An empty passphrase counts as none.
+pub fn wallet_passphrase() -> Result<Option<Secret>> {
+    passphrase_from(constants::wallet_passphrase_file())
+}
+
+/// Like [`wallet_passphrase`], with the passphrase file given explicitly.
+pub fn passphrase_from(file: Option<&str>) -> Result<Option<Secret>> {
+    let passphrase = match file {
+        Some(path) => Some(read_password_file(Path::new(path))?),
+        None => take_env("WALLET_PASSPHRASE"),
+    };
+    Ok(passphrase.filter(|passphrase| !passphrase.is_empty()))
+}
+
+/// Reads a password from the first line of `path`.
+pub fn read_password_file(path: &Path) -> Result<Secret> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)
+            .with_context(|| format!("Failed to read password file {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            warn!(
+                "Password file {} is accessible by other users",
+                path.display()
+            );
+        }
+    }
+
+    let mut password = Zeroizing::new(
+        fs::read_to_string(path)
+            .with_context(|| format!("Failed to read password file {}", path.display()))?,
+    );
+    let len = password.lines().next().unwrap_or("").len();
+    password.truncate(len);
+    Ok(password)
+}
+
+/// Reads a line without echoing it if stdin is a terminal, or as is from
+/// stdin otherwise.
+pub fn read_secret(prompt: &str) -> Result<Secret> {
+    if io::stdin().is_terminal() {
+        return Ok(Zeroizing::new(rpassword::prompt_password(format!(
+            "{} ",
+            prompt
+        ))?));
+    }
+
+    println!("{}", prompt);
+    let mut line = Zeroizing::new(String::new());
+    io::stdin().read_line(&mut line)?;
+    let len = line.trim_end_matches(['\r', '\n']).len();
+    line.truncate(len);
+    Ok(line)
+}
+
+fn take_env(key: &str) -> Option<Secret> {
+    let mut taken = ENV_SECRETS.lock().unwrap();
+    let index = taken.iter().position(|(name, _)| *name == key)?;
+    Some(taken.swap_remove(index).1)
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::error;
+use serde::Serialize;
+
+/// Crate-wide error type returned by the HTTP API.
+///
+/// Wallet internals keep using `anyhow`; raise one of these variants through
+/// `anyhow` (e.g. `Err(Error::Conflict(..).into())`) and it is recovered
+/// unchanged when converted back at the API boundary.
+#[derive(Debug)]
+pub enum Error {
+    /// The request was malformed or failed validation.
+    InvalidInput(String),
+    /// The caller is not allowed to use this route.
+    Unauthorized(String),
+    /// The Ethereum node could not be reached or rejected the request.
+    RpcUnavailable(anyhow::Error),
+    /// The requested resource does not exist.
+    NotFound(String),
+    /// The request conflicts with the current state of the wallet.
+    Conflict(String),
+    /// The wallet has not been unlocked, so no keys are available.
+    Locked(String),
+    /// A withdrawal breaks the withdrawal policy, e.g. a limit or denylist.
+    PolicyViolation(String),
+    /// Anything else. Details are logged but never returned to the client.
+    Internal(anyhow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Stable, machine readable error code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::InvalidInput(_) => "invalid_input",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::RpcUnavailable(_) => "rpc_unavailable",
+            Error::NotFound(_) => "not_found",
+            Error::Conflict(_) => "conflict",
+            Error::Locked(_) => "wallet_locked",
+            Error::PolicyViolation(_) => "policy_violation",
+            Error::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::RpcUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::Locked(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::PolicyViolation(_) => StatusCode::FORBIDDEN,
+            Error::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn rpc(e: impl Into<anyhow::Error>) -> Self {
+        Error::RpcUnavailable(e.into())
+    }
+
+    /// Message that is safe to show to API clients.
+    pub fn public_message(&self) -> String {
+        match self {
+            Error::InvalidInput(msg)
+            | Error::Unauthorized(msg)
+            | Error::NotFound(msg)
+            | Error::Conflict(msg)
+            | Error::Locked(msg)
+            | Error::PolicyViolation(msg) => msg.clone(),
+            Error::RpcUnavailable(_) => String::from("Ethereum node unavailable"),
+            Error::Internal(_) => String::from("Internal server error"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidInput(msg) => write!(f, "invalid input: {}", msg),
+            Error::Unauthorized(msg) => write!(f, "unauthorized: {}", msg),
+            Error::RpcUnavailable(e) => write!(f, "rpc unavailable: {}", e),
+            Error::NotFound(msg) => write!(f, "not found: {}", msg),
+            Error::Conflict(msg) => write!(f, "conflict: {}", msg),
+            Error::Locked(msg) => write!(f, "wallet locked: {}", msg),
+            Error::PolicyViolation(msg) => write!(f, "policy violation: {}", msg),
+            Error::Internal(e) => write!(f, "internal error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<Error>() {
+            Ok(e) => e,
+            Err(e) => Error::Internal(e),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    success: bool,
+    code: &'static str,
+    error: String,
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        match &self {
+            Error::RpcUnavailable(_) | Error::Internal(_) => error!("{:?}", self),
+            _ => {}
+        }
+
+        let body = ErrorResponse {
+            success: false,
+            code: self.code(),
+            error: self.public_message(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
@@ -1,8 +1,10 @@
 pub mod api;
 pub mod config;
+pub mod error;
 pub mod pubsub;
-mod wallet;
+pub mod wallet;
 
 // Re-export the main types that users of our library will need
+pub use error::Error;
 pub use pubsub::Publisher;
 pub use wallet::ethserv::EthServWallet;
@@ -1,31 +1,138 @@
-use alloy::{providers::ProviderBuilder, transports::ws::WsConnect};
-use anyhow::Result;
+use alloy::{
+    primitives::{Address, U256},
+    providers::{Provider, ProviderBuilder},
+    signers::local::PrivateKeySigner,
+    transports::ws::WsConnect,
+};
+use alloy_dyn_abi::TypedData;
+use anyhow::{anyhow, Result};
 use log::info;
-use std::sync::Arc;
+use std::{
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use zeroize::Zeroizing;
 
-use ethserv::{api::create_router, config, EthServWallet};
+use ethserv::{
+    api::{create_router, unlock},
+    config::{
+        self,
+        secrets::{self, Secret},
+    },
+    wallet::{
+        accounts, backup,
+        database::WalletDatabase,
+        derivation::AddressDeriver,
+        discovery::{self, ChainProbe},
+        fees::{self, FeeCaps, Urgency},
+        keys::ProtectedKey,
+        mnemonic::{MnemonicStorage, PassphraseStorage},
+        offline::{
+            self, broadcast_bundle, SignedBundle, UnsignedBundle, UsdtTransfer,
+            USDT_TRANSFER_GAS_LIMIT,
+        },
+        paths::WalletPaths,
+        restore,
+        shares::{self, Share},
+        signing::{self, SignedMessage},
+    },
+    EthServWallet,
+};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     // Initialize the logger
     env_logger::init();
+    // Edits the environment, so it must run before the runtime's threads
+    secrets::init();
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run())
+}
+
+async fn run() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return run_command(&args).await;
+    }
+
     info!("Starting BitServ wallet application...");
 
     let port = config::port();
     let rpc_url = config::rpc_url();
 
-    let wallet_pw = config::wallet_pw();
-
     let ws = WsConnect::new(rpc_url);
 
     let provider = ProviderBuilder::new().on_ws(ws).await?;
 
-    let mut wallet = EthServWallet::new(wallet_pw, provider)?;
+    let mut wallet = match config::xpub() {
+        Some(xpub) => {
+            info!("Running in watch-only mode");
+            EthServWallet::new_watch_only(xpub, provider)?
+        }
+        None => match secrets::wallet_password()? {
+            Some(password) => {
+                let passphrase = secrets::wallet_passphrase()?;
+                EthServWallet::new(
+                    &password,
+                    passphrase.as_deref().map(String::as_str),
+                    provider,
+                )?
+            }
+            None => {
+                info!("No wallet password available, starting locked. Run `ethserv unlock`");
+                EthServWallet::new_locked(provider)?
+            }
+        },
+    };
 
     wallet.start_sync();
+    // Re-broadcasts forgotten transactions before their nonces count as gaps
+    if let Err(e) = wallet.poll_transactions().await {
+        log::error!("Transaction polling failed: {:?}", e);
+    }
+    if let Err(e) = wallet.recover_withdrawals() {
+        log::error!("Withdrawal recovery failed: {:?}", e);
+    }
+    if let Err(e) = wallet.reconcile_nonces(None).await {
+        log::error!("Nonce reconciliation failed: {:?}", e);
+    }
+    if let Err(e) = wallet.sync_blacklist().await {
+        log::error!("Blacklist sync failed: {:?}", e);
+    }
 
     // // Create router
     let wallet = Arc::new(wallet);
+
+    if !wallet.is_watch_only() && config::wallet_id().is_some() {
+        let socket_path = WalletPaths::from_config("")?.socket_path;
+        let wallet = wallet.clone();
+        tokio::spawn(async move {
+            if let Err(e) = unlock::serve(&socket_path, wallet).await {
+                log::error!("Control socket failed: {:?}", e);
+            }
+        });
+    }
+    {
+        let wallet = wallet.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config::tx_poll_interval());
+            loop {
+                interval.tick().await;
+                if let Err(e) = wallet.poll_transactions().await {
+                    log::error!("Transaction polling failed: {:?}", e);
+                }
+                // Keeps the synced block current, so a restart has little
+                // to catch up on, and picks up events the subscription missed
+                if let Err(e) = wallet.sync_blacklist().await {
+                    log::error!("Blacklist sync failed: {:?}", e);
+                }
+            }
+        });
+    }
     let app = create_router(wallet);
 
     let bind_address = format!("127.0.0.1:{}", port);
@@ -38,3 +145,439 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+async fn run_command(args: &[String]) -> Result<()> {
+    match args {
+        [command] if command == "export-xpub" => {
+            let passphrase = secrets::wallet_passphrase()?;
+            let xpub = EthServWallet::export_account_xpub(
+                &require_wallet_pw()?,
+                passphrase.as_deref().map(String::as_str),
+            )?;
+            println!("{}", xpub);
+            Ok(())
+        }
+        [command] if command == "init" => init_wallet(),
+        [command] if command == "unlock" => {
+            config::wallet_id().ok_or_else(|| anyhow!("WALLET_ID must be set"))?;
+            let socket_path = WalletPaths::from_config("")?.socket_path;
+            let password = secrets::read_secret("Wallet password:")?;
+            let passphrase = secrets::wallet_passphrase()?;
+            unlock::send_unlock(&socket_path, &password, passphrase.as_ref()).await?;
+            println!("Wallet unlocked");
+            Ok(())
+        }
+        [command] if command == "lock" => {
+            config::wallet_id().ok_or_else(|| anyhow!("WALLET_ID must be set"))?;
+            unlock::send_lock(&WalletPaths::from_config("")?.socket_path).await?;
+            println!("Wallet locked");
+            Ok(())
+        }
+        [command] if command == "rotate-password" => rotate_password(),
+        [command] if command == "adopt-wallet-id" => {
+            let wallet_id = config::wallet_id().ok_or_else(|| anyhow!("WALLET_ID must be set"))?;
+            let paths =
+                WalletPaths::resolve(Path::new(config::data_dir()), None, &require_wallet_pw()?)?;
+            let _lock = paths.lock()?;
+            paths.adopt_wallet_id(wallet_id)?;
+            println!("Wallet files renamed for id {}", wallet_id);
+            Ok(())
+        }
+        [command] if command == "migrate-mnemonic" => {
+            let password = require_wallet_pw()?;
+            let paths = WalletPaths::from_config(&password)?;
+            let _lock = paths.lock()?;
+            if MnemonicStorage::new(paths.mnemonic_path).migrate(&password)? {
+                println!("Mnemonic re-encrypted with the current format");
+            } else {
+                println!("Mnemonic is already up to date");
+            }
+            Ok(())
+        }
+        [command, from, to, amount, unsigned_path] if command == "prepare" => {
+            prepare_transfer(from, to, amount, unsigned_path).await
+        }
+        [command, signed_path] if command == "broadcast" => {
+            let bundle = SignedBundle::load(signed_path)?;
+            let provider = ProviderBuilder::new()
+                .on_ws(WsConnect::new(config::rpc_url()))
+                .await?;
+            let hashes = broadcast_bundle(&provider, &bundle).await?;
+            println!("Broadcast {} transactions", hashes.len());
+            Ok(())
+        }
+        [command, out] if command == "backup" => {
+            let password = require_wallet_pw()?;
+            let paths = WalletPaths::from_config(&password)?;
+            let passphrase = wallet_passphrase(&paths, &password)?;
+            let manifest = backup::create_backup(
+                &paths,
+                &password,
+                passphrase.as_deref().map(String::as_str),
+                config::derivation_path(),
+                Path::new(out),
+            )?;
+            println!("Backup of seed {} written to {}", manifest.fingerprint, out);
+            Ok(())
+        }
+        [command, bundle] if command == "verify-backup" => {
+            let password = require_wallet_pw()?;
+            let paths = WalletPaths::from_config(&password)?;
+            let passphrase = wallet_passphrase(&paths, &password)?;
+            let manifest = backup::verify_backup(
+                Path::new(bundle),
+                &password,
+                passphrase.as_deref().map(String::as_str),
+                config::derivation_path(),
+                &paths,
+            )?;
+            println!(
+                "Backup of seed {} taken at {} is intact",
+                manifest.fingerprint, manifest.created_at
+            );
+            Ok(())
+        }
+        [command, bundle] if command == "restore" => {
+            let password = require_wallet_pw()?;
+            let paths = WalletPaths::from_config(&password)?;
+            let _lock = paths.lock()?;
+            let passphrase = wallet_passphrase(&paths, &password)?;
+            let manifest = backup::restore_backup(
+                Path::new(bundle),
+                &password,
+                passphrase.as_deref().map(String::as_str),
+                config::derivation_path(),
+                &paths,
+            )?;
+            PassphraseStorage::new(paths.passphrase_path)
+                .set(passphrase.as_deref().map(String::as_str), &password)?;
+            println!(
+                "Restored seed {} from backup taken at {}",
+                manifest.fingerprint, manifest.created_at
+            );
+            Ok(())
+        }
+        [command, rest @ ..] if command == "import-mnemonic" && rest.len() <= 1 => {
+            let rescan_count = match rest.first() {
+                Some(count) => count.parse()?,
+                None => 0,
+            };
+            import_mnemonic(rescan_count, |_| {
+                secrets::read_secret("Enter mnemonic phrase:")
+            })
+        }
+        [command, rest @ ..] if command == "discover" && rest.len() <= 1 => {
+            let gap_limit = match rest.first() {
+                Some(gap_limit) => gap_limit.parse()?,
+                None => config::discovery_gap_limit(),
+            };
+            discover_addresses(gap_limit).await
+        }
+        [command, address, message] if command == "sign-message" => {
+            let signer = address_signer(address)?;
+            print_signature(&signing::sign_message(&signer, message.as_bytes())?)
+        }
+        [command, address, typed_data_path] if command == "sign-typed-data" => {
+            let typed_data: TypedData =
+                serde_json::from_str(&fs::read_to_string(typed_data_path)?)?;
+            let signer = address_signer(address)?;
+            print_signature(&signing::sign_typed_data(&signer, &typed_data)?)
+        }
+        [command, threshold, count] if command == "export-ethserv-shares" => {
+            export_shares(threshold.parse()?, count.parse()?)
+        }
+        [command, rest @ ..] if command == "recover-ethserv-shares" && rest.len() <= 1 => {
+            let rescan_count = match rest.first() {
+                Some(count) => count.parse()?,
+                None => 0,
+            };
+            import_mnemonic(rescan_count, read_shares)
+        }
+        _ => Err(anyhow!("Unknown command: {}", args.join(" "))),
+    }
+}
+
+/// Creates a new wallet. The service never does this on its own, so a wrong
+/// password or a damaged file cannot silently lead to a fresh seed.
+fn init_wallet() -> Result<()> {
+    let password = require_wallet_pw()?;
+    let passphrase = secrets::wallet_passphrase()?;
+    let paths = WalletPaths::from_config(&password)?;
+    let _lock = paths.lock()?;
+    // A data dir whose mnemonic file is gone keeps its database
+    let db = WalletDatabase::new(paths.wallet_path)?;
+    restore::check_unclaimed(&db)?;
+    let storage = MnemonicStorage::new(paths.mnemonic_path);
+    let mnemonic = storage.create(&password, config::mnemonic_words())?;
+
+    let deriver = AddressDeriver::new(
+        &mnemonic,
+        passphrase.as_deref().map(String::as_str),
+        config::derivation_path(),
+    )?;
+    restore::record_fingerprint(&db, &deriver)?;
+    PassphraseStorage::new(paths.passphrase_path)
+        .set(passphrase.as_deref().map(String::as_str), &password)?;
+
+    println!("Write down this mnemonic and keep it offline:");
+    println!("{}", Zeroizing::new(mnemonic.to_phrase()).as_str());
+    if passphrase.is_some() {
+        println!("The seed uses a BIP-39 passphrase, stored encrypted in its own file. Back it up separately from the mnemonic.");
+    }
+    Ok(())
+}
+
+/// Restores a wallet from a phrase read on stdin, so it never appears in the
+/// process list or shell history.
+fn import_mnemonic(
+    rescan_count: u32,
+    read_phrase: impl FnOnce(Option<&str>) -> Result<Secret>,
+) -> Result<()> {
+    let password = require_wallet_pw()?;
+    let passphrase = secrets::wallet_passphrase()?;
+    let paths = WalletPaths::from_config(&password)?;
+    let _lock = paths.lock()?;
+    let storage = MnemonicStorage::new(paths.mnemonic_path);
+    if storage.exists() {
+        return Err(anyhow!(
+            "A wallet already exists for this password, refusing to overwrite it"
+        ));
+    }
+
+    let phrase = read_phrase(passphrase.as_deref().map(String::as_str))?;
+
+    let db = WalletDatabase::new(paths.wallet_path)?;
+    let accounts = accounts::from_config()?;
+    accounts::check_accounts(&db, &accounts)?;
+    let (_, restored) = restore::import_mnemonic(
+        &storage,
+        &db,
+        &password,
+        &phrase,
+        passphrase.as_deref().map(String::as_str),
+        &accounts,
+        rescan_count,
+    )?;
+    PassphraseStorage::new(paths.passphrase_path)
+        .set(passphrase.as_deref().map(String::as_str), &password)?;
+
+    println!("Mnemonic imported, restored {} addresses", restored);
+    Ok(())
+}
+
+/// Scans the chain for addresses issued before the wallet was restored and
+/// stores them, so their deposits are watched again.
+async fn discover_addresses(gap_limit: u32) -> Result<()> {
+    let password = require_wallet_pw()?;
+    let paths = WalletPaths::from_config(&password)?;
+    let _lock = paths.lock()?;
+    let passphrase = wallet_passphrase(&paths, &password)?;
+
+    let db = WalletDatabase::new(paths.wallet_path)?;
+    let accounts = accounts::from_config()?;
+    accounts::check_accounts(&db, &accounts)?;
+    let derivers = {
+        let mnemonic = MnemonicStorage::new(paths.mnemonic_path).load_mnemonic(&password)?;
+        accounts
+            .iter()
+            .map(|account| {
+                AddressDeriver::new(
+                    &mnemonic,
+                    passphrase.as_deref().map(String::as_str),
+                    &account.path,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+    restore::check_fingerprint(&db, &derivers[0], config::derivation_path())?;
+
+    let provider = ProviderBuilder::new()
+        .on_ws(WsConnect::new(config::rpc_url()))
+        .await?;
+    let probe = ChainProbe::new(provider, config::discovery_from_block()).await?;
+    let db = Mutex::new(db);
+    for (account, deriver) in accounts.iter().zip(&derivers) {
+        let report = discovery::discover(&db, deriver, account, gap_limit, &probe).await?;
+        println!(
+            "Account {}: scanned {} addresses, last used index {:?}, added {}",
+            report.account, report.scanned, report.last_used, report.added
+        );
+    }
+    Ok(())
+}
+
+/// Writes an unsigned USDT transfer of `amount` base units to
+/// `unsigned_path`, to be signed with `ethserv-signer` and sent with
+/// `broadcast`. Needs no keys, so it runs on a watch-only instance too.
+async fn prepare_transfer(from: &str, to: &str, amount: &str, unsigned_path: &str) -> Result<()> {
+    let from = Address::from_str(from).map_err(|_| anyhow!("Invalid address {}", from))?;
+    let to = Address::from_str(to).map_err(|_| anyhow!("Invalid address {}", to))?;
+    let amount = U256::from_str_radix(amount, 10)
+        .ok()
+        .filter(|amount| !amount.is_zero())
+        .ok_or_else(|| anyhow!("Amount must be a positive integer of USDT base units"))?;
+    let paths = match config::xpub() {
+        Some(xpub) => WalletPaths::from_config(xpub)?,
+        None => WalletPaths::from_config(&require_wallet_pw()?)?,
+    };
+    let _lock = paths.lock()?;
+    let db = Mutex::new(WalletDatabase::new(paths.wallet_path)?);
+
+    let provider = ProviderBuilder::new()
+        .on_ws(WsConnect::new(config::rpc_url()))
+        .await?;
+    let fees = fees::estimate(&provider, Urgency::from_config()?, FeeCaps::from_config()).await?;
+    let chain_id = provider.get_chain_id().await?;
+    let tx = offline::prepare_usdt_transfer(
+        &db,
+        &provider,
+        chain_id,
+        config::usdt_contract_address(),
+        UsdtTransfer {
+            from,
+            to,
+            amount,
+            gas_limit: USDT_TRANSFER_GAS_LIMIT,
+            gas: fees.gas,
+        },
+    )
+    .await?;
+
+    let nonce = tx.nonce;
+    if let Err(e) = UnsignedBundle::new(vec![tx]).save(unsigned_path) {
+        db.lock().unwrap().release_nonce(&from.to_string(), nonce)?;
+        return Err(e);
+    }
+    println!(
+        "Transfer from {} with nonce {} written to {}, sign it with ethserv-signer",
+        from, nonce, unsigned_path
+    );
+    Ok(())
+}
+
+/// Signer for an address the wallet has issued, for proving control of it
+/// without starting the service.
+fn address_signer(address: &str) -> Result<PrivateKeySigner> {
+    let address = Address::from_str(address).map_err(|_| anyhow!("Invalid address {}", address))?;
+    let password = require_wallet_pw()?;
+    let paths = WalletPaths::from_config(&password)?;
+    let passphrase = wallet_passphrase(&paths, &password)?;
+
+    let db = WalletDatabase::new(paths.wallet_path)?;
+    let record = db
+        .get_address_record(&address.to_string())?
+        .ok_or_else(|| anyhow!("Address {} is not ours", address))?;
+    let account = accounts::from_config()?
+        .into_iter()
+        .find(|account| account.name == record.account)
+        .ok_or_else(|| anyhow!("Account {} is not configured", record.account))?;
+
+    let mnemonic = MnemonicStorage::new(paths.mnemonic_path).load_mnemonic(&password)?;
+    let key = ProtectedKey::account(
+        &mnemonic,
+        passphrase.as_deref().map(String::as_str),
+        &account.path,
+    )?;
+    signing::signer_for(&key, &record)
+}
+
+fn print_signature(signed: &SignedMessage) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(signed)?);
+    Ok(())
+}
+
+/// Prints the seed as `count` Shamir shares, any `threshold` of which
+/// recover it with `recover-ethserv-shares`. The shares hold the mnemonic
+/// only, not the BIP-39 passphrase. They use the ethserv format of
+/// [`shares`], not SLIP-39, so other wallets cannot read them.
+fn export_shares(threshold: u8, count: u8) -> Result<()> {
+    let password = require_wallet_pw()?;
+    let paths = WalletPaths::from_config(&password)?;
+    let passphrase = wallet_passphrase(&paths, &password)?;
+    let mnemonic = MnemonicStorage::new(paths.mnemonic_path).load_mnemonic(&password)?;
+    let deriver = AddressDeriver::new(
+        &mnemonic,
+        passphrase.as_deref().map(String::as_str),
+        config::derivation_path(),
+    )?;
+    let shares = shares::split(&mnemonic, threshold, count)?;
+
+    println!(
+        "Seed {} split into {} shares, {} needed to recover.",
+        restore::fingerprint(&deriver)?,
+        count,
+        threshold
+    );
+    println!("Store each share separately. Either line of a share works.");
+    println!(
+        "These are ethserv shares, not SLIP-39 or a mnemonic. Recover them with \
+         `ethserv recover-ethserv-shares`."
+    );
+    if passphrase.is_some() {
+        println!("The BIP-39 passphrase is not part of the shares, keep it as well.");
+    }
+    for share in &shares {
+        println!();
+        println!("Ethserv share {}/{}:", share.index(), count);
+        println!("{}", share.to_words().as_str());
+        println!("{}", share.to_qr_string().as_str());
+    }
+    Ok(())
+}
+
+/// Reads shares until the threshold of the first one is reached and
+/// returns the recovered phrase.
+fn read_shares(passphrase: Option<&str>) -> Result<Secret> {
+    let mut collected: Vec<Share> = Vec::new();
+    loop {
+        let share: Share =
+            secrets::read_secret(&format!("Enter share {}:", collected.len() + 1))?.parse()?;
+        let threshold = share.threshold() as usize;
+        collected.push(share);
+        if collected.len() >= threshold {
+            break;
+        }
+    }
+
+    let mnemonic = shares::combine(&collected)?;
+    let deriver = AddressDeriver::new(&mnemonic, passphrase, config::derivation_path())?;
+    println!("Recovered seed {}", restore::fingerprint(&deriver)?);
+    Ok(Zeroizing::new(mnemonic.to_phrase()))
+}
+
+/// Re-encrypts the wallet with a new password read from stdin. Update
+/// `WALLET_PW_FILE` or `WALLET_PW` before restarting the service.
+fn rotate_password() -> Result<()> {
+    let old_password = require_wallet_pw()?;
+    let paths = WalletPaths::from_config(&old_password)?;
+    let _lock = paths.lock()?;
+
+    let new_password = secrets::read_secret("Enter new password:")?;
+    if new_password.is_empty() {
+        return Err(anyhow!("New password must not be empty"));
+    }
+    if secrets::read_secret("Repeat new password:")? != new_password {
+        return Err(anyhow!("Passwords do not match"));
+    }
+
+    let new_paths = paths.rotate_password(config::wallet_id(), &old_password, &new_password)?;
+    println!(
+        "Password rotated, wallet files: {} and {}",
+        new_paths.mnemonic_path.display(),
+        new_paths.wallet_path.display()
+    );
+    Ok(())
+}
+
+/// The BIP-39 passphrase of the wallet at `paths`: the stored one, or the
+/// configured one for wallets that have none stored.
+fn wallet_passphrase(paths: &WalletPaths, password: &str) -> Result<Option<Secret>> {
+    let configured = secrets::wallet_passphrase()?;
+    PassphraseStorage::new(paths.passphrase_path.clone())
+        .load_or(password, configured.as_deref().map(String::as_str))
+}
+
+fn require_wallet_pw() -> Result<Secret> {
+    secrets::wallet_password()?
+        .ok_or_else(|| anyhow!("Set WALLET_PW_FILE or WALLET_PW, or run on a terminal"))
+}
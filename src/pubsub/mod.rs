@@ -1,6 +1,6 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use zmq::{Context, Socket};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -17,7 +17,79 @@ pub enum ChainEvent {
     #[serde(rename = "dpst")]
     NewDeposit {
         deposit: (String, String, u64, String, u64), // (address, amount, block_number, hash, index)
+        customer_id: Option<String>,
+        label: Option<String>,
+        /// Whether the sender and recipient are on the USDT blacklist,
+        /// `None` if the contract could not be asked.
+        sender_blacklisted: Option<bool>,
+        recipient_blacklisted: Option<bool>,
     },
+    #[serde(rename = "withdrawal.pending")]
+    WithdrawalPending(WithdrawalEvent),
+    #[serde(rename = "withdrawal.replaced")]
+    WithdrawalReplaced(WithdrawalEvent),
+    #[serde(rename = "withdrawal.mined")]
+    WithdrawalMined(WithdrawalEvent),
+    #[serde(rename = "withdrawal.confirmed")]
+    WithdrawalConfirmed(WithdrawalEvent),
+    #[serde(rename = "withdrawal.failed")]
+    WithdrawalFailed(WithdrawalEvent),
+    #[serde(rename = "withdrawal.dropped")]
+    WithdrawalDropped(WithdrawalEvent),
+    #[serde(rename = "withdrawal.cancelled")]
+    WithdrawalCancelled(WithdrawalEvent),
+    #[serde(rename = "address.frozen")]
+    AddressFrozen(FreezeEvent),
+    #[serde(rename = "address.unfrozen")]
+    AddressUnfrozen(FreezeEvent),
+}
+
+impl ChainEvent {
+    /// ZMQ topic the event is sent under. Withdrawal and address events use
+    /// their name, so subscribers can filter on the `withdrawal.` or
+    /// `address.` prefix.
+    pub fn topic(&self) -> &'static str {
+        match self {
+            ChainEvent::NewTransaction { .. }
+            | ChainEvent::NewAddress { .. }
+            | ChainEvent::NewDeposit { .. } => "tx",
+            ChainEvent::WithdrawalPending(_) => "withdrawal.pending",
+            ChainEvent::WithdrawalReplaced(_) => "withdrawal.replaced",
+            ChainEvent::WithdrawalMined(_) => "withdrawal.mined",
+            ChainEvent::WithdrawalConfirmed(_) => "withdrawal.confirmed",
+            ChainEvent::WithdrawalFailed(_) => "withdrawal.failed",
+            ChainEvent::WithdrawalDropped(_) => "withdrawal.dropped",
+            ChainEvent::WithdrawalCancelled(_) => "withdrawal.cancelled",
+            ChainEvent::AddressFrozen(_) => "address.frozen",
+            ChainEvent::AddressUnfrozen(_) => "address.unfrozen",
+        }
+    }
+}
+
+/// State of an outbound transaction, see `wallet::tracker`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WithdrawalEvent {
+    pub id: i64,
+    pub tx_hash: String,
+    pub from: String,
+    pub nonce: u64,
+    pub recipient: Option<String>,
+    pub amount: Option<String>,
+    pub status: String,
+    pub block_number: Option<u64>,
+}
+
+/// One of our addresses added to or removed from the USDT blacklist, see
+/// `wallet::blacklist`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FreezeEvent {
+    pub address: String,
+    pub customer_id: Option<String>,
+    pub label: Option<String>,
+    /// Block and transaction of the blacklist event, `None` if the change
+    /// was found by checking the address.
+    pub block_number: Option<u64>,
+    pub tx_hash: Option<String>,
 }
 
 pub struct Publisher {
@@ -35,7 +107,7 @@ impl Publisher {
     pub fn publish(&self, event: ChainEvent) -> Result<()> {
         let message = serde_json::to_string(&event)?;
         // Send topic first
-        self.socket.send("tx", zmq::SNDMORE)?;
+        self.socket.send(event.topic(), zmq::SNDMORE)?;
         // Then send the actual message
         self.socket.send(&message, 0)?;
         Ok(())
@@ -43,7 +115,7 @@ impl Publisher {
 }
 
 // Helper function to create a publisher instance
-pub fn create_publisher() -> Arc<Publisher> {
+pub fn create_publisher() -> Arc<Mutex<Publisher>> {
     let publisher = Publisher::new("tcp://*:5556").expect("Failed to create ZMQ publisher");
-    Arc::new(publisher)
+    Arc::new(Mutex::new(publisher))
 }
@@ -0,0 +1,140 @@
+//! Named derivation accounts, e.g. deposits on `m/44'/60'/0'/0/{index}`, a
+//! hot wallet on `m/44'/60'/1'/0/{index}` and gas funding on
+//! `m/44'/60'/2'/0/{index}`.
+//!
+//! The `default` account uses `DERIVATION_PATH`. Others are configured with
+//! `ACCOUNTS`, a comma separated list of `name=template` pairs.
+
+use anyhow::{anyhow, Result};
+use coins_bip32::path::DerivationPath;
+use serde::Serialize;
+
+use crate::{config, error::Error};
+
+use super::database::WalletDatabase;
+
+/// Name of the account issued from when a request names none.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// Placeholder for the address index in a path template.
+const INDEX_PLACEHOLDER: &str = "{index}";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Account {
+    pub name: String,
+    /// Path prefix the address index is appended to, e.g. `m/44'/60'/1'/0/`.
+    pub path: String,
+}
+
+impl Account {
+    /// Builds an account from a path template. The template is either a path
+    /// ending in `/{index}` or, like `DERIVATION_PATH`, a prefix ending in
+    /// `/`. The index must be the last, non-hardened component, so addresses
+    /// can be derived from the key at the prefix.
+    pub fn new(name: &str, template: &str) -> Result<Self> {
+        validate_name(name)?;
+        let invalid = |msg: &str| -> anyhow::Error {
+            Error::InvalidInput(format!("Path template {} {}", template, msg)).into()
+        };
+
+        let prefix = template.strip_suffix(INDEX_PLACEHOLDER).unwrap_or(template);
+        if prefix.contains(INDEX_PLACEHOLDER) {
+            return Err(invalid(
+                "must have {index} as its last, non-hardened component",
+            ));
+        }
+        if !prefix.ends_with('/') {
+            return Err(invalid("must end in /{index} or /"));
+        }
+
+        let path: DerivationPath = prefix
+            .trim_end_matches('/')
+            .parse()
+            .map_err(|_| invalid("is not a valid derivation path"))?;
+        if path.is_empty() {
+            return Err(invalid("must not be the master key"));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            path: prefix.to_string(),
+        })
+    }
+
+    /// Full derivation path of the address at `index`.
+    pub fn derivation_path(&self, index: u32) -> String {
+        format!("{}{}", self.path, index)
+    }
+}
+
+/// Parses `spec`, a comma separated list of `name=template` pairs, and
+/// returns it after the default account at `default_path`.
+pub fn parse_accounts(default_path: &str, spec: Option<&str>) -> Result<Vec<Account>> {
+    let mut accounts = vec![Account::new(DEFAULT_ACCOUNT, default_path)?];
+    for entry in spec.unwrap_or("").split(',').map(str::trim) {
+        if entry.is_empty() {
+            continue;
+        }
+        let (name, template) = entry.split_once('=').ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "Account {} must have the form name=template",
+                entry
+            ))
+        })?;
+        let account = Account::new(name.trim(), template.trim())?;
+
+        if let Some(other) = accounts
+            .iter()
+            .find(|other| other.name == account.name || other.path == account.path)
+        {
+            return Err(Error::InvalidInput(format!(
+                "Accounts {} and {} must have distinct names and paths",
+                other.name, account.name
+            ))
+            .into());
+        }
+        accounts.push(account);
+    }
+    Ok(accounts)
+}
+
+/// The accounts configured with `DERIVATION_PATH` and `ACCOUNTS`.
+pub fn from_config() -> Result<Vec<Account>> {
+    parse_accounts(config::derivation_path(), config::accounts())
+}
+
+/// Fails if `db` holds addresses of an account that is now configured with
+/// a different path, or of a path now used by a different account. Either
+/// would issue the same addresses twice or mix up two accounts.
+pub fn check_accounts(db: &WalletDatabase, accounts: &[Account]) -> Result<()> {
+    for (name, path) in db.get_account_paths()? {
+        for account in accounts {
+            if (account.name == name) != (account.path == path) {
+                return Err(anyhow!(
+                    "Addresses of account {} were issued under {}, but account {} is configured with {}",
+                    name,
+                    path,
+                    account.name,
+                    account.path
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    if !valid {
+        return Err(Error::InvalidInput(format!(
+            "Account name {:?} must be 1 to 32 characters of a-z, 0-9, _ and -",
+            name
+        ))
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,357 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::{
+    aead::{Aead, Payload},
+    ChaCha20Poly1305, KeyInit, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::error::Error;
+
+use super::{
+    database::WalletDatabase,
+    derivation::AddressDeriver,
+    keys::ProtectedKey,
+    mnemonic::{derive_key, write_private, KdfParams, MnemonicStorage},
+    paths::WalletPaths,
+    restore,
+};
+
+pub const BACKUP_VERSION: u32 = 1;
+
+const MNEMONIC_FILE: &str = "mnemonic.dat";
+const DATABASE_FILE: &str = "wallet.sqlite";
+
+/// Describes the contents of a backup. Stored in the encrypted part of the
+/// bundle, so it cannot be altered without the password.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Unix time the backup was taken.
+    pub created_at: u64,
+    pub derivation_path: String,
+    /// Seed fingerprint, see [`restore::fingerprint`].
+    pub fingerprint: String,
+    pub files: Vec<FileEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub name: String,
+    pub size: u64,
+    /// Hex encoded SHA-256 of the file.
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Contents {
+    manifest: Manifest,
+    files: Vec<FileData>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileData {
+    name: String,
+    /// Hex encoded file contents.
+    data: String,
+}
+
+/// On-disk form of a backup bundle.
+#[derive(Serialize, Deserialize)]
+struct EncryptedBackup {
+    version: u32,
+    kdf: KdfParams,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedBackup {
+    fn associated_data(&self) -> Vec<u8> {
+        format!(
+            "ethserv-backup-v{}:{}",
+            self.version,
+            serde_json::to_string(&self.kdf).unwrap_or_default()
+        )
+        .into_bytes()
+    }
+}
+
+/// Writes an encrypted backup of the wallet at `paths` to `out`.
+///
+/// The bundle holds the mnemonic file as is and a snapshot of the database
+/// taken with SQLite's backup API, so it can be taken while the service is
+/// running. Both are checked to belong to the same seed first. The BIP-39
+/// passphrase is not part of the backup and must be kept separately.
+pub fn create_backup(
+    paths: &WalletPaths,
+    password: &str,
+    passphrase: Option<&str>,
+    path_prefix: &str,
+    out: &Path,
+) -> Result<Manifest> {
+    if out.exists() {
+        return Err(anyhow!(
+            "{} already exists, refusing to overwrite it",
+            out.display()
+        ));
+    }
+    if !paths.wallet_path.exists() {
+        return Err(anyhow!(
+            "No wallet database at {}",
+            paths.wallet_path.display()
+        ));
+    }
+
+    let envelope = fs::read(&paths.mnemonic_path)
+        .with_context(|| format!("Failed to read {}", paths.mnemonic_path.display()))?;
+    let deriver = load_deriver(&paths.mnemonic_path, password, passphrase, path_prefix)?;
+
+    let snapshot = StagedFile(paths.wallet_path.with_extension("sqlite.snapshot"));
+    WalletDatabase::new(&paths.wallet_path)?.snapshot_to(&snapshot.0)?;
+    restore::check_fingerprint(&WalletDatabase::new(&snapshot.0)?, &deriver, path_prefix)?;
+    let database = fs::read(&snapshot.0)?;
+
+    let files = [(MNEMONIC_FILE, envelope), (DATABASE_FILE, database)];
+    let manifest = Manifest {
+        version: BACKUP_VERSION,
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        derivation_path: path_prefix.to_string(),
+        fingerprint: restore::fingerprint(&deriver)?,
+        files: files
+            .iter()
+            .map(|(name, data)| FileEntry {
+                name: name.to_string(),
+                size: data.len() as u64,
+                sha256: sha256_hex(data),
+            })
+            .collect(),
+    };
+    let contents = Contents {
+        manifest: manifest.clone(),
+        files: files
+            .iter()
+            .map(|(name, data)| FileData {
+                name: name.to_string(),
+                data: hex::encode(data),
+            })
+            .collect(),
+    };
+
+    let json = serde_json::to_string(&encrypt(&serde_json::to_vec(&contents)?, password)?)?;
+    write_private(out, json.as_bytes())?;
+    Ok(manifest)
+}
+
+/// Checks that `bundle` decrypts with `password`, matches its manifest, and
+/// that its mnemonic and database belong to the same seed. Nothing is
+/// activated; the files are staged next to `target` and removed again.
+pub fn verify_backup(
+    bundle: &Path,
+    password: &str,
+    passphrase: Option<&str>,
+    path_prefix: &str,
+    target: &WalletPaths,
+) -> Result<Manifest> {
+    let (manifest, _, _) = stage(bundle, password, passphrase, path_prefix, target)?;
+    Ok(manifest)
+}
+
+/// Verifies `bundle` like [`verify_backup`] and moves its files into place
+/// at `target`. Refuses to replace an existing wallet. The caller must hold
+/// the wallet lock.
+pub fn restore_backup(
+    bundle: &Path,
+    password: &str,
+    passphrase: Option<&str>,
+    path_prefix: &str,
+    target: &WalletPaths,
+) -> Result<Manifest> {
+    for path in [&target.mnemonic_path, &target.wallet_path] {
+        if path.exists() {
+            return Err(Error::Conflict(format!(
+                "{} already exists, refusing to overwrite it",
+                path.display()
+            ))
+            .into());
+        }
+    }
+
+    let (manifest, mnemonic_file, database_file) =
+        stage(bundle, password, passphrase, path_prefix, target)?;
+
+    // Database first: the wallet only counts as present once its mnemonic is
+    fs::rename(&database_file.0, &target.wallet_path)?;
+    fs::rename(&mnemonic_file.0, &target.mnemonic_path)?;
+    Ok(manifest)
+}
+
+fn stage(
+    bundle: &Path,
+    password: &str,
+    passphrase: Option<&str>,
+    path_prefix: &str,
+    target: &WalletPaths,
+) -> Result<(Manifest, StagedFile, StagedFile)> {
+    let contents = read_bundle(bundle, password)?;
+    let manifest = contents.manifest;
+    if manifest.derivation_path != path_prefix {
+        return Err(anyhow!(
+            "Backup was taken with derivation path {}, configured path is {}",
+            manifest.derivation_path,
+            path_prefix
+        ));
+    }
+
+    let file = |name: &str| -> Result<Vec<u8>> {
+        let data = contents
+            .files
+            .iter()
+            .find(|file| file.name == name)
+            .ok_or_else(|| anyhow!("Backup is missing {}", name))?;
+        Ok(hex::decode(&data.data)?)
+    };
+
+    let mnemonic_file = StagedFile(target.mnemonic_path.with_extension("dat.restore"));
+    write_private(&mnemonic_file.0, &file(MNEMONIC_FILE)?)?;
+    let database_file = StagedFile(target.wallet_path.with_extension("sqlite.restore"));
+    write_private(&database_file.0, &file(DATABASE_FILE)?)?;
+
+    let deriver = load_deriver(&mnemonic_file.0, password, passphrase, path_prefix)?;
+    let fingerprint = restore::fingerprint(&deriver)?;
+    if fingerprint != manifest.fingerprint {
+        return Err(anyhow!(
+            "Backup manifest names seed {}, but its mnemonic is seed {}",
+            manifest.fingerprint,
+            fingerprint
+        ));
+    }
+    restore::check_fingerprint(
+        &WalletDatabase::new(&database_file.0)?,
+        &deriver,
+        path_prefix,
+    )?;
+
+    Ok((manifest, mnemonic_file, database_file))
+}
+
+/// Decrypts `bundle` and checks every file against the manifest.
+fn read_bundle(bundle: &Path, password: &str) -> Result<Contents> {
+    let json = fs::read_to_string(bundle)
+        .with_context(|| format!("Failed to read backup {}", bundle.display()))?;
+    let encrypted: EncryptedBackup =
+        serde_json::from_str(&json).map_err(|e| anyhow!("Backup is not readable: {}", e))?;
+    if encrypted.version != BACKUP_VERSION {
+        return Err(anyhow!(
+            "Unsupported backup version {}, expected {}",
+            encrypted.version,
+            BACKUP_VERSION
+        ));
+    }
+
+    let plaintext = decrypt(&encrypted, password)?;
+    let contents: Contents = serde_json::from_slice(&plaintext)?;
+    if contents.manifest.version != encrypted.version {
+        return Err(anyhow!("Backup manifest version does not match the bundle"));
+    }
+
+    if contents.files.len() != contents.manifest.files.len() {
+        return Err(anyhow!("Backup files do not match the manifest"));
+    }
+    for entry in &contents.manifest.files {
+        let data = contents
+            .files
+            .iter()
+            .find(|file| file.name == entry.name)
+            .ok_or_else(|| anyhow!("Backup is missing {}", entry.name))?;
+        let data = hex::decode(&data.data)?;
+        if data.len() as u64 != entry.size || sha256_hex(&data) != entry.sha256 {
+            return Err(anyhow!("Checksum mismatch for {}", entry.name));
+        }
+    }
+    Ok(contents)
+}
+
+fn encrypt(plaintext: &[u8], password: &str) -> Result<EncryptedBackup> {
+    let mut salt = [0u8; 32];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut encrypted = EncryptedBackup {
+        version: BACKUP_VERSION,
+        kdf: KdfParams::argon2id(),
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: String::new(),
+    };
+
+    let key = Zeroizing::new(derive_key(&encrypted.kdf, password, &salt)?);
+    let cipher = ChaCha20Poly1305::new(key.as_ref().into());
+    let aad = encrypted.associated_data();
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: &aad,
+            },
+        )
+        .map_err(|e| anyhow!("Encryption error: {}", e))?;
+    encrypted.ciphertext = hex::encode(ciphertext);
+    Ok(encrypted)
+}
+
+fn decrypt(encrypted: &EncryptedBackup, password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let salt = hex::decode(&encrypted.salt)?;
+    let nonce = hex::decode(&encrypted.nonce)?;
+    if nonce.len() != 12 {
+        return Err(anyhow!("Backup nonce has the wrong length"));
+    }
+    let ciphertext = hex::decode(&encrypted.ciphertext)?;
+
+    let key = Zeroizing::new(derive_key(&encrypted.kdf, password, &salt)?);
+    let cipher = ChaCha20Poly1305::new(key.as_ref().into());
+    let aad = encrypted.associated_data();
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| anyhow!("Could not decrypt backup: wrong password or corrupted file"))?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+fn load_deriver(
+    mnemonic_path: &Path,
+    password: &str,
+    passphrase: Option<&str>,
+    path_prefix: &str,
+) -> Result<AddressDeriver> {
+    let mnemonic = MnemonicStorage::new(mnemonic_path.to_path_buf()).load_mnemonic(password)?;
+    ProtectedKey::account(&mnemonic, passphrase, path_prefix)?.deriver()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// A file that is removed when dropped unless it was moved away first.
+struct StagedFile(PathBuf);
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,269 @@
+//! Screening against the USDT contract's blacklist.
+//!
+//! Tether freezes an address by adding it to the blacklist: a frozen
+//! address can no longer send USDT, and its balance may be destroyed.
+//! Withdrawals from or to a blacklisted address are denied, and deposits are
+//! published with the blacklist status of their sender and recipient.
+//!
+//! Our own addresses that are frozen are flagged in the database. The
+//! `AddedBlackList` and `RemovedBlackList` events keep the flags current
+//! while the service runs. A sync at startup and after every transaction
+//! poll catches up on them from the last synced block, querying at most
+//! `DISCOVERY_LOG_RANGE` blocks at a time. The first sync has no such block
+//! and checks every address with `isBlackListed` instead.
+
+use std::{future::Future, sync::Mutex};
+
+use alloy::{
+    primitives::Address,
+    providers::{Provider, RootProvider},
+    pubsub::PubSubFrontend,
+    rpc::types::{Filter, Log},
+    sol_types::SolEvent,
+};
+use anyhow::Result;
+use futures_util::StreamExt;
+use serde::Serialize;
+use tokio::sync::mpsc::Receiver;
+
+use crate::{
+    config,
+    error::Error,
+    pubsub::{ChainEvent, FreezeEvent},
+};
+
+use super::{
+    database::{AddressRecord, WalletDatabase},
+    discovery,
+    usdt::contract::IUESDT,
+};
+
+/// Meta key of the last block whose blacklist events were applied.
+pub const SYNCED_BLOCK_KEY: &str = "blacklist_synced_block";
+
+/// An address added to or removed from the blacklist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlacklistChange {
+    pub address: Address,
+    pub blacklisted: bool,
+    /// Block and transaction of the event. `None` for changes found by
+    /// checking an address directly.
+    pub block_number: Option<u64>,
+    pub tx_hash: Option<String>,
+}
+
+impl BlacklistChange {
+    /// Event announcing the change of one of our addresses.
+    pub fn event(&self, record: &AddressRecord) -> ChainEvent {
+        let event = FreezeEvent {
+            address: record.address.clone(),
+            customer_id: record.customer_id.clone(),
+            label: record.label.clone(),
+            block_number: self.block_number,
+            tx_hash: self.tx_hash.clone(),
+        };
+        if self.blacklisted {
+            ChainEvent::AddressFrozen(event)
+        } else {
+            ChainEvent::AddressUnfrozen(event)
+        }
+    }
+}
+
+/// One of our addresses that is on the blacklist.
+#[derive(Debug, Clone, Serialize)]
+pub struct FrozenAddress {
+    #[serde(flatten)]
+    pub record: AddressRecord,
+    pub block_number: Option<u64>,
+    pub tx_hash: Option<String>,
+    pub frozen_at: String,
+}
+
+/// What screening needs from the node.
+pub trait BlacklistSource {
+    fn block_number(&self) -> impl Future<Output = Result<u64>> + Send;
+    /// `isBlackListed` of the USDT contract.
+    fn is_blacklisted(&self, address: Address) -> impl Future<Output = Result<bool>> + Send;
+    /// Blacklist events of the USDT contract in the given blocks, in order.
+    fn blacklist_changes(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> impl Future<Output = Result<Vec<BlacklistChange>>> + Send;
+}
+
+impl BlacklistSource for RootProvider<PubSubFrontend> {
+    async fn block_number(&self) -> Result<u64> {
+        Ok(self.get_block_number().await?)
+    }
+
+    async fn is_blacklisted(&self, address: Address) -> Result<bool> {
+        let contract = IUESDT::new(config::usdt_contract_address(), self);
+        Ok(contract.isBlackListed(address).call().await?._0)
+    }
+
+    async fn blacklist_changes(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<BlacklistChange>> {
+        let filter = blacklist_filter().from_block(from_block).to_block(to_block);
+        let logs = self.get_logs(&filter).await?;
+        Ok(logs.iter().filter_map(parse_blacklist_event).collect())
+    }
+}
+
+fn blacklist_filter() -> Filter {
+    Filter::new()
+        .address(config::usdt_contract_address())
+        .event_signature(vec![
+            IUESDT::AddedBlackList::SIGNATURE_HASH,
+            IUESDT::RemovedBlackList::SIGNATURE_HASH,
+        ])
+}
+
+/// Decodes an `AddedBlackList` or `RemovedBlackList` log.
+pub fn parse_blacklist_event(log: &Log) -> Option<BlacklistChange> {
+    let (address, blacklisted) = if let Ok(decoded) = log.log_decode::<IUESDT::AddedBlackList>() {
+        (decoded.inner.data._user, true)
+    } else if let Ok(decoded) = log.log_decode::<IUESDT::RemovedBlackList>() {
+        (decoded.inner.data._user, false)
+    } else {
+        return None;
+    };
+    Some(BlacklistChange {
+        address,
+        blacklisted,
+        block_number: log.block_number,
+        tx_hash: log.transaction_hash.map(|hash| hash.to_string()),
+    })
+}
+
+/// Streams blacklist events as they are mined.
+pub async fn subscribe(
+    provider: &RootProvider<PubSubFrontend>,
+) -> Result<Receiver<BlacklistChange>> {
+    let (sender, receiver) = tokio::sync::mpsc::channel(100);
+    let sub = provider.subscribe_logs(&blacklist_filter()).await?;
+
+    tokio::spawn(async move {
+        let mut stream = sub.into_stream();
+        while let Some(log) = stream.next().await {
+            if let Some(change) = parse_blacklist_event(&log) {
+                if sender.send(change).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/// Flags or unflags those of `changes` that concern our addresses, and
+/// returns the ones that changed a flag with the address's record.
+pub fn apply(
+    db: &Mutex<WalletDatabase>,
+    changes: &[BlacklistChange],
+) -> Result<Vec<(BlacklistChange, AddressRecord)>> {
+    apply_locked(&db.lock().unwrap(), changes)
+}
+
+fn apply_locked(
+    db: &WalletDatabase,
+    changes: &[BlacklistChange],
+) -> Result<Vec<(BlacklistChange, AddressRecord)>> {
+    let mut applied = Vec::new();
+    for change in changes {
+        let address = change.address.to_string();
+        let Some(record) = db.get_address_record(&address)? else {
+            continue;
+        };
+        if db.set_address_frozen(
+            &address,
+            change.blacklisted,
+            change.block_number,
+            change.tx_hash.as_deref(),
+        )? {
+            println!(
+                "Address {} was {} the USDT blacklist",
+                address,
+                if change.blacklisted {
+                    "added to"
+                } else {
+                    "removed from"
+                }
+            );
+            applied.push((change.clone(), record));
+        }
+    }
+    Ok(applied)
+}
+
+/// Applies the blacklist events since the last synced block, or checks
+/// every address if there is none, and records the head as synced. Events
+/// are queried `log_range` blocks at a time, each range recorded as synced
+/// once applied, so a failure does not lose the progress made.
+pub async fn sync<S: BlacklistSource>(
+    db: &Mutex<WalletDatabase>,
+    source: &S,
+    log_range: u64,
+) -> Result<Vec<(BlacklistChange, AddressRecord)>> {
+    let head = source.block_number().await?;
+    let synced = db.lock().unwrap().get_meta(SYNCED_BLOCK_KEY)?;
+
+    let Some(synced) = synced else {
+        let applied = scan(db, source).await?;
+        db.lock()
+            .unwrap()
+            .set_meta(SYNCED_BLOCK_KEY, &head.to_string())?;
+        return Ok(applied);
+    };
+    let synced: u64 = synced.parse()?;
+    let mut applied = Vec::new();
+    if synced >= head {
+        return Ok(applied);
+    }
+    for (from_block, to_block) in discovery::block_ranges(synced + 1, head, log_range) {
+        let changes = source.blacklist_changes(from_block, to_block).await?;
+        let db = db.lock().unwrap();
+        applied.extend(apply_locked(&db, &changes)?);
+        db.set_meta(SYNCED_BLOCK_KEY, &to_block.to_string())?;
+    }
+    Ok(applied)
+}
+
+/// Checks every one of our addresses with `isBlackListed`.
+pub async fn scan<S: BlacklistSource>(
+    db: &Mutex<WalletDatabase>,
+    source: &S,
+) -> Result<Vec<(BlacklistChange, AddressRecord)>> {
+    let addresses = db.lock().unwrap().get_all_addresses()?;
+    let mut changes = Vec::new();
+    for (address, _path) in addresses {
+        let address: Address = address.parse()?;
+        changes.push(BlacklistChange {
+            address,
+            blacklisted: source.is_blacklisted(address).await?,
+            block_number: None,
+            tx_hash: None,
+        });
+    }
+    apply(db, &changes)
+}
+
+/// Fails with [`Error::PolicyViolation`] if a transfer from `from` to `to`
+/// involves a blacklisted address.
+pub async fn screen<S: BlacklistSource>(source: &S, from: Address, to: Address) -> Result<()> {
+    for (role, address) in [("Source", from), ("Destination", to)] {
+        if source.is_blacklisted(address).await.map_err(Error::rpc)? {
+            return Err(Error::PolicyViolation(format!(
+                "{} {} is on the USDT blacklist",
+                role, address
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
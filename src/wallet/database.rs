@@ -1,6 +1,42 @@
-use anyhow::Result;
-use rusqlite::{params, Connection};
-use std::path::Path;
+use alloy::primitives::U256;
+use anyhow::{anyhow, Result};
+use rusqlite::{
+    params, Connection, DatabaseName, OptionalExtension, Transaction, TransactionBehavior,
+};
+use serde::Serialize;
+use std::{path::Path, time::Duration};
+
+use super::{
+    accounts::DEFAULT_ACCOUNT,
+    blacklist::FrozenAddress,
+    nonces::{NonceReservation, NonceState, NonceStatus},
+    offline::UnsignedTransaction,
+    policy::{
+        Approval, AuditAction, AuditEntry, NewWithdrawal, WithdrawalRequest, WithdrawalStatus,
+        POLICY_ACTOR,
+    },
+    tracker::{TrackedTransaction, TxAttempt, TxStatus},
+};
+
+/// How long a writer waits for another connection to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A derived address together with the caller supplied metadata it was
+/// issued with.
+#[derive(Debug, Clone, Serialize)]
+pub struct AddressRecord {
+    pub address: String,
+    /// Name of the derivation account, see [`super::accounts`].
+    pub account: String,
+    pub path: String,
+    pub addr_index: u32,
+    pub customer_id: Option<String>,
+    pub label: Option<String>,
+    pub idempotency_key: Option<String>,
+}
+
+/// Index of the first address issued under a derivation path.
+pub const FIRST_ADDRESS_INDEX: u32 = 1;
 
 pub struct WalletDatabase {
     conn: Connection,
@@ -9,6 +45,7 @@ pub struct WalletDatabase {
 impl WalletDatabase {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
 
         // Create addresses table with new schema
         conn.execute(
@@ -30,6 +67,156 @@ impl WalletDatabase {
             [],
         )?;
 
+        // Columns added after the initial schema
+        add_column_if_missing(&conn, "addresses", "customer_id", "TEXT")?;
+        add_column_if_missing(&conn, "addresses", "label", "TEXT")?;
+        add_column_if_missing(&conn, "addresses", "idempotency_key", "TEXT")?;
+        add_column_if_missing(&conn, "addresses", "account", "TEXT")?;
+
+        // Addresses from before accounts existed were all issued from the default one
+        conn.execute(
+            "UPDATE addresses SET account = ?1 WHERE account IS NULL",
+            [DEFAULT_ACCOUNT],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_addresses_customer_id ON addresses(customer_id)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS wallet_meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // SQLite cannot add a UNIQUE column in place, so enforce it with an index.
+        // NULLs are distinct, so addresses issued without a key are unaffected.
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_addresses_idempotency_key
+             ON addresses(idempotency_key)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS nonces (
+                address TEXT PRIMARY KEY,
+                next_nonce INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS nonce_reservations (
+                address TEXT NOT NULL,
+                nonce INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                tx_hash TEXT,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY(address, nonce)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transactions (
+                id INTEGER PRIMARY KEY,
+                from_address TEXT NOT NULL,
+                nonce INTEGER NOT NULL,
+                tx TEXT NOT NULL,
+                tx_hash TEXT NOT NULL,
+                raw TEXT NOT NULL,
+                recipient TEXT,
+                amount TEXT,
+                status TEXT NOT NULL,
+                block_number INTEGER,
+                broadcasts INTEGER NOT NULL DEFAULT 0,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_status ON transactions(status)",
+            [],
+        )?;
+
+        // Every signed version of a transaction, any of which may be mined
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transaction_attempts (
+                tx_hash TEXT PRIMARY KEY,
+                transaction_id INTEGER NOT NULL REFERENCES transactions(id),
+                cancel INTEGER NOT NULL DEFAULT 0,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS withdrawal_requests (
+                id INTEGER PRIMARY KEY,
+                from_address TEXT NOT NULL,
+                to_address TEXT NOT NULL,
+                amount TEXT NOT NULL,
+                urgency TEXT,
+                requested_by TEXT NOT NULL,
+                status TEXT NOT NULL,
+                reason TEXT,
+                transaction_id INTEGER REFERENCES transactions(id),
+                approved_at DATETIME,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+        add_column_if_missing(&conn, "withdrawal_requests", "approved_at", "DATETIME")?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_withdrawal_requests_created_at
+             ON withdrawal_requests(created_at)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS withdrawal_approvals (
+                id INTEGER PRIMARY KEY,
+                withdrawal_id INTEGER NOT NULL REFERENCES withdrawal_requests(id),
+                approver TEXT NOT NULL,
+                approved INTEGER NOT NULL,
+                comment TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        // Our addresses on the USDT blacklist
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS frozen_addresses (
+                address TEXT PRIMARY KEY REFERENCES addresses(address),
+                block_number INTEGER,
+                tx_hash TEXT,
+                frozen_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        // Append only, nothing ever updates or deletes entries
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY,
+                actor TEXT NOT NULL,
+                action TEXT NOT NULL,
+                withdrawal_id INTEGER,
+                transaction_id INTEGER,
+                details TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
         Ok(Self { conn })
     }
 
@@ -47,14 +234,925 @@ impl WalletDatabase {
 
         // Try to insert and check if it actually happened
         let changes = self.conn.execute(
-            "INSERT INTO addresses (address, path, addr_index) VALUES (?1, ?2, ?3)",
-            params![address, path, addr_index],
+            "INSERT INTO addresses (address, account, path, addr_index) VALUES (?1, ?2, ?3, ?4)",
+            params![address, DEFAULT_ACCOUNT, path, addr_index],
         )?;
 
         // Verify that exactly one row was inserted
         Ok(changes == 1)
     }
 
+    /// Inserts all `records` in a single transaction. Either every address is
+    /// stored or none is.
+    pub fn store_addresses(&self, records: &[AddressRecord]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        insert_addresses(&tx, records)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Allocates the next `count` indexes under `path` and stores the records
+    /// produced by `build` for each of them.
+    ///
+    /// The transaction takes SQLite's write lock before reading the current
+    /// maximum index, so concurrent callers, including other connections or
+    /// processes, are serialized and never receive the same index.
+    pub fn allocate_addresses<F>(
+        &self,
+        path: &str,
+        count: u32,
+        mut build: F,
+    ) -> Result<Vec<AddressRecord>>
+    where
+        F: FnMut(u32) -> Result<AddressRecord>,
+    {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+
+        let last_index: Option<u32> = tx.query_row(
+            "SELECT MAX(addr_index) FROM addresses WHERE path = ?1",
+            [path],
+            |row| row.get(0),
+        )?;
+        let first_index = last_index.map_or(FIRST_ADDRESS_INDEX, |i| i + 1);
+
+        let records = (first_index..first_index + count)
+            .map(&mut build)
+            .collect::<Result<Vec<_>>>()?;
+
+        insert_addresses(&tx, &records)?;
+        tx.commit()?;
+        Ok(records)
+    }
+
+    /// Writes a consistent copy of the database to `dest` using SQLite's
+    /// backup API, which is safe while other connections write to it.
+    pub fn snapshot_to(&self, dest: &Path) -> Result<()> {
+        self.conn.backup(DatabaseName::Main, dest, None)?;
+        Ok(())
+    }
+
+    pub fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        let value = self
+            .conn
+            .query_row(
+                "SELECT value FROM wallet_meta WHERE key = ?1",
+                [key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO wallet_meta (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    /// Hands out the lowest released nonce of `address`, or else its next
+    /// nonce, but at least `floor`. Takes the write lock first, like
+    /// [`Self::allocate_addresses`], so no nonce is handed out twice.
+    pub fn reserve_nonce(&self, address: &str, floor: u64) -> Result<u64> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+
+        let released: Option<u64> = tx.query_row(
+            "SELECT MIN(nonce) FROM nonce_reservations WHERE address = ?1 AND status = ?2",
+            params![address, NonceStatus::Released.as_str()],
+            |row| row.get(0),
+        )?;
+        let nonce = match released {
+            Some(nonce) if nonce >= floor => nonce,
+            _ => {
+                let next: Option<u64> = tx
+                    .query_row(
+                        "SELECT next_nonce FROM nonces WHERE address = ?1",
+                        [address],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                let nonce = next.unwrap_or(0).max(floor);
+                set_next_nonce(&tx, address, nonce + 1)?;
+                nonce
+            }
+        };
+
+        tx.execute(
+            "INSERT INTO nonce_reservations (address, nonce, status) VALUES (?1, ?2, ?3)
+             ON CONFLICT(address, nonce) DO UPDATE
+             SET status = excluded.status, tx_hash = NULL, updated_at = CURRENT_TIMESTAMP",
+            params![address, nonce, NonceStatus::Reserved.as_str()],
+        )?;
+        tx.commit()?;
+        Ok(nonce)
+    }
+
+    /// Records that the transaction with `nonce` was broadcast as `tx_hash`.
+    pub fn mark_nonce_sent(&self, address: &str, nonce: u64, tx_hash: &str) -> Result<()> {
+        let changes = self.conn.execute(
+            "UPDATE nonce_reservations SET status = ?3, tx_hash = ?4, updated_at = CURRENT_TIMESTAMP
+             WHERE address = ?1 AND nonce = ?2",
+            params![address, nonce, NonceStatus::Sent.as_str(), tx_hash],
+        )?;
+        if changes == 0 {
+            return Err(anyhow!("Nonce {} of {} was not reserved", nonce, address));
+        }
+        Ok(())
+    }
+
+    /// Makes `nonce` available again, for a transaction that was never sent.
+    pub fn release_nonce(&self, address: &str, nonce: u64) -> Result<()> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        let changes = tx.execute(
+            "UPDATE nonce_reservations SET status = ?3, tx_hash = NULL, updated_at = CURRENT_TIMESTAMP
+             WHERE address = ?1 AND nonce = ?2",
+            params![address, nonce, NonceStatus::Released.as_str()],
+        )?;
+        if changes == 0 {
+            return Err(anyhow!("Nonce {} of {} was not reserved", nonce, address));
+        }
+        trim_released_nonces(&tx, address)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_nonce_state(&self, address: &str) -> Result<Option<NonceState>> {
+        let next: Option<u64> = self
+            .conn
+            .query_row(
+                "SELECT next_nonce FROM nonces WHERE address = ?1",
+                [address],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match next {
+            Some(next_nonce) => Ok(Some(NonceState {
+                address: address.to_string(),
+                next_nonce,
+                reservations: nonce_reservations(&self.conn, address)?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Addresses nonces were handed out for.
+    pub fn get_nonce_addresses(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT address FROM nonces ORDER BY address")?;
+
+        let addresses = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(addresses)
+    }
+
+    /// Applies what the node reported for `address`, see
+    /// [`super::nonces::reconcile`]. `dropped` holds the sent transactions
+    /// the node no longer knows; they are only released if still recorded
+    /// with the same hash.
+    pub fn reconcile_nonces(
+        &self,
+        address: &str,
+        pending: u64,
+        dropped: &[(u64, String)],
+        stale_after: Duration,
+    ) -> Result<NonceState> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+
+        // The node has every nonce below its pending one
+        tx.execute(
+            "DELETE FROM nonce_reservations WHERE address = ?1 AND nonce < ?2",
+            params![address, pending],
+        )?;
+
+        let next: Option<u64> = tx
+            .query_row(
+                "SELECT next_nonce FROM nonces WHERE address = ?1",
+                [address],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let next = next.unwrap_or(0).max(pending);
+        set_next_nonce(&tx, address, next)?;
+
+        for (nonce, hash) in dropped {
+            tx.execute(
+                "UPDATE nonce_reservations SET status = ?4, tx_hash = NULL, updated_at = CURRENT_TIMESTAMP
+                 WHERE address = ?1 AND nonce = ?2 AND status = ?5 AND tx_hash = ?3",
+                params![
+                    address,
+                    nonce,
+                    hash,
+                    NonceStatus::Released.as_str(),
+                    NonceStatus::Sent.as_str()
+                ],
+            )?;
+        }
+
+        tx.execute(
+            "UPDATE nonce_reservations SET status = ?2, updated_at = CURRENT_TIMESTAMP
+             WHERE address = ?1 AND status = ?3 AND updated_at <= datetime('now', ?4)",
+            params![
+                address,
+                NonceStatus::Released.as_str(),
+                NonceStatus::Reserved.as_str(),
+                format!("-{} seconds", stale_after.as_secs())
+            ],
+        )?;
+
+        // Nonces the node lacks and nobody accounts for, e.g. from a
+        // transaction whose record was lost
+        for nonce in pending..next {
+            tx.execute(
+                "INSERT OR IGNORE INTO nonce_reservations (address, nonce, status)
+                 VALUES (?1, ?2, ?3)",
+                params![address, nonce, NonceStatus::Released.as_str()],
+            )?;
+        }
+
+        trim_released_nonces(&tx, address)?;
+        tx.commit()?;
+
+        self.get_nonce_state(address)?
+            .ok_or_else(|| anyhow!("Nonce state of {} vanished", address))
+    }
+
+    /// Records a transaction about to be broadcast as `pending`.
+    pub fn insert_transaction(
+        &self,
+        tx: &UnsignedTransaction,
+        tx_hash: &str,
+        raw: &str,
+        recipient: Option<&str>,
+        amount: Option<&str>,
+    ) -> Result<i64> {
+        let db_tx = self.conn.unchecked_transaction()?;
+        let id = insert_transaction(&db_tx, tx, tx_hash, raw, recipient, amount)?;
+        db_tx.commit()?;
+        Ok(id)
+    }
+
+    /// Records a transaction about to be broadcast for the approved
+    /// `request` and settles the request as `executed` on behalf of
+    /// `actor`, both at once: an approved request never has a transaction.
+    pub fn insert_withdrawal_transaction(
+        &self,
+        request: &WithdrawalRequest,
+        actor: &str,
+        tx: &UnsignedTransaction,
+        tx_hash: &str,
+        raw: &str,
+    ) -> Result<i64> {
+        let db_tx = self.conn.unchecked_transaction()?;
+        let id = insert_transaction(
+            &db_tx,
+            tx,
+            tx_hash,
+            raw,
+            Some(&request.to),
+            Some(&request.amount),
+        )?;
+        settle_withdrawal_request(&db_tx, request.id, actor, Some(id), None)?;
+        db_tx.commit()?;
+        Ok(id)
+    }
+
+    /// Makes `tx` the latest attempt of transaction `id`, after a speed-up
+    /// or, if `cancel`, a cancel.
+    pub fn replace_transaction(
+        &self,
+        id: i64,
+        tx: &UnsignedTransaction,
+        tx_hash: &str,
+        raw: &str,
+        cancel: bool,
+    ) -> Result<()> {
+        let db_tx = self.conn.unchecked_transaction()?;
+        db_tx.execute(
+            "UPDATE transactions
+             SET tx = ?2, tx_hash = ?3, raw = ?4, broadcasts = 1, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?1",
+            params![id, serde_json::to_string(tx)?, tx_hash, raw],
+        )?;
+        db_tx.execute(
+            "INSERT INTO transaction_attempts (tx_hash, transaction_id, cancel) VALUES (?1, ?2, ?3)",
+            params![tx_hash, id, cancel],
+        )?;
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    /// Restores `previous` as the latest attempt of its transaction after
+    /// the node rejected the attempt `tx_hash` replacing it.
+    pub fn revert_replacement(
+        &self,
+        previous: &TrackedTransaction,
+        raw: &str,
+        tx_hash: &str,
+    ) -> Result<()> {
+        let db_tx = self.conn.unchecked_transaction()?;
+        db_tx.execute(
+            "UPDATE transactions
+             SET tx = ?2, tx_hash = ?3, raw = ?4, broadcasts = ?5, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?1",
+            params![
+                previous.id,
+                serde_json::to_string(&previous.tx)?,
+                previous.tx_hash,
+                raw,
+                previous.broadcasts
+            ],
+        )?;
+        db_tx.execute(
+            "DELETE FROM transaction_attempts WHERE tx_hash = ?1 AND transaction_id = ?2",
+            params![tx_hash, previous.id],
+        )?;
+        db_tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_transaction(&self, id: i64) -> Result<Option<TrackedTransaction>> {
+        Ok(self
+            .query_transactions("t.id = ?1", params![id])?
+            .into_iter()
+            .next())
+    }
+
+    /// Transactions not yet final, oldest first.
+    pub fn get_open_transactions(&self) -> Result<Vec<TrackedTransaction>> {
+        self.query_transactions(
+            "t.status IN (?1, ?2)",
+            params![TxStatus::Pending.as_str(), TxStatus::Mined.as_str()],
+        )
+    }
+
+    /// The latest `limit` transactions, optionally only those with
+    /// `status`, newest first.
+    pub fn list_transactions(
+        &self,
+        status: Option<TxStatus>,
+        limit: u32,
+    ) -> Result<Vec<TrackedTransaction>> {
+        let mut transactions = self.query_transactions(
+            "t.id IN (SELECT id FROM transactions WHERE ?1 IS NULL OR status = ?1
+                      ORDER BY id DESC LIMIT ?2)",
+            params![status.map(|status| status.as_str()), limit],
+        )?;
+        transactions.reverse();
+        Ok(transactions)
+    }
+
+    pub fn get_transaction_attempts(&self, id: i64) -> Result<Vec<TxAttempt>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tx_hash, cancel FROM transaction_attempts
+             WHERE transaction_id = ?1 ORDER BY created_at, rowid",
+        )?;
+
+        let attempts = stmt
+            .query_map([id], |row| {
+                Ok(TxAttempt {
+                    tx_hash: row.get(0)?,
+                    cancel: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(attempts)
+    }
+
+    /// EIP-2718 encoding of the latest attempt of transaction `id`.
+    pub fn get_transaction_raw(&self, id: i64) -> Result<String> {
+        Ok(self
+            .conn
+            .query_row("SELECT raw FROM transactions WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })?)
+    }
+
+    pub fn set_transaction_status(
+        &self,
+        id: i64,
+        status: TxStatus,
+        tx_hash: &str,
+        block_number: Option<u64>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE transactions
+             SET status = ?2, tx_hash = ?3, block_number = ?4, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?1",
+            params![id, status.as_str(), tx_hash, block_number],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_broadcast(&self, id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE transactions SET broadcasts = broadcasts + 1, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?1",
+            [id],
+        )?;
+        Ok(())
+    }
+
+    fn query_transactions(
+        &self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<TrackedTransaction>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT t.id, t.tx, t.tx_hash, t.recipient, t.amount, t.status, t.block_number,
+                    t.broadcasts,
+                    EXISTS (SELECT 1 FROM transaction_attempts a
+                            WHERE a.transaction_id = t.id AND a.cancel)
+             FROM transactions t WHERE {} ORDER BY t.id",
+            condition
+        ))?;
+
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, Option<u64>>(6)?,
+                    row.get::<_, u32>(7)?,
+                    row.get::<_, bool>(8)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(
+                |(id, tx, tx_hash, recipient, amount, status, block_number, broadcasts, cancel)| {
+                    Ok(TrackedTransaction {
+                        id,
+                        tx: serde_json::from_str(&tx)?,
+                        tx_hash,
+                        recipient,
+                        amount,
+                        status: TxStatus::parse(&status)
+                            .ok_or_else(|| anyhow!("Unknown transaction status {}", status))?,
+                        block_number,
+                        broadcasts,
+                        cancel_requested: cancel,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Records a withdrawal request with the policy's decision on it, and
+    /// the request (and denial) in the audit log.
+    pub fn insert_withdrawal_request(
+        &self,
+        withdrawal: &NewWithdrawal,
+        status: WithdrawalStatus,
+        reason: Option<&str>,
+    ) -> Result<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO withdrawal_requests
+                 (from_address, to_address, amount, urgency, requested_by, status, reason,
+                  approved_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7,
+                     CASE WHEN ?6 = ?8 THEN CURRENT_TIMESTAMP END)",
+            params![
+                withdrawal.from.to_string(),
+                withdrawal.to.to_string(),
+                withdrawal.amount.to_string(),
+                withdrawal.urgency.map(|urgency| urgency.as_str()),
+                withdrawal.requested_by,
+                status.as_str(),
+                reason,
+                WithdrawalStatus::Approved.as_str()
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+
+        let details = format!(
+            "{} from {} to {}",
+            withdrawal.amount, withdrawal.from, withdrawal.to
+        );
+        insert_audit_entry(
+            &tx,
+            &withdrawal.requested_by,
+            AuditAction::Requested,
+            Some(id),
+            None,
+            Some(&details),
+        )?;
+        if status == WithdrawalStatus::Denied {
+            insert_audit_entry(
+                &tx,
+                POLICY_ACTOR,
+                AuditAction::Denied,
+                Some(id),
+                None,
+                reason,
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Records `approver`'s decision on request `id` and moves it to
+    /// `status`. A rejection's `comment` becomes the request's reason. A
+    /// request moved to `denied` was denied by the policy for `denial`,
+    /// which is recorded in the same transaction. Only a request awaiting
+    /// approval, or one approved but not yet sent for a rejection, can be
+    /// decided on.
+    pub fn add_withdrawal_approval(
+        &self,
+        id: i64,
+        approver: &str,
+        approved: bool,
+        comment: Option<&str>,
+        status: WithdrawalStatus,
+        denial: Option<&str>,
+    ) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO withdrawal_approvals (withdrawal_id, approver, approved, comment)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![id, approver, approved, comment],
+        )?;
+        let reason = if approved { denial } else { comment };
+        let changes = tx.execute(
+            "UPDATE withdrawal_requests
+             SET status = ?2, reason = COALESCE(?3, reason),
+                 approved_at = CASE WHEN ?2 = ?5 THEN CURRENT_TIMESTAMP END,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND (status = ?4 OR (NOT ?6 AND status = ?5))",
+            params![
+                id,
+                status.as_str(),
+                reason,
+                WithdrawalStatus::AwaitingApproval.as_str(),
+                WithdrawalStatus::Approved.as_str(),
+                approved
+            ],
+        )?;
+        if changes == 0 {
+            return Err(anyhow!(
+                "Withdrawal request {} can no longer be decided on",
+                id
+            ));
+        }
+        let action = if approved {
+            AuditAction::Approved
+        } else {
+            AuditAction::Rejected
+        };
+        insert_audit_entry(&tx, approver, action, Some(id), None, comment)?;
+        if status == WithdrawalStatus::Denied {
+            insert_audit_entry(
+                &tx,
+                POLICY_ACTOR,
+                AuditAction::Denied,
+                Some(id),
+                None,
+                denial,
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Denies the approved request `id` for `reason` on behalf of the
+    /// policy, before it is sent.
+    pub fn deny_withdrawal_request(&self, id: i64, reason: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let changes = tx.execute(
+            "UPDATE withdrawal_requests
+             SET status = ?2, reason = ?3, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?1 AND status = ?4",
+            params![
+                id,
+                WithdrawalStatus::Denied.as_str(),
+                reason,
+                WithdrawalStatus::Approved.as_str()
+            ],
+        )?;
+        if changes == 0 {
+            return Err(anyhow!("Withdrawal request {} is not approved", id));
+        }
+        insert_audit_entry(
+            &tx,
+            POLICY_ACTOR,
+            AuditAction::Denied,
+            Some(id),
+            None,
+            Some(reason),
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records the outcome of sending the approved request `id`: `executed`
+    /// as `transaction_id`, or `failed` for `reason`.
+    pub fn settle_withdrawal_request(
+        &self,
+        id: i64,
+        actor: &str,
+        transaction_id: Option<i64>,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        settle_withdrawal_request(&tx, id, actor, transaction_id, reason)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_withdrawal_request(&self, id: i64) -> Result<Option<WithdrawalRequest>> {
+        Ok(self
+            .query_withdrawal_requests("id = ?1", params![id])?
+            .into_iter()
+            .next())
+    }
+
+    /// The latest `limit` requests, optionally only those with `status`,
+    /// oldest first.
+    pub fn list_withdrawal_requests(
+        &self,
+        status: Option<WithdrawalStatus>,
+        limit: u32,
+    ) -> Result<Vec<WithdrawalRequest>> {
+        self.query_withdrawal_requests(
+            "id IN (SELECT id FROM withdrawal_requests WHERE ?1 IS NULL OR status = ?1
+                    ORDER BY id DESC LIMIT ?2)",
+            params![status.map(|status| status.as_str()), limit],
+        )
+    }
+
+    /// Total amount of the requests within `window` that are sent or may
+    /// still be, other than request `except`. A request counts from when it
+    /// was sent, approved or, while it awaits approval, made. A request
+    /// whose transaction failed, was dropped or was cancelled moved nothing
+    /// and does not count.
+    pub fn get_withdrawn_amount(&self, window: Duration, except: Option<i64>) -> Result<U256> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.amount FROM withdrawal_requests r
+             LEFT JOIN transactions t ON t.id = r.transaction_id
+             WHERE COALESCE(t.created_at, r.approved_at, r.created_at) >= datetime('now', ?1)
+               AND r.status IN (?2, ?3, ?4)
+               AND (t.status IS NULL OR t.status NOT IN (?5, ?6, ?7))
+               AND r.id IS NOT ?8",
+        )?;
+        let amounts = stmt
+            .query_map(
+                params![
+                    format!("-{} seconds", window.as_secs()),
+                    WithdrawalStatus::AwaitingApproval.as_str(),
+                    WithdrawalStatus::Approved.as_str(),
+                    WithdrawalStatus::Executed.as_str(),
+                    TxStatus::Failed.as_str(),
+                    TxStatus::Dropped.as_str(),
+                    TxStatus::Cancelled.as_str(),
+                    except
+                ],
+                |row| row.get::<_, String>(0),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        amounts.iter().try_fold(U256::ZERO, |total, amount| {
+            Ok(total.saturating_add(U256::from_str_radix(amount, 10)?))
+        })
+    }
+
+    fn query_withdrawal_requests(
+        &self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<WithdrawalRequest>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, from_address, to_address, amount, urgency, requested_by, status, reason,
+                    transaction_id, created_at
+             FROM withdrawal_requests WHERE {} ORDER BY id",
+            condition
+        ))?;
+
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                    row.get::<_, Option<i64>>(8)?,
+                    row.get::<_, String>(9)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(
+                |(
+                    id,
+                    from,
+                    to,
+                    amount,
+                    urgency,
+                    requested_by,
+                    status,
+                    reason,
+                    transaction_id,
+                    created_at,
+                )| {
+                    Ok(WithdrawalRequest {
+                        id,
+                        from,
+                        to,
+                        amount,
+                        urgency: urgency.as_deref().map(str::parse).transpose()?,
+                        requested_by,
+                        status: WithdrawalStatus::parse(&status)
+                            .ok_or_else(|| anyhow!("Unknown withdrawal status {}", status))?,
+                        reason,
+                        transaction_id,
+                        approvals: self.withdrawal_approvals(id)?,
+                        created_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    fn withdrawal_approvals(&self, id: i64) -> Result<Vec<Approval>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT approver, approved, comment, created_at FROM withdrawal_approvals
+             WHERE withdrawal_id = ?1 ORDER BY id",
+        )?;
+
+        let approvals = stmt
+            .query_map([id], |row| {
+                Ok(Approval {
+                    approver: row.get(0)?,
+                    approved: row.get(1)?,
+                    comment: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(approvals)
+    }
+
+    /// Appends to the audit log. An entry about a transaction is filed under
+    /// the withdrawal request it was sent for, if any.
+    pub fn record_audit_entry(
+        &self,
+        actor: &str,
+        action: AuditAction,
+        transaction_id: Option<i64>,
+        details: Option<&str>,
+    ) -> Result<()> {
+        insert_audit_entry(&self.conn, actor, action, None, transaction_id, details)
+    }
+
+    /// The latest `limit` audit entries, optionally only those about
+    /// withdrawal request `withdrawal_id`, oldest first.
+    pub fn list_audit_entries(
+        &self,
+        withdrawal_id: Option<i64>,
+        limit: u32,
+    ) -> Result<Vec<AuditEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, actor, action, withdrawal_id, transaction_id, details, created_at
+             FROM audit_log
+             WHERE id IN (SELECT id FROM audit_log WHERE ?1 IS NULL OR withdrawal_id = ?1
+                          ORDER BY id DESC LIMIT ?2)
+             ORDER BY id",
+        )?;
+
+        let rows = stmt
+            .query_map(params![withdrawal_id, limit], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, String>(6)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(
+                |(id, actor, action, withdrawal_id, transaction_id, details, created_at)| {
+                    Ok(AuditEntry {
+                        id,
+                        actor,
+                        action: AuditAction::parse(&action)
+                            .ok_or_else(|| anyhow!("Unknown audit action {}", action))?,
+                        withdrawal_id,
+                        transaction_id,
+                        details,
+                        created_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    pub fn get_address_record(&self, address: &str) -> Result<Option<AddressRecord>> {
+        self.query_address_record("address = ?1", address)
+    }
+
+    pub fn get_address_by_idempotency_key(&self, key: &str) -> Result<Option<AddressRecord>> {
+        self.query_address_record("idempotency_key = ?1", key)
+    }
+
+    fn query_address_record(&self, condition: &str, value: &str) -> Result<Option<AddressRecord>> {
+        let record = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT address, account, path, addr_index, customer_id, label, idempotency_key
+                     FROM addresses WHERE {}",
+                    condition
+                ),
+                [value],
+                |row| {
+                    Ok(AddressRecord {
+                        address: row.get(0)?,
+                        account: row.get(1)?,
+                        path: row.get(2)?,
+                        addr_index: row.get(3)?,
+                        customer_id: row.get(4)?,
+                        label: row.get(5)?,
+                        idempotency_key: row.get(6)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(record)
+    }
+
+    /// Flags `address` as frozen, or clears the flag. Returns `false` if the
+    /// flag already had that state.
+    pub fn set_address_frozen(
+        &self,
+        address: &str,
+        frozen: bool,
+        block_number: Option<u64>,
+        tx_hash: Option<&str>,
+    ) -> Result<bool> {
+        let changes = if frozen {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO frozen_addresses (address, block_number, tx_hash)
+                 VALUES (?1, ?2, ?3)",
+                params![address, block_number, tx_hash],
+            )?
+        } else {
+            self.conn
+                .execute("DELETE FROM frozen_addresses WHERE address = ?1", [address])?
+        };
+        Ok(changes == 1)
+    }
+
+    pub fn get_frozen_addresses(&self) -> Result<Vec<FrozenAddress>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT a.address, a.account, a.path, a.addr_index, a.customer_id, a.label,
+                    a.idempotency_key, f.block_number, f.tx_hash, f.frozen_at
+             FROM frozen_addresses f JOIN addresses a ON a.address = f.address
+             ORDER BY f.frozen_at, a.id",
+        )?;
+
+        let frozen = stmt
+            .query_map([], |row| {
+                Ok(FrozenAddress {
+                    record: AddressRecord {
+                        address: row.get(0)?,
+                        account: row.get(1)?,
+                        path: row.get(2)?,
+                        addr_index: row.get(3)?,
+                        customer_id: row.get(4)?,
+                        label: row.get(5)?,
+                        idempotency_key: row.get(6)?,
+                    },
+                    block_number: row.get(7)?,
+                    tx_hash: row.get(8)?,
+                    frozen_at: row.get(9)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(frozen)
+    }
+
     pub fn get_max_index_for_path(&self, path: &str) -> Result<Option<u32>> {
         let mut stmt = self
             .conn
@@ -93,6 +1191,19 @@ impl WalletDatabase {
         Ok(addresses)
     }
 
+    /// Distinct `(account, path)` pairs addresses were issued under.
+    pub fn get_account_paths(&self) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT account, path FROM addresses ORDER BY account, path")?;
+
+        let pairs = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(pairs)
+    }
+
     pub fn update_last_used(&self, address: &str) -> Result<()> {
         self.conn.execute(
             "UPDATE addresses SET last_used = CURRENT_TIMESTAMP WHERE address = ?1",
@@ -147,3 +1258,181 @@ impl WalletDatabase {
         Ok(count > 0)
     }
 }
+
+fn insert_addresses(tx: &Transaction, records: &[AddressRecord]) -> Result<()> {
+    let mut stmt = tx.prepare(
+        "INSERT INTO addresses
+             (address, account, path, addr_index, customer_id, label, idempotency_key)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+    )?;
+    for record in records {
+        stmt.execute(params![
+            record.address,
+            record.account,
+            record.path,
+            record.addr_index,
+            record.customer_id,
+            record.label,
+            record.idempotency_key
+        ])?;
+    }
+    Ok(())
+}
+
+fn set_next_nonce(tx: &Transaction, address: &str, next_nonce: u64) -> Result<()> {
+    tx.execute(
+        "INSERT INTO nonces (address, next_nonce) VALUES (?1, ?2)
+         ON CONFLICT(address) DO UPDATE SET next_nonce = excluded.next_nonce",
+        params![address, next_nonce],
+    )?;
+    Ok(())
+}
+
+/// Released nonces directly below the next nonce are not gaps, so the next
+/// nonce moves back over them.
+fn trim_released_nonces(tx: &Transaction, address: &str) -> Result<()> {
+    let mut next: u64 = tx.query_row(
+        "SELECT next_nonce FROM nonces WHERE address = ?1",
+        [address],
+        |row| row.get(0),
+    )?;
+    while next > 0 {
+        let removed = tx.execute(
+            "DELETE FROM nonce_reservations WHERE address = ?1 AND nonce = ?2 AND status = ?3",
+            params![address, next - 1, NonceStatus::Released.as_str()],
+        )?;
+        if removed == 0 {
+            break;
+        }
+        next -= 1;
+    }
+    set_next_nonce(tx, address, next)
+}
+
+fn nonce_reservations(conn: &Connection, address: &str) -> Result<Vec<NonceReservation>> {
+    let mut stmt = conn.prepare(
+        "SELECT nonce, status, tx_hash FROM nonce_reservations WHERE address = ?1 ORDER BY nonce",
+    )?;
+
+    let rows = stmt
+        .query_map([address], |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    rows.into_iter()
+        .map(|(nonce, status, tx_hash)| {
+            Ok(NonceReservation {
+                nonce,
+                status: NonceStatus::parse(&status)
+                    .ok_or_else(|| anyhow!("Unknown nonce status {}", status))?,
+                tx_hash,
+            })
+        })
+        .collect()
+}
+
+fn insert_audit_entry(
+    conn: &Connection,
+    actor: &str,
+    action: AuditAction,
+    withdrawal_id: Option<i64>,
+    transaction_id: Option<i64>,
+    details: Option<&str>,
+) -> Result<()> {
+    conn.execute(
+        "INSERT INTO audit_log (actor, action, withdrawal_id, transaction_id, details)
+         VALUES (?1, ?2,
+                 COALESCE(?3, (SELECT id FROM withdrawal_requests WHERE transaction_id = ?4)),
+                 ?4, ?5)",
+        params![
+            actor,
+            action.as_str(),
+            withdrawal_id,
+            transaction_id,
+            details
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_transaction(
+    db_tx: &Transaction,
+    tx: &UnsignedTransaction,
+    tx_hash: &str,
+    raw: &str,
+    recipient: Option<&str>,
+    amount: Option<&str>,
+) -> Result<i64> {
+    db_tx.execute(
+        "INSERT INTO transactions
+             (from_address, nonce, tx, tx_hash, raw, recipient, amount, status)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            tx.from.to_string(),
+            tx.nonce,
+            serde_json::to_string(tx)?,
+            tx_hash,
+            raw,
+            recipient,
+            amount,
+            TxStatus::Pending.as_str()
+        ],
+    )?;
+    let id = db_tx.last_insert_rowid();
+    db_tx.execute(
+        "INSERT INTO transaction_attempts (tx_hash, transaction_id) VALUES (?1, ?2)",
+        params![tx_hash, id],
+    )?;
+    Ok(id)
+}
+
+fn settle_withdrawal_request(
+    tx: &Transaction,
+    id: i64,
+    actor: &str,
+    transaction_id: Option<i64>,
+    reason: Option<&str>,
+) -> Result<()> {
+    let (status, action) = match transaction_id {
+        Some(_) => (WithdrawalStatus::Executed, AuditAction::Executed),
+        None => (WithdrawalStatus::Failed, AuditAction::Failed),
+    };
+    let changes = tx.execute(
+        "UPDATE withdrawal_requests
+         SET status = ?2, transaction_id = ?3, reason = ?4, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?1 AND status = ?5",
+        params![
+            id,
+            status.as_str(),
+            transaction_id,
+            reason,
+            WithdrawalStatus::Approved.as_str()
+        ],
+    )?;
+    if changes == 0 {
+        return Err(anyhow!("Withdrawal request {} is not approved", id));
+    }
+    insert_audit_entry(tx, actor, action, Some(id), transaction_id, reason)
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl),
+            [],
+        )?;
+    }
+    Ok(())
+}
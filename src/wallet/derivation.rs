@@ -0,0 +1,96 @@
+use alloy::{
+    primitives::Address,
+    signers::local::coins_bip39::{English, Mnemonic},
+};
+use anyhow::{anyhow, Result};
+use coins_bip32::{
+    enc::{MainnetEncoder, XKeyEncoder},
+    path::DerivationPath,
+    primitives::{Hint, XKeyInfo},
+    xkeys::{Parent, XPriv, XPub},
+    BIP32_HARDEN,
+};
+use zeroize::Zeroizing;
+
+/// Derives receiving addresses below a fixed parent path such as
+/// `m/44'/60'/0'/0/`.
+///
+/// Only the parent's extended public key is kept, so deriving addresses
+/// needs no private key material and a single child derivation per index.
+#[derive(Clone)]
+pub struct AddressDeriver {
+    parent: XPub,
+}
+
+impl AddressDeriver {
+    pub fn new(
+        mnemonic: &Mnemonic<English>,
+        passphrase: Option<&str>,
+        path_prefix: &str,
+    ) -> Result<Self> {
+        let parent = master_key(mnemonic, passphrase)?
+            .derive_path(path_prefix.trim_end_matches('/'))?
+            .verify_key();
+        Ok(Self { parent })
+    }
+
+    pub(crate) fn from_parent(parent: XPub) -> Self {
+        Self { parent }
+    }
+
+    /// Builds a watch-only deriver from the account-level xpub, i.e. the key
+    /// at `path_prefix` without its last component (`m/44'/60'/0'` for
+    /// `m/44'/60'/0'/0/`). The last component must not be hardened.
+    pub fn from_account_xpub(xpub: &str, path_prefix: &str) -> Result<Self> {
+        let path: DerivationPath = path_prefix.trim_end_matches('/').parse()?;
+        let change = *path
+            .last()
+            .ok_or_else(|| anyhow!("Derivation path {} is empty", path_prefix))?;
+        if change >= BIP32_HARDEN {
+            return Err(anyhow!(
+                "Derivation path {} ends in a hardened index and cannot be derived from an xpub",
+                path_prefix
+            ));
+        }
+
+        let account = MainnetEncoder::xpub_from_base58(xpub)?;
+        let depth = AsRef::<XKeyInfo>::as_ref(&account).depth as usize;
+        if depth != path.len() - 1 {
+            return Err(anyhow!(
+                "xpub has depth {}, expected an account-level key at depth {}",
+                depth,
+                path.len() - 1
+            ));
+        }
+
+        let parent = account.derive_child(change)?;
+        Ok(Self { parent })
+    }
+
+    pub fn derive(&self, index: u32) -> Result<Address> {
+        let child = self.parent.derive_child(index)?;
+        Ok(Address::from_public_key(child.as_ref()))
+    }
+}
+
+/// Returns the account-level xpub for `path_prefix`, suitable for
+/// [`AddressDeriver::from_account_xpub`].
+pub fn account_xpub(
+    mnemonic: &Mnemonic<English>,
+    passphrase: Option<&str>,
+    path_prefix: &str,
+) -> Result<String> {
+    let path: DerivationPath = path_prefix.trim_end_matches('/').parse()?;
+    let account_path = path.resized(path.len().saturating_sub(1), 0);
+    let account = master_key(mnemonic, passphrase)?
+        .derive_path(account_path)?
+        .verify_key();
+    Ok(MainnetEncoder::xpub_to_base58(&account)?)
+}
+
+/// BIP-32 root key of `mnemonic` and its optional BIP-39 `passphrase`. Uses
+/// the legacy hint so keys encode as `xpub`, as Ethereum tooling expects.
+pub(crate) fn master_key(mnemonic: &Mnemonic<English>, passphrase: Option<&str>) -> Result<XPriv> {
+    let seed = Zeroizing::new(mnemonic.to_seed(passphrase)?);
+    Ok(XPriv::root_from_seed(seed.as_slice(), Some(Hint::Legacy))?)
+}
@@ -0,0 +1,192 @@
+//! Finds the addresses a seed has issued by looking for on-chain activity,
+//! for wallets restored onto a fresh database.
+//!
+//! Addresses are issued sequentially, so the scan derives them in order and
+//! stops once `gap_limit` consecutive addresses show no activity. Every
+//! address up to the last used one is stored, used or not.
+
+use std::{collections::HashSet, future::Future, sync::Mutex};
+
+use alloy::{
+    primitives::Address,
+    providers::{Provider, RootProvider},
+    pubsub::PubSubFrontend,
+};
+use anyhow::Result;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+
+use crate::{config, error::Error};
+
+use super::{
+    accounts::Account,
+    database::{WalletDatabase, FIRST_ADDRESS_INDEX},
+    derivation::AddressDeriver,
+    restore::{self, MAX_RESCAN_COUNT},
+    usdt::contract,
+};
+
+/// Upper bound on the gap limit, to bound the RPC calls of one scan.
+pub const MAX_GAP_LIMIT: u32 = 1000;
+
+/// Addresses checked at the same time. Nodes rate-limit bursts of calls.
+pub const MAX_CONCURRENT_PROBES: usize = 4;
+
+/// Tells which addresses have ever been used.
+pub trait ActivityProbe {
+    /// Whether each of `addresses` was used, in order.
+    fn used(&self, addresses: &[Address]) -> impl Future<Output = Result<Vec<bool>>> + Send;
+}
+
+/// Checks the chain: an address is used if it sent a transaction, holds
+/// ETH, or received USDT since `from_block`. Transfers to a whole batch of
+/// addresses are searched together, in ranges of `DISCOVERY_LOG_RANGE`
+/// blocks up to the head at the start, as nodes refuse larger queries.
+pub struct ChainProbe {
+    provider: RootProvider<PubSubFrontend>,
+    from_block: u64,
+    head: u64,
+    log_range: u64,
+}
+
+impl ChainProbe {
+    /// Searches transfers from `from_block`, or else from the block the
+    /// mainnet USDT contract was deployed in.
+    pub async fn new(
+        provider: RootProvider<PubSubFrontend>,
+        from_block: Option<u64>,
+    ) -> Result<Self> {
+        let head = provider.get_block_number().await?;
+        Ok(Self {
+            provider,
+            from_block: from_block.unwrap_or(contract::USDT_DEPLOYMENT_BLOCK),
+            head,
+            log_range: config::discovery_log_range(),
+        })
+    }
+
+    async fn has_history(&self, address: Address) -> Result<bool> {
+        Ok(self.provider.get_transaction_count(address).await? > 0
+            || !self.provider.get_balance(address).await?.is_zero())
+    }
+}
+
+impl ActivityProbe for ChainProbe {
+    async fn used(&self, addresses: &[Address]) -> Result<Vec<bool>> {
+        let used: Vec<bool> = stream::iter(addresses.iter().copied())
+            .map(|address| self.has_history(address))
+            .buffered(MAX_CONCURRENT_PROBES)
+            .try_collect()
+            .await?;
+
+        let mut unused: Vec<Address> = addresses
+            .iter()
+            .zip(&used)
+            .filter(|(_, used)| !**used)
+            .map(|(address, _)| *address)
+            .collect();
+        for (from, to) in block_ranges(self.from_block, self.head, self.log_range) {
+            if unused.is_empty() {
+                break;
+            }
+            let logs = contract::get_receive_logs_of(&self.provider, from, to, &unused).await?;
+            let receivers: HashSet<Address> =
+                logs.iter().filter_map(|log| log.to.parse().ok()).collect();
+            unused.retain(|address| !receivers.contains(address));
+        }
+        Ok(addresses
+            .iter()
+            .map(|address| !unused.contains(address))
+            .collect())
+    }
+}
+
+/// Splits the blocks `from..=to` into inclusive ranges of at most `size`
+/// blocks, in order.
+pub fn block_ranges(from: u64, to: u64, size: u64) -> impl Iterator<Item = (u64, u64)> {
+    let size = size.max(1);
+    (from..=to)
+        .step_by(size as usize)
+        .map(move |start| (start, start.saturating_add(size - 1).min(to)))
+}
+
+/// Outcome of discovering one account.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveryReport {
+    pub account: String,
+    /// Number of addresses checked on chain.
+    pub scanned: u32,
+    /// Highest index with activity or already in the database.
+    pub last_used: Option<u32>,
+    /// Number of addresses added to the database.
+    pub added: u32,
+}
+
+/// Scans `account` until `gap_limit` consecutive unused addresses are found
+/// and stores every address up to the last used one. Indexes already in
+/// the database count as used.
+pub async fn discover<P: ActivityProbe>(
+    db: &Mutex<WalletDatabase>,
+    deriver: &AddressDeriver,
+    account: &Account,
+    gap_limit: u32,
+    probe: &P,
+) -> Result<DiscoveryReport> {
+    if gap_limit == 0 || gap_limit > MAX_GAP_LIMIT {
+        return Err(Error::InvalidInput(format!(
+            "Gap limit must be between 1 and {}",
+            MAX_GAP_LIMIT
+        ))
+        .into());
+    }
+
+    let mut last_used = db.lock().unwrap().get_max_index_for_path(&account.path)?;
+    let mut scanned = 0;
+    let mut next = last_used.map_or(FIRST_ADDRESS_INDEX, |index| index + 1);
+    let end = FIRST_ADDRESS_INDEX + MAX_RESCAN_COUNT;
+
+    // The rest of the current gap is checked as one batch. Activity in it
+    // extends the gap, so check again from there.
+    loop {
+        let gap_end = last_used
+            .map_or(FIRST_ADDRESS_INDEX, |index| index + 1)
+            .saturating_add(gap_limit)
+            .min(end);
+        if next >= gap_end {
+            break;
+        }
+        let batch = (next..gap_end)
+            .map(|index| Ok((index, deriver.derive(index)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let addresses: Vec<Address> = batch.iter().map(|(_, address)| *address).collect();
+        let used = probe.used(&addresses).await?;
+        scanned += batch.len() as u32;
+
+        for ((index, address), used) in batch.iter().zip(used) {
+            if used {
+                println!(
+                    "Found activity on {} at {}",
+                    address,
+                    account.derivation_path(*index)
+                );
+                last_used = last_used.max(Some(*index));
+            }
+        }
+        next = gap_end;
+    }
+
+    let added = match last_used {
+        Some(last) => {
+            let db_lock = db.lock().unwrap();
+            restore::rebuild_addresses(&db_lock, deriver, account, last - FIRST_ADDRESS_INDEX + 1)?
+        }
+        None => 0,
+    };
+
+    Ok(DiscoveryReport {
+        account: account.name.clone(),
+        scanned,
+        last_used,
+        added,
+    })
+}
@@ -1,55 +1,270 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+};
 
 use alloy::{
-    providers::RootProvider,
+    eips::eip2718::Encodable2718,
+    primitives::{Address, Bytes, B256, U256},
+    providers::{Provider, RootProvider},
     pubsub::PubSubFrontend,
     signers::local::{
         coins_bip39::{English, Mnemonic},
-        MnemonicBuilder,
+        PrivateKeySigner,
     },
 };
-use anyhow::Result;
+use alloy_dyn_abi::TypedData;
+use anyhow::{anyhow, Result};
 use tokio::sync::oneshot;
 
-use crate::{config, pubsub::ChainEvent, Publisher};
+use crate::{config, error::Error, pubsub::ChainEvent, Publisher};
 
 use super::{
-    database::WalletDatabase, mnemonic::MnemonicStorage, paths::WalletPaths, usdt::contract,
+    accounts::{self, Account, DEFAULT_ACCOUNT},
+    blacklist::{self, BlacklistChange, BlacklistSource, FrozenAddress},
+    database::{AddressRecord, WalletDatabase},
+    derivation::{account_xpub, AddressDeriver},
+    discovery::{self, ChainProbe, DiscoveryReport},
+    fees::{self, FeeCaps, FeeEstimate, Urgency},
+    keys::ProtectedKey,
+    mnemonic::{LoadError, MnemonicStorage, PassphraseStorage},
+    nonces::{self, NonceState},
+    offline::{self, UnsignedTransaction, UsdtTransfer, USDT_TRANSFER_GAS_LIMIT},
+    paths::{WalletLock, WalletPaths},
+    policy::{
+        self, AuditAction, AuditEntry, NewWithdrawal, WithdrawalPolicy, WithdrawalRequest,
+        WithdrawalStatus,
+    },
+    restore,
+    signing::{self, SignedMessage},
+    tracker::{self, TrackedTransaction, TxChain, TxStatus, CANCEL_GAS_LIMIT},
+    usdt::contract,
 };
 
 pub struct EthServWallet {
-    mnemonic: Mnemonic<English>,
+    /// Keys by account name. Empty while locked. The mnemonic itself is
+    /// never kept.
+    keys: RwLock<HashMap<String, AccountKeys>>,
+    accounts: Vec<Account>,
+    policy: WithdrawalPolicy,
+    watch_only: bool,
+    mnemonic_path: PathBuf,
+    passphrase_path: PathBuf,
     db: Arc<Mutex<WalletDatabase>>,
     pub provider: RootProvider<PubSubFrontend>,
     is_syncing: bool,
     stop_sync_tx: Arc<Mutex<Option<oneshot::Sender<()>>>>,
     publisher: Arc<Mutex<Publisher>>,
+    _lock: WalletLock,
 }
 
-const DERIVATION_PATH: &str = "m/44'/60'/0'/0/";
+/// Keys of one derivation account.
+struct AccountKeys {
+    deriver: AddressDeriver,
+    /// Private key at the account's path. `None` in watch-only mode.
+    key: Option<ProtectedKey>,
+}
 
 impl EthServWallet {
-    pub fn new(password: &str, provider: RootProvider<PubSubFrontend>) -> Result<Self> {
-        let paths = WalletPaths::from_password(password);
-        let mnemonic_storage = MnemonicStorage::new(paths.mnemonic_path);
+    pub fn new(
+        password: &str,
+        passphrase: Option<&str>,
+        provider: RootProvider<PubSubFrontend>,
+    ) -> Result<Self> {
+        let wallet = Self::open(WalletPaths::from_config(password)?, None, provider)?;
+        wallet.unlock(password, passphrase)?;
+        Ok(wallet)
+    }
+
+    /// Opens the wallet named by `WALLET_ID` without its password. Deposits
+    /// are watched, but nothing that needs keys works until [`Self::unlock`].
+    pub fn new_locked(provider: RootProvider<PubSubFrontend>) -> Result<Self> {
+        if config::wallet_id().is_none() {
+            return Err(anyhow!(
+                "WALLET_ID must be set to start without a password, wallet file names depend on the password otherwise"
+            ));
+        }
+        let paths = WalletPaths::from_config("")?;
+        if !paths.mnemonic_path.exists() {
+            return Err(anyhow!(
+                "No wallet found at {}, run `ethserv init` first",
+                paths.mnemonic_path.display()
+            ));
+        }
+        Self::open(paths, None, provider)
+    }
+
+    /// Opens a wallet that derives addresses from an account-level xpub and
+    /// holds no private key material. Only the default account is available.
+    pub fn new_watch_only(xpub: &str, provider: RootProvider<PubSubFrontend>) -> Result<Self> {
+        let paths = WalletPaths::from_config(xpub)?;
+        let deriver = AddressDeriver::from_account_xpub(xpub, config::derivation_path())?;
+
+        Self::open(paths, Some(deriver), provider)
+    }
 
-        let mnemonic = mnemonic_storage.load_or_create_by_password(password);
+    fn open(
+        paths: WalletPaths,
+        watch_only_deriver: Option<AddressDeriver>,
+        provider: RootProvider<PubSubFrontend>,
+    ) -> Result<Self> {
+        let lock = paths.lock()?;
         let db = WalletDatabase::new(paths.wallet_path)?;
+        let accounts = accounts::from_config()?;
+        accounts::check_accounts(&db, &accounts)?;
+        let policy = WithdrawalPolicy::from_config()?;
+        // Read again by every request, but a typo should stop the service now
+        policy::operators_from_config()?;
+        let watch_only = watch_only_deriver.is_some();
+        let mut keys = HashMap::new();
+        if let Some(deriver) = watch_only_deriver {
+            restore::check_fingerprint(&db, &deriver, config::derivation_path())?;
+            keys.insert(
+                DEFAULT_ACCOUNT.to_string(),
+                AccountKeys { deriver, key: None },
+            );
+        }
 
         let publisher_bind_address = config::publisher_bind_address();
 
         let publisher = Arc::new(Mutex::new(Publisher::new(publisher_bind_address).unwrap()));
 
         Ok(Self {
-            mnemonic,
+            watch_only,
+            keys: RwLock::new(keys),
+            accounts,
+            policy,
+            mnemonic_path: paths.mnemonic_path,
+            passphrase_path: paths.passphrase_path,
             db: Arc::new(Mutex::new(db)),
             provider,
             is_syncing: false,
             stop_sync_tx: Arc::new(Mutex::new(None)),
             publisher,
+            _lock: lock,
         })
     }
 
+    /// Decrypts the mnemonic with `password` and makes the keys of its seed
+    /// with the stored BIP-39 passphrase, or `passphrase` if none is stored,
+    /// available.
+    pub fn unlock(&self, password: &str, passphrase: Option<&str>) -> Result<()> {
+        if self.watch_only {
+            return Err(
+                Error::Conflict(String::from("A watch-only wallet cannot be unlocked")).into(),
+            );
+        }
+        if !self.is_locked() {
+            return Err(Error::Conflict(String::from("Wallet is already unlocked")).into());
+        }
+
+        let storage = MnemonicStorage::new(self.mnemonic_path.clone());
+        let account_keys = {
+            let mnemonic = load_mnemonic(&storage, password)?;
+            let passphrase = PassphraseStorage::new(self.passphrase_path.clone())
+                .load_or(password, passphrase)?;
+            self.accounts
+                .iter()
+                .map(|account| {
+                    ProtectedKey::account(
+                        &mnemonic,
+                        passphrase.as_deref().map(String::as_str),
+                        &account.path,
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+        if storage.needs_migration()? {
+            println!("Mnemonic file uses an outdated format, run `ethserv migrate-mnemonic`");
+        }
+        let keys = self.account_keys(account_keys)?;
+        restore::check_fingerprint(
+            &self.db.lock().unwrap(),
+            &keys[DEFAULT_ACCOUNT].deriver,
+            config::derivation_path(),
+        )?;
+
+        self.set_keys(keys);
+        println!("Wallet unlocked");
+        Ok(())
+    }
+
+    /// Wipes the wallet's keys from memory. Deposits keep being watched;
+    /// issuing addresses fails until the wallet is unlocked again.
+    pub fn lock(&self) -> Result<()> {
+        if self.watch_only {
+            return Err(
+                Error::Conflict(String::from("A watch-only wallet cannot be locked")).into(),
+            );
+        }
+        // The write lock waits for in-flight derivations to finish
+        self.keys.write().unwrap().clear();
+        println!("Wallet locked");
+        Ok(())
+    }
+
+    /// `true` until the wallet has been unlocked. Watch-only wallets are
+    /// never locked.
+    pub fn is_locked(&self) -> bool {
+        self.keys.read().unwrap().is_empty()
+    }
+
+    /// Pairs `keys`, given in the order of [`Self::accounts`], with their
+    /// derivers.
+    fn account_keys(&self, keys: Vec<ProtectedKey>) -> Result<HashMap<String, AccountKeys>> {
+        self.accounts
+            .iter()
+            .zip(keys)
+            .map(|(account, key)| {
+                let deriver = key.deriver()?;
+                Ok((
+                    account.name.clone(),
+                    AccountKeys {
+                        deriver,
+                        key: Some(key),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    fn set_keys(&self, keys: HashMap<String, AccountKeys>) {
+        *self.keys.write().unwrap() = keys;
+    }
+
+    /// The configured derivation accounts, the default one first.
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    fn account(&self, name: Option<&str>) -> Result<&Account> {
+        let name = name.unwrap_or(DEFAULT_ACCOUNT);
+        self.accounts
+            .iter()
+            .find(|account| account.name == name)
+            .ok_or_else(|| Error::NotFound(format!("Unknown account {}", name)).into())
+    }
+
+    /// Loads the mnemonic for `password` and returns the account-level xpub
+    /// to configure a watch-only instance with.
+    pub fn export_account_xpub(password: &str, passphrase: Option<&str>) -> Result<String> {
+        let paths = WalletPaths::from_config(password)?;
+        let mnemonic = MnemonicStorage::new(paths.mnemonic_path).load_mnemonic(password)?;
+        let passphrase =
+            PassphraseStorage::new(paths.passphrase_path).load_or(password, passphrase)?;
+        account_xpub(
+            &mnemonic,
+            passphrase.as_deref().map(String::as_str),
+            config::derivation_path(),
+        )
+    }
+
+    /// `true` if the wallet was opened from an xpub and cannot sign.
+    pub fn is_watch_only(&self) -> bool {
+        self.watch_only
+    }
+
     pub fn start_sync(&mut self) {
         if self.is_syncing {
             println!("Sync already in progress");
@@ -76,8 +291,38 @@ impl EthServWallet {
 
                 *stop_sync_tx.lock().unwrap() = Some(a);
 
+                match blacklist::subscribe(&provider).await {
+                    Ok(mut changes) => {
+                        let db = db.clone();
+                        let publisher = publisher.clone();
+                        tokio::spawn(async move {
+                            while let Some(change) = changes.recv().await {
+                                let published =
+                                    blacklist::apply(&db, &[change]).and_then(|applied| {
+                                        publish_blacklist_changes(&publisher, &applied)
+                                    });
+                                if let Err(e) = published {
+                                    println!("Failed to apply blacklist event: {:?}", e);
+                                }
+                            }
+                        });
+                    }
+                    Err(e) => println!("Failed to subscribe to blacklist events: {:?}", e),
+                }
+
                 while let Some(transfer) = b.recv().await {
                     println!("Transfer: {:?}", transfer);
+                    let sender_blacklisted = check_blacklist(&provider, &transfer.from).await;
+                    let recipient_blacklisted = check_blacklist(&provider, &transfer.to).await;
+                    let record = db.lock().unwrap().get_address_record(&transfer.to);
+                    let (customer_id, label) = match record {
+                        Ok(Some(record)) => (record.customer_id, record.label),
+                        Ok(None) => (None, None),
+                        Err(e) => {
+                            println!("Failed to look up address {}: {:?}", transfer.to, e);
+                            (None, None)
+                        }
+                    };
                     let chain_event = ChainEvent::NewDeposit {
                         deposit: (
                             transfer.to,
@@ -86,6 +331,10 @@ impl EthServWallet {
                             transfer.hash,
                             transfer.index,
                         ),
+                        customer_id,
+                        label,
+                        sender_blacklisted,
+                        recipient_blacklisted,
                     };
 
                     publisher.lock().unwrap().publish(chain_event).unwrap();
@@ -101,36 +350,831 @@ impl EthServWallet {
         self.is_syncing = false;
     }
 
-    pub fn reveal_next_address(&self) -> Result<String> {
-        let derivation_path = config::derivation_path();
+    /// Issues the next address of `account`, or of the default account.
+    pub fn reveal_next_address(&self, account: Option<&str>) -> Result<String> {
+        let mut records = self.reveal_addresses(1, account, None, None)?;
+        Ok(records.remove(0).address)
+    }
+
+    /// Derives `count` consecutive addresses of `account`, or of the default
+    /// account, and stores them, tagged with `customer_id` and `label`, in a
+    /// single database transaction.
+    pub fn reveal_addresses(
+        &self,
+        count: u32,
+        account: Option<&str>,
+        customer_id: Option<&str>,
+        label: Option<&str>,
+    ) -> Result<Vec<AddressRecord>> {
+        let account = self.account(account)?;
         let db_lock = self.db.lock().unwrap();
-        let last_index = db_lock
-            .get_max_index_for_path(derivation_path)?
-            .unwrap_or(0);
+        self.derive_and_store(&db_lock, account, count, customer_id, label, None)
+    }
+
+    /// Returns the address previously issued for `idempotency_key`, or
+    /// derives and stores a new one. The flag is `true` if a new address was
+    /// created.
+    ///
+    /// Reusing a key with a different account, `customer_id` or `label` is a
+    /// conflict.
+    pub fn reveal_address_idempotent(
+        &self,
+        idempotency_key: &str,
+        account: Option<&str>,
+        customer_id: Option<&str>,
+        label: Option<&str>,
+    ) -> Result<(AddressRecord, bool)> {
+        let account = self.account(account)?;
+        let db_lock = self.db.lock().unwrap();
+        if let Some(record) = db_lock.get_address_by_idempotency_key(idempotency_key)? {
+            if record.account != account.name
+                || record.customer_id.as_deref() != customer_id
+                || record.label.as_deref() != label
+            {
+                return Err(Error::Conflict(String::from(
+                    "Idempotency key was already used with different parameters",
+                ))
+                .into());
+            }
+            return Ok((record, false));
+        }
+
+        let mut records = self.derive_and_store(
+            &db_lock,
+            account,
+            1,
+            customer_id,
+            label,
+            Some(idempotency_key),
+        )?;
+        Ok((records.remove(0), true))
+    }
+
+    fn derive_and_store(
+        &self,
+        db_lock: &WalletDatabase,
+        account: &Account,
+        count: u32,
+        customer_id: Option<&str>,
+        label: Option<&str>,
+        idempotency_key: Option<&str>,
+    ) -> Result<Vec<AddressRecord>> {
+        let keys = self.keys.read().unwrap();
+        if keys.is_empty() {
+            return Err(Error::Locked(String::from("Wallet is locked")).into());
+        }
+        let deriver = &keys
+            .get(&account.name)
+            .ok_or_else(|| {
+                Error::Conflict(format!(
+                    "Account {} is not available in watch-only mode",
+                    account.name
+                ))
+            })?
+            .deriver;
+        let result = db_lock.allocate_addresses(&account.path, count, |index| {
+            println!("Derivation path: {}", account.derivation_path(index));
+            Ok(AddressRecord {
+                address: deriver.derive(index)?.to_string(),
+                account: account.name.clone(),
+                path: account.path.clone(),
+                addr_index: index,
+                customer_id: customer_id.map(String::from),
+                label: label.map(String::from),
+                idempotency_key: idempotency_key.map(String::from),
+            })
+        });
+
+        match result {
+            Ok(records) => Ok(records),
+            Err(e) if is_constraint_violation(&e) => {
+                Err(Error::Conflict(String::from("Address already exists")).into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Looks for on-chain activity on the addresses of `account`, or of every
+    /// account available, and stores those found, see [`discovery`].
+    pub async fn discover_addresses(
+        &self,
+        account: Option<&str>,
+        gap_limit: u32,
+        from_block: Option<u64>,
+    ) -> Result<Vec<DiscoveryReport>> {
+        let targets = match account {
+            Some(name) => vec![self.account(Some(name))?],
+            None => self.accounts.iter().collect(),
+        };
+        let derivers = {
+            let keys = self.keys.read().unwrap();
+            if keys.is_empty() {
+                return Err(Error::Locked(String::from("Wallet is locked")).into());
+            }
+            let mut derivers = Vec::new();
+            for target in targets {
+                match keys.get(&target.name) {
+                    Some(keys) => derivers.push((target, keys.deriver.clone())),
+                    None if account.is_none() => {}
+                    None => {
+                        return Err(Error::Conflict(format!(
+                            "Account {} is not available in watch-only mode",
+                            target.name
+                        ))
+                        .into())
+                    }
+                }
+            }
+            derivers
+        };
+
+        let probe = ChainProbe::new(self.provider.clone(), from_block)
+            .await
+            .map_err(Error::rpc)?;
+        let mut reports = Vec::new();
+        for (target, deriver) in derivers {
+            let report = discovery::discover(&self.db, &deriver, target, gap_limit, &probe).await?;
+            println!(
+                "Discovered account {}: scanned {}, added {} addresses",
+                report.account, report.scanned, report.added
+            );
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    /// Stores `phrase` and `passphrase` encrypted with `password` as the
+    /// wallet's seed and re-derives the first `rescan_count` addresses.
+    /// Refused if a mnemonic file exists, as by the `import-mnemonic`
+    /// command, or if the database belongs to another seed. Unlocks the
+    /// wallet.
+    pub fn import_mnemonic(
+        &self,
+        password: &str,
+        phrase: &str,
+        passphrase: Option<&str>,
+        rescan_count: u32,
+    ) -> Result<u32> {
+        if self.watch_only {
+            return Err(Error::Conflict(String::from(
+                "Cannot import a mnemonic into a watch-only wallet",
+            ))
+            .into());
+        }
+
+        let storage = MnemonicStorage::new(self.mnemonic_path.clone());
+        if storage.exists() {
+            return Err(Error::Conflict(String::from(
+                "A wallet already exists for this password, refusing to overwrite it",
+            ))
+            .into());
+        }
+
+        let db_lock = self.db.lock().unwrap();
+        let (account_keys, restored) = restore::import_mnemonic(
+            &storage,
+            &db_lock,
+            password,
+            phrase,
+            passphrase,
+            &self.accounts,
+            rescan_count,
+        )?;
+        PassphraseStorage::new(self.passphrase_path.clone()).set(passphrase, password)?;
+        self.set_keys(self.account_keys(account_keys)?);
+
+        println!("Imported mnemonic, restored {} addresses", restored);
+        Ok(restored)
+    }
 
-        println!("Last index: {}", last_index);
+    /// Prepares an unsigned USDT transfer from one of our stored addresses,
+    /// to be signed by the offline signer. The nonce is reserved for it.
+    pub async fn prepare_usdt_transfer(
+        &self,
+        transfer: UsdtTransfer,
+    ) -> Result<UnsignedTransaction> {
+        let chain_id = self.provider.get_chain_id().await.map_err(Error::rpc)?;
+        offline::prepare_usdt_transfer(
+            &self.db,
+            &self.provider,
+            chain_id,
+            config::usdt_contract_address(),
+            transfer,
+        )
+        .await
+    }
+
+    /// Estimates fees for a transaction sent now with `urgency`, or the
+    /// configured urgency, within the configured caps.
+    pub async fn estimate_fees(&self, urgency: Option<Urgency>) -> Result<FeeEstimate> {
+        let urgency = match urgency {
+            Some(urgency) => urgency,
+            None => Urgency::from_config()?,
+        };
+        fees::estimate(&self.provider, urgency, FeeCaps::from_config()).await
+    }
+
+    /// Hands out the next nonce for a transaction from the stored address
+    /// `from`, see [`nonces`]. Report it with [`Self::mark_nonce_sent`] once
+    /// broadcast, or give it back with [`Self::release_nonce`].
+    pub async fn reserve_nonce(&self, from: Address) -> Result<u64> {
+        self.stored_address(from)?;
+        nonces::reserve(&self.db, &self.provider, from).await
+    }
 
-        let new_index = last_index + 1;
+    pub fn mark_nonce_sent(&self, from: Address, nonce: u64, tx_hash: B256) -> Result<()> {
+        self.db
+            .lock()
+            .unwrap()
+            .mark_nonce_sent(&from.to_string(), nonce, &tx_hash.to_string())
+    }
 
-        let derivation_path = format!("{}{}", derivation_path, new_index);
+    /// Gives back a nonce whose transaction was not sent, so the next
+    /// transaction takes it.
+    pub fn release_nonce(&self, from: Address, nonce: u64) -> Result<()> {
+        println!("Releasing nonce {} of {}", nonce, from);
+        self.db
+            .lock()
+            .unwrap()
+            .release_nonce(&from.to_string(), nonce)
+    }
 
-        println!("Derivation path: {}", derivation_path);
+    pub fn nonce_state(&self, address: Address) -> Result<NonceState> {
+        self.db
+            .lock()
+            .unwrap()
+            .get_nonce_state(&address.to_string())?
+            .ok_or_else(|| {
+                Error::NotFound(format!("No nonces were handed out for {}", address)).into()
+            })
+    }
 
-        let wallet = MnemonicBuilder::<English>::default()
-            .phrase(self.mnemonic.to_phrase())
-            .derivation_path(derivation_path)?
-            .build()?;
+    /// Reconciles the nonces of `address`, or of every address nonces were
+    /// handed out for, with the node's pending nonce. Dropped transactions
+    /// leave gaps that the next reservations fill.
+    pub async fn reconcile_nonces(&self, address: Option<Address>) -> Result<Vec<NonceState>> {
+        let addresses = match address {
+            Some(address) => vec![address],
+            None => self
+                .db
+                .lock()
+                .unwrap()
+                .get_nonce_addresses()?
+                .iter()
+                .map(|address| address.parse())
+                .collect::<Result<Vec<_>, _>>()?,
+        };
 
-        let address = wallet.address().to_string();
-        match db_lock.store_address(&address, DERIVATION_PATH, new_index) {
-            Ok(true) => Ok(address),
-            Ok(false) => Err(anyhow::anyhow!("Address already exists")),
-            Err(e) => Err(anyhow::anyhow!(e)),
+        let mut states = Vec::new();
+        for address in addresses {
+            let state = nonces::reconcile(
+                &self.db,
+                &self.provider,
+                address,
+                config::nonce_reservation_timeout(),
+            )
+            .await?;
+            println!(
+                "Reconciled nonces of {}: next {}, gaps {:?}",
+                address,
+                state.next_nonce,
+                state.gaps()
+            );
+            states.push(state);
         }
+        Ok(states)
+    }
+
+    /// Sends `amount` USDT base units from the stored address `from` to `to`
+    /// and tracks the transaction, see [`tracker`]. Signs with the wallet's
+    /// own key, so the wallet must be unlocked. Fails only if the node
+    /// rejected the transaction, a broadcast that may have reached it
+    /// returns the transaction as pending.
+    pub async fn send_usdt(
+        &self,
+        from: Address,
+        to: Address,
+        amount: U256,
+        urgency: Option<Urgency>,
+    ) -> Result<TrackedTransaction> {
+        self.transfer_usdt(from, to, amount, urgency, None).await
+    }
+
+    /// [`Self::send_usdt`], for the approved `withdrawal` if given. Its
+    /// request is settled on behalf of the actor when the transaction is
+    /// recorded, so it cannot be sent twice.
+    async fn transfer_usdt(
+        &self,
+        from: Address,
+        to: Address,
+        amount: U256,
+        urgency: Option<Urgency>,
+        withdrawal: Option<(&WithdrawalRequest, &str)>,
+    ) -> Result<TrackedTransaction> {
+        let signer = self.address_signer(from)?;
+        let fees = self.estimate_fees(urgency).await?;
+        let tx = self
+            .prepare_usdt_transfer(UsdtTransfer {
+                from,
+                to,
+                amount,
+                gas_limit: USDT_TRANSFER_GAS_LIMIT,
+                gas: fees.gas,
+            })
+            .await?;
+        let envelope = match tx.sign(&signer) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                self.release_nonce(from, tx.nonce)?;
+                return Err(e);
+            }
+        };
+        let tx_hash = envelope.tx_hash().to_string();
+        let raw = Bytes::from(envelope.encoded_2718());
+
+        // Recorded first, so a crash after the broadcast cannot lose it
+        let recorded = {
+            let db_lock = self.db.lock().unwrap();
+            match withdrawal {
+                Some((request, actor)) => db_lock.insert_withdrawal_transaction(
+                    request,
+                    actor,
+                    &tx,
+                    &tx_hash,
+                    &raw.to_string(),
+                ),
+                None => db_lock.insert_transaction(
+                    &tx,
+                    &tx_hash,
+                    &raw.to_string(),
+                    Some(&to.to_string()),
+                    Some(&amount.to_string()),
+                ),
+            }
+        };
+        let id = match recorded {
+            Ok(id) => id,
+            Err(e) => {
+                self.release_nonce(from, tx.nonce)?;
+                return Err(e);
+            }
+        };
+        if let Err(e) = self.provider.send_raw(&raw).await {
+            if tracker::is_rejected(&e) {
+                self.db.lock().unwrap().set_transaction_status(
+                    id,
+                    TxStatus::Dropped,
+                    &tx_hash,
+                    None,
+                )?;
+                if tracker::is_nonce_used(&e) {
+                    // The chain is ahead of the nonce manager, which must
+                    // catch up rather than hand the nonce out again
+                    if let Err(e) = self.reconcile_nonces(Some(from)).await {
+                        println!("Failed to reconcile the nonces of {}: {:?}", from, e);
+                    }
+                } else {
+                    self.release_nonce(from, tx.nonce)?;
+                }
+                return Err(Error::rpc(e).into());
+            }
+            // The node may have it, so it stays pending and holds its nonce
+            // until the tracker finds it or gives up broadcasting it again
+            println!("Broadcast of {} failed: {:?}", tx_hash, e);
+        }
+        println!(
+            "Sent {} USDT units from {} to {}: {}",
+            amount, from, to, tx_hash
+        );
+
+        // The transfer may be on its way, so nothing from here on fails the
+        // call: a caller retrying it would send the amount twice
+        let recorded = {
+            let db_lock = self.db.lock().unwrap();
+            db_lock
+                .record_broadcast(id)
+                .and_then(|()| db_lock.mark_nonce_sent(&from.to_string(), tx.nonce, &tx_hash))
+        };
+        if let Err(e) = recorded {
+            println!("Failed to record the broadcast of {}: {:?}", tx_hash, e);
+        }
+        let tracked = TrackedTransaction {
+            id,
+            tx,
+            tx_hash,
+            recipient: Some(to.to_string()),
+            amount: Some(amount.to_string()),
+            status: TxStatus::Pending,
+            block_number: None,
+            broadcasts: 1,
+            cancel_requested: false,
+        };
+        self.publish_after_broadcast(tracked.event());
+        Ok(tracked)
+    }
+
+    /// Requests `withdrawal` and sends it right away if the policy approves
+    /// it, see [`policy`]. Returns the request and, if sent, its tracked
+    /// transaction.
+    pub async fn request_withdrawal(
+        &self,
+        withdrawal: NewWithdrawal,
+    ) -> Result<(WithdrawalRequest, Option<TrackedTransaction>)> {
+        // Fails early for addresses that are not ours or a locked wallet
+        self.address_signer(withdrawal.from)?;
+        if let Err(e) = blacklist::screen(&self.provider, withdrawal.from, withdrawal.to).await {
+            return match Error::from(e) {
+                Error::PolicyViolation(reason) => Err(policy::deny(&self.db, &withdrawal, reason)),
+                e => Err(e.into()),
+            };
+        }
+        let request = policy::request(&self.db, &self.policy, &withdrawal)?;
+        self.settle_withdrawal(request, &withdrawal.requested_by)
+            .await
+    }
+
+    /// Approves request `id` on behalf of `approver`, and sends it if this
+    /// was the last approval it needed.
+    pub async fn approve_withdrawal(
+        &self,
+        id: i64,
+        approver: &str,
+        comment: Option<&str>,
+    ) -> Result<(WithdrawalRequest, Option<TrackedTransaction>)> {
+        let request = self.withdrawal_request(id)?;
+        self.address_signer(request.from.parse()?)?;
+        let request = policy::approve(&self.db, &self.policy, id, approver, comment)?;
+        self.settle_withdrawal(request, approver).await
+    }
+
+    /// Sends the approved request `id` on behalf of `operator`, e.g. after
+    /// screening it failed on an unreachable node.
+    pub async fn send_withdrawal(
+        &self,
+        id: i64,
+        operator: &str,
+    ) -> Result<(WithdrawalRequest, Option<TrackedTransaction>)> {
+        let request = self.withdrawal_request(id)?;
+        if request.status != WithdrawalStatus::Approved {
+            return Err(Error::Conflict(format!(
+                "Withdrawal request {} is {}, only approved requests can be sent",
+                id,
+                request.status.as_str()
+            ))
+            .into());
+        }
+        self.address_signer(request.from.parse()?)?;
+        self.settle_withdrawal(request, operator).await
+    }
+
+    pub fn reject_withdrawal(
+        &self,
+        id: i64,
+        operator: &str,
+        comment: Option<&str>,
+    ) -> Result<WithdrawalRequest> {
+        policy::reject(&self.db, id, operator, comment)
+    }
+
+    /// Sends `request` if it is approved, recording the outcome on behalf
+    /// of `actor`, who took the final decision.
+    async fn settle_withdrawal(
+        &self,
+        request: WithdrawalRequest,
+        actor: &str,
+    ) -> Result<(WithdrawalRequest, Option<TrackedTransaction>)> {
+        if request.status != WithdrawalStatus::Approved {
+            return Ok((request, None));
+        }
+
+        let (from, to) = (request.from.parse()?, request.to.parse()?);
+        let amount = U256::from_str_radix(&request.amount, 10)?;
+        // Either may have been frozen while the request awaited approval
+        if let Err(e) = blacklist::screen(&self.provider, from, to).await {
+            return match Error::from(e) {
+                Error::PolicyViolation(reason) => Err(policy::revoke(&self.db, request.id, reason)),
+                // Nothing was decided, the request stays approved to be sent
+                e => Err(e.into()),
+            };
+        }
+        let withdrawal = Some((&request, actor));
+        let sent = self
+            .transfer_usdt(from, to, amount, request.urgency, withdrawal)
+            .await;
+        match sent {
+            Ok(tracked) => {
+                let request = WithdrawalRequest {
+                    status: WithdrawalStatus::Executed,
+                    transaction_id: Some(tracked.id),
+                    ..request
+                };
+                Ok((request, Some(tracked)))
+            }
+            Err(e) => {
+                policy::fail(&self.db, request.id, actor, &e)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Fails the withdrawal requests a previous run approved but did not
+    /// send, see [`policy::recover`].
+    pub fn recover_withdrawals(&self) -> Result<Vec<i64>> {
+        policy::recover(&self.db)
+    }
+
+    pub fn withdrawal_request(&self, id: i64) -> Result<WithdrawalRequest> {
+        self.db
+            .lock()
+            .unwrap()
+            .get_withdrawal_request(id)?
+            .ok_or_else(|| Error::NotFound(format!("Withdrawal request {} not found", id)).into())
+    }
+
+    pub fn withdrawal_requests(
+        &self,
+        status: Option<WithdrawalStatus>,
+        limit: u32,
+    ) -> Result<Vec<WithdrawalRequest>> {
+        self.db
+            .lock()
+            .unwrap()
+            .list_withdrawal_requests(status, limit)
+    }
+
+    pub fn audit_log(&self, withdrawal_id: Option<i64>, limit: u32) -> Result<Vec<AuditEntry>> {
+        self.db
+            .lock()
+            .unwrap()
+            .list_audit_entries(withdrawal_id, limit)
+    }
+
+    /// Asks the USDT contract whether `address` is blacklisted.
+    pub async fn is_blacklisted(&self, address: Address) -> Result<bool> {
+        Ok(self
+            .provider
+            .is_blacklisted(address)
+            .await
+            .map_err(Error::rpc)?)
+    }
+
+    /// Our addresses flagged as frozen, see [`blacklist`].
+    pub fn frozen_addresses(&self) -> Result<Vec<FrozenAddress>> {
+        self.db.lock().unwrap().get_frozen_addresses()
+    }
+
+    /// Catches up on the blacklist events since the last sync and publishes
+    /// an `address.*` event for each of our addresses frozen or unfrozen.
+    pub async fn sync_blacklist(&self) -> Result<()> {
+        let applied = blacklist::sync(&self.db, &self.provider, config::discovery_log_range())
+            .await
+            .map_err(Error::rpc)?;
+        publish_blacklist_changes(&self.publisher, &applied)
+    }
+
+    /// Checks every one of our addresses against the blacklist, for flags
+    /// that may have missed an event, and returns the frozen ones.
+    pub async fn scan_blacklist(&self) -> Result<Vec<FrozenAddress>> {
+        let applied = blacklist::scan(&self.db, &self.provider)
+            .await
+            .map_err(Error::rpc)?;
+        publish_blacklist_changes(&self.publisher, &applied)?;
+        self.frozen_addresses()
+    }
+
+    /// Replaces the pending transaction `id` with a copy paying higher fees
+    /// on behalf of `actor`.
+    pub async fn speed_up_transaction(
+        &self,
+        id: i64,
+        actor: &str,
+        urgency: Option<Urgency>,
+    ) -> Result<TrackedTransaction> {
+        self.replace_transaction(id, actor, urgency, false).await
+    }
+
+    /// Replaces the pending transaction `id` with a transfer of nothing to
+    /// itself at the same nonce, paying higher fees, on behalf of `actor`.
+    pub async fn cancel_transaction(
+        &self,
+        id: i64,
+        actor: &str,
+        urgency: Option<Urgency>,
+    ) -> Result<TrackedTransaction> {
+        self.replace_transaction(id, actor, urgency, true).await
+    }
+
+    async fn replace_transaction(
+        &self,
+        id: i64,
+        actor: &str,
+        urgency: Option<Urgency>,
+        cancel: bool,
+    ) -> Result<TrackedTransaction> {
+        let tracked = self.transaction(id)?;
+        if tracked.status != TxStatus::Pending {
+            return Err(Error::Conflict(format!(
+                "Transaction {} is {}, only pending transactions can be replaced",
+                id,
+                tracked.status.as_str()
+            ))
+            .into());
+        }
+        if tracked.cancel_requested && !cancel {
+            return Err(Error::Conflict(format!("Transaction {} is being cancelled", id)).into());
+        }
+
+        let from = tracked.tx.from;
+        let signer = self.address_signer(from)?;
+        let estimate = self.estimate_fees(urgency).await?;
+        let mut tx = tracked.tx.clone();
+        tx.gas = fees::bump_fees(&tracked.tx.gas, &estimate.gas, FeeCaps::from_config())?;
+        if cancel {
+            tx.to = from;
+            tx.value = U256::ZERO;
+            tx.input = Bytes::new();
+            tx.gas_limit = CANCEL_GAS_LIMIT;
+        }
+
+        let envelope = tx.sign(&signer)?;
+        let tx_hash = envelope.tx_hash().to_string();
+        let raw = Bytes::from(envelope.encoded_2718());
+
+        // Recorded first, so a crash after the broadcast cannot lose it
+        let previous_raw = {
+            let db_lock = self.db.lock().unwrap();
+            let previous_raw = db_lock.get_transaction_raw(id)?;
+            db_lock.replace_transaction(id, &tx, &tx_hash, &raw.to_string(), cancel)?;
+            previous_raw
+        };
+        if let Err(e) = self.provider.send_raw(&raw).await {
+            if tracker::is_rejected(&e) {
+                self.db
+                    .lock()
+                    .unwrap()
+                    .revert_replacement(&tracked, &previous_raw, &tx_hash)?;
+                return Err(Error::rpc(e).into());
+            }
+            // The node may have it, the tracker broadcasts it again if not
+            println!("Broadcast of replacement {} failed: {:?}", tx_hash, e);
+        }
+        println!(
+            "Replaced transaction {} ({}) with {}{}",
+            id,
+            tracked.tx_hash,
+            tx_hash,
+            if cancel { ", cancelling it" } else { "" }
+        );
+
+        let action = if cancel {
+            AuditAction::Cancelled
+        } else {
+            AuditAction::SpedUp
+        };
+        let details = format!("Replaced {} with {}", tracked.tx_hash, tx_hash);
+        // As in `send_usdt`, nothing after the broadcast fails the call
+        if let Err(e) =
+            self.db
+                .lock()
+                .unwrap()
+                .record_audit_entry(actor, action, Some(id), Some(&details))
+        {
+            println!("Failed to audit the replacement of {}: {:?}", id, e);
+        }
+        let tracked = TrackedTransaction {
+            tx,
+            tx_hash,
+            broadcasts: 1,
+            cancel_requested: tracked.cancel_requested || cancel,
+            ..tracked
+        };
+        self.publish_after_broadcast(tracked.replaced_event());
+        Ok(tracked)
+    }
+
+    /// Checks the tracked transactions once and publishes a `withdrawal.*`
+    /// event for each whose status changed.
+    pub async fn poll_transactions(&self) -> Result<()> {
+        let changed =
+            tracker::poll(&self.db, &self.provider, config::withdrawal_confirmations()).await?;
+        for tx in changed {
+            self.publish_chainevent(tx.event())?;
+        }
+        Ok(())
+    }
+
+    pub fn transaction(&self, id: i64) -> Result<TrackedTransaction> {
+        self.db
+            .lock()
+            .unwrap()
+            .get_transaction(id)?
+            .ok_or_else(|| Error::NotFound(format!("Transaction {} not found", id)).into())
+    }
+
+    pub fn transactions(
+        &self,
+        status: Option<TxStatus>,
+        limit: u32,
+    ) -> Result<Vec<TrackedTransaction>> {
+        self.db.lock().unwrap().list_transactions(status, limit)
+    }
+
+    fn stored_address(&self, address: Address) -> Result<AddressRecord> {
+        self.db
+            .lock()
+            .unwrap()
+            .get_address_record(&address.to_string())?
+            .ok_or_else(|| Error::NotFound(format!("Address {} is not ours", address)).into())
+    }
+
+    /// Signs `message` as EIP-191 `personal_sign` with the key of `address`,
+    /// which must be one of our stored addresses.
+    pub fn sign_message(&self, address: Address, message: &[u8]) -> Result<SignedMessage> {
+        signing::sign_message(&self.address_signer(address)?, message)
+    }
+
+    /// Signs EIP-712 `typed_data` with the key of the stored `address`.
+    pub fn sign_typed_data(
+        &self,
+        address: Address,
+        typed_data: &TypedData,
+    ) -> Result<SignedMessage> {
+        signing::sign_typed_data(&self.address_signer(address)?, typed_data)
+    }
+
+    fn address_signer(&self, address: Address) -> Result<PrivateKeySigner> {
+        if self.watch_only {
+            return Err(Error::Conflict(String::from("A watch-only wallet cannot sign")).into());
+        }
+        let record = self.stored_address(address)?;
+
+        let keys = self.keys.read().unwrap();
+        if keys.is_empty() {
+            return Err(Error::Locked(String::from("Wallet is locked")).into());
+        }
+        let key = keys
+            .get(&record.account)
+            .and_then(|keys| keys.key.as_ref())
+            .ok_or_else(|| {
+                Error::Conflict(format!("Account {} is not configured", record.account))
+            })?;
+        signing::signer_for(key, &record)
     }
 
     pub fn publish_chainevent(&self, event: ChainEvent) -> Result<()> {
         let publisher = self.publisher.lock().unwrap();
         publisher.publish(event)
     }
+
+    /// Publishes `event` about a transaction already broadcast, where a
+    /// failure must not fail the call.
+    fn publish_after_broadcast(&self, event: ChainEvent) {
+        let topic = event.topic();
+        if let Err(e) = self.publish_chainevent(event) {
+            println!("Failed to publish {}: {:?}", topic, e);
+        }
+    }
+}
+
+fn publish_blacklist_changes(
+    publisher: &Mutex<Publisher>,
+    applied: &[(BlacklistChange, AddressRecord)],
+) -> Result<()> {
+    let publisher = publisher.lock().unwrap();
+    for (change, record) in applied {
+        publisher.publish(change.event(record))?;
+    }
+    Ok(())
+}
+
+/// Blacklist status of a deposit's sender or recipient, `None` if the
+/// contract could not be asked.
+async fn check_blacklist(provider: &RootProvider<PubSubFrontend>, address: &str) -> Option<bool> {
+    let checked = match address.parse() {
+        Ok(address) => provider.is_blacklisted(address).await,
+        Err(e) => Err(e.into()),
+    };
+    checked
+        .map_err(|e| println!("Failed to check {} against the blacklist: {:?}", address, e))
+        .ok()
+}
+
+/// Loads the mnemonic, reporting a wrong password as [`Error::Unauthorized`].
+fn load_mnemonic(storage: &MnemonicStorage, password: &str) -> Result<Mnemonic<English>> {
+    storage
+        .load_mnemonic(password)
+        .map_err(|e| match e.downcast_ref::<LoadError>() {
+            Some(LoadError::DecryptionFailed) => {
+                Error::Unauthorized(String::from("Wrong wallet password")).into()
+            }
+            _ => e,
+        })
+}
+
+fn is_constraint_violation(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(err, _))
+            if err.code == rusqlite::ErrorCode::ConstraintViolation
+    )
 }
@@ -0,0 +1,286 @@
+//! Gas pricing for outbound transactions.
+//!
+//! On EIP-1559 chains the priority fee is a percentile of the rewards paid
+//! in recent blocks, as reported by `eth_feeHistory`, and the max fee adds
+//! headroom above the next block's base fee so the transaction survives a
+//! few fuller blocks. Chains without a base fee get a legacy gas price.
+//!
+//! Both are capped by `MAX_FEE_PER_GAS_GWEI` and
+//! `MAX_PRIORITY_FEE_PER_GAS_GWEI`. When the network alone costs more than
+//! the cap, estimation fails instead of producing a transaction that can
+//! never be mined.
+
+use std::str::FromStr;
+
+use alloy::{
+    eips::BlockNumberOrTag,
+    providers::{Provider, RootProvider},
+    pubsub::PubSubFrontend,
+    rpc::types::FeeHistory,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{config, error::Error};
+
+use super::offline::GasParams;
+
+/// Number of recent blocks the priority fee is taken from.
+pub const FEE_HISTORY_BLOCKS: u64 = 20;
+
+const GWEI: u128 = 1_000_000_000;
+
+/// Priority fee used when recent blocks were empty and paid no rewards.
+const MIN_PRIORITY_FEE: u128 = GWEI / 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Slow,
+    #[default]
+    Normal,
+    Fast,
+}
+
+impl Urgency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Urgency::Slow => "slow",
+            Urgency::Normal => "normal",
+            Urgency::Fast => "fast",
+        }
+    }
+
+    /// Percentile of recent priority fees to pay.
+    fn reward_percentile(&self) -> f64 {
+        match self {
+            Urgency::Slow => 10.0,
+            Urgency::Normal => 50.0,
+            Urgency::Fast => 90.0,
+        }
+    }
+
+    /// Max fee as a multiple of the next base fee, as a fraction. The base
+    /// fee rises by at most 12.5% per block.
+    fn base_fee_headroom(&self) -> (u128, u128) {
+        match self {
+            Urgency::Slow => (5, 4),
+            Urgency::Normal => (2, 1),
+            Urgency::Fast => (3, 1),
+        }
+    }
+
+    /// Multiple of the node's suggested legacy gas price to pay.
+    fn gas_price_factor(&self) -> (u128, u128) {
+        match self {
+            Urgency::Slow => (1, 1),
+            Urgency::Normal => (11, 10),
+            Urgency::Fast => (5, 4),
+        }
+    }
+
+    /// The urgency configured with `FEE_URGENCY`, `normal` by default.
+    pub fn from_config() -> Result<Self> {
+        config::fee_urgency().map_or(Ok(Urgency::default()), str::parse)
+    }
+}
+
+impl FromStr for Urgency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "slow" => Ok(Urgency::Slow),
+            "normal" => Ok(Urgency::Normal),
+            "fast" => Ok(Urgency::Fast),
+            _ => Err(
+                Error::InvalidInput(format!("Urgency {} must be slow, normal or fast", s)).into(),
+            ),
+        }
+    }
+}
+
+/// Upper bounds on what a transaction may pay per gas, in wei.
+#[derive(Debug, Clone, Copy)]
+pub struct FeeCaps {
+    pub max_fee_per_gas: u128,
+    pub max_priority_fee_per_gas: u128,
+}
+
+impl FeeCaps {
+    pub fn from_config() -> Self {
+        Self {
+            max_fee_per_gas: config::max_fee_per_gas_gwei() as u128 * GWEI,
+            max_priority_fee_per_gas: config::max_priority_fee_per_gas_gwei() as u128 * GWEI,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FeeEstimate {
+    pub urgency: Urgency,
+    pub gas: GasParams,
+    /// Base fee of the next block, `None` on chains without EIP-1559.
+    pub base_fee_per_gas: Option<u128>,
+    /// `true` if a cap lowered the fees.
+    pub capped: bool,
+}
+
+/// Estimates fees for a transaction sent now.
+pub async fn estimate(
+    provider: &RootProvider<PubSubFrontend>,
+    urgency: Urgency,
+    caps: FeeCaps,
+) -> Result<FeeEstimate> {
+    let history = provider
+        .get_fee_history(
+            FEE_HISTORY_BLOCKS,
+            BlockNumberOrTag::Latest,
+            &[urgency.reward_percentile()],
+        )
+        .await;
+    match history {
+        Ok(history) if next_base_fee(&history).is_some() => {
+            estimate_from_history(&history, urgency, caps)
+        }
+        result => {
+            if let Err(e) = result {
+                println!("eth_feeHistory failed, using the legacy gas price: {:?}", e);
+            }
+            let gas_price = provider.get_gas_price().await.map_err(Error::rpc)?;
+            estimate_legacy(gas_price, urgency, caps)
+        }
+    }
+}
+
+/// EIP-1559 fees from `history`, fetched with the reward percentile of
+/// `urgency`.
+pub fn estimate_from_history(
+    history: &FeeHistory,
+    urgency: Urgency,
+    caps: FeeCaps,
+) -> Result<FeeEstimate> {
+    let base_fee = next_base_fee(history)
+        .ok_or_else(|| Error::Conflict(String::from("Chain does not support EIP-1559")))?;
+    check_cap(base_fee, caps.max_fee_per_gas, "Base fee")?;
+
+    let mut rewards: Vec<u128> = history
+        .reward
+        .iter()
+        .flatten()
+        .filter_map(|block| block.first().copied())
+        .filter(|reward| *reward > 0)
+        .collect();
+    rewards.sort_unstable();
+    let priority_fee = rewards
+        .get(rewards.len() / 2)
+        .copied()
+        .unwrap_or(MIN_PRIORITY_FEE);
+
+    let (num, den) = urgency.base_fee_headroom();
+    let capped_priority_fee = priority_fee.min(caps.max_priority_fee_per_gas);
+    let wanted_max_fee = base_fee * num / den + capped_priority_fee;
+    let max_fee_per_gas = wanted_max_fee.min(caps.max_fee_per_gas);
+    // Nodes reject a priority fee above the max fee, e.g. under a low cap
+    let max_priority_fee_per_gas = capped_priority_fee.min(max_fee_per_gas);
+
+    Ok(FeeEstimate {
+        urgency,
+        gas: GasParams::Eip1559 {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        },
+        base_fee_per_gas: Some(base_fee),
+        capped: max_priority_fee_per_gas < priority_fee || max_fee_per_gas < wanted_max_fee,
+    })
+}
+
+/// Legacy fees from the node's suggested `gas_price`.
+pub fn estimate_legacy(gas_price: u128, urgency: Urgency, caps: FeeCaps) -> Result<FeeEstimate> {
+    check_cap(gas_price, caps.max_fee_per_gas, "Gas price")?;
+
+    let (num, den) = urgency.gas_price_factor();
+    let wanted = gas_price * num / den;
+    let gas_price = wanted.min(caps.max_fee_per_gas);
+
+    Ok(FeeEstimate {
+        urgency,
+        gas: GasParams::Legacy { gas_price },
+        base_fee_per_gas: None,
+        capped: gas_price < wanted,
+    })
+}
+
+/// Fees for a transaction replacing one that paid `old`: at least the
+/// current `estimate`, and more than 10% above `old`, which nodes require
+/// to accept a replacement. Fails if that exceeds the caps.
+pub fn bump_fees(old: &GasParams, estimate: &GasParams, caps: FeeCaps) -> Result<GasParams> {
+    let bump = |fee: u128| fee + fee / 10 + 1;
+    let gas = match (old, estimate) {
+        (
+            GasParams::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            },
+            estimate,
+        ) => {
+            let (estimated_max_fee, estimated_priority_fee) = match estimate {
+                GasParams::Eip1559 {
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                } => (*max_fee_per_gas, *max_priority_fee_per_gas),
+                GasParams::Legacy { gas_price } => (*gas_price, *gas_price),
+            };
+            let max_fee = bump(*max_fee_per_gas).max(estimated_max_fee);
+            // Nodes reject a priority fee above the max fee
+            let priority_fee = bump(*max_priority_fee_per_gas)
+                .max(estimated_priority_fee)
+                .min(max_fee);
+            check_cap(
+                priority_fee,
+                caps.max_priority_fee_per_gas,
+                "Replacement priority fee",
+            )?;
+            check_cap(max_fee, caps.max_fee_per_gas, "Replacement max fee")?;
+            GasParams::Eip1559 {
+                max_fee_per_gas: max_fee,
+                max_priority_fee_per_gas: priority_fee,
+            }
+        }
+        (GasParams::Legacy { gas_price }, estimate) => {
+            let estimated = match estimate {
+                GasParams::Eip1559 {
+                    max_fee_per_gas, ..
+                } => *max_fee_per_gas,
+                GasParams::Legacy { gas_price } => *gas_price,
+            };
+            let gas_price = bump(*gas_price).max(estimated);
+            check_cap(gas_price, caps.max_fee_per_gas, "Replacement gas price")?;
+            GasParams::Legacy { gas_price }
+        }
+    };
+    Ok(gas)
+}
+
+/// Base fee of the block after the newest in `history`. `None` on chains
+/// without EIP-1559, for which nodes report zero.
+fn next_base_fee(history: &FeeHistory) -> Option<u128> {
+    history
+        .base_fee_per_gas
+        .last()
+        .copied()
+        .filter(|fee| *fee > 0)
+}
+
+fn check_cap(fee: u128, cap: u128, what: &str) -> Result<()> {
+    if fee > cap {
+        return Err(Error::Conflict(format!(
+            "{} of {} gwei exceeds the cap of {} gwei, try again later",
+            what,
+            fee / GWEI,
+            cap / GWEI
+        ))
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use alloy::signers::{
+    k256::ecdsa::SigningKey,
+    local::coins_bip39::{English, Mnemonic},
+};
+use anyhow::Result;
+use coins_bip32::{
+    primitives::{ChainCode, Hint, KeyFingerprint, XKeyInfo},
+    xkeys::XPriv,
+};
+use log::warn;
+use zeroize::Zeroize;
+
+use super::derivation::{master_key, AddressDeriver};
+
+/// Heap buffer that is locked into RAM, so it is never written to swap, and
+/// zeroed when dropped.
+pub struct LockedBuffer {
+    data: Box<[u8]>,
+    locked: bool,
+}
+
+impl LockedBuffer {
+    pub fn new(len: usize) -> Self {
+        let data = vec![0u8; len].into_boxed_slice();
+        let locked = mlock(&data);
+        if !locked {
+            warn!("Failed to lock key memory, it may be written to swap");
+        }
+        Self { data, locked }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl Drop for LockedBuffer {
+    fn drop(&mut self) {
+        self.data.zeroize();
+        if self.locked {
+            munlock(&self.data);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn mlock(data: &[u8]) -> bool {
+    unsafe { libc::mlock(data.as_ptr().cast(), data.len()) == 0 }
+}
+
+#[cfg(unix)]
+fn munlock(data: &[u8]) {
+    unsafe {
+        libc::munlock(data.as_ptr().cast(), data.len());
+    }
+}
+
+#[cfg(not(unix))]
+fn mlock(_data: &[u8]) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn munlock(_data: &[u8]) {}
+
+const KEY_LEN: usize = 32;
+
+/// An extended private key whose secret half (private key and chain code)
+/// lives in a [`LockedBuffer`].
+///
+/// Keep one of these instead of a mnemonic: it is derived once, after which
+/// the mnemonic and seed can be dropped. [`XPriv`]s are rebuilt on demand
+/// and zeroize their private key when dropped.
+pub struct ProtectedKey {
+    secret: LockedBuffer,
+    depth: u8,
+    parent: KeyFingerprint,
+    index: u32,
+    hint: Hint,
+}
+
+impl ProtectedKey {
+    /// The BIP-32 root key of `mnemonic` and `passphrase`.
+    pub fn master(mnemonic: &Mnemonic<English>, passphrase: Option<&str>) -> Result<Self> {
+        Ok(Self::from_xpriv(&master_key(mnemonic, passphrase)?))
+    }
+
+    /// The key at `path_prefix`, the parent of the wallet's addresses.
+    pub fn account(
+        mnemonic: &Mnemonic<English>,
+        passphrase: Option<&str>,
+        path_prefix: &str,
+    ) -> Result<Self> {
+        let key =
+            master_key(mnemonic, passphrase)?.derive_path(path_prefix.trim_end_matches('/'))?;
+        Ok(Self::from_xpriv(&key))
+    }
+
+    fn from_xpriv(key: &XPriv) -> Self {
+        let info: &XKeyInfo = key.as_ref();
+        let signing_key: &SigningKey = key.as_ref();
+
+        let mut secret = LockedBuffer::new(2 * KEY_LEN);
+        let mut key_bytes = signing_key.to_bytes();
+        secret.as_mut_slice()[..KEY_LEN].copy_from_slice(&key_bytes);
+        secret.as_mut_slice()[KEY_LEN..].copy_from_slice(&info.chain_code.0);
+        key_bytes.zeroize();
+
+        Self {
+            secret,
+            depth: info.depth,
+            parent: info.parent,
+            index: info.index,
+            hint: info.hint,
+        }
+    }
+
+    /// Rebuilds the extended private key. Drop it as soon as possible.
+    pub fn xpriv(&self) -> Result<XPriv> {
+        let secret = self.secret.as_slice();
+        let key = SigningKey::from_slice(&secret[..KEY_LEN])?;
+        let mut chain_code = [0u8; KEY_LEN];
+        chain_code.copy_from_slice(&secret[KEY_LEN..]);
+
+        Ok(XPriv::new(
+            key,
+            XKeyInfo {
+                depth: self.depth,
+                parent: self.parent,
+                index: self.index,
+                chain_code: ChainCode(chain_code),
+                hint: self.hint,
+            },
+        ))
+    }
+
+    /// Address deriver for the children of this key.
+    pub fn deriver(&self) -> Result<AddressDeriver> {
+        Ok(AddressDeriver::from_parent(self.xpriv()?.verify_key()))
+    }
+}
@@ -1,61 +1,159 @@
 use alloy::signers::local::coins_bip39::{English, Mnemonic};
 use anyhow::{anyhow, Result};
 use chacha20poly1305::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     ChaCha20Poly1305, Nonce,
 };
 use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf};
+use std::{
+    fmt, fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+use crate::error::Error;
+
+/// Current on-disk format. Version 0 files predate versioning: they have no
+/// `version` or `kdf` fields and always use PBKDF2 with 100,000 iterations.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Kinds of secret files, authenticated with their contents.
+const MNEMONIC_KIND: &str = "mnemonic";
+const PASSPHRASE_KIND: &str = "passphrase";
+
+/// Mnemonic lengths accepted by [`MnemonicStorage::create`].
+pub const WORD_COUNTS: [usize; 3] = [12, 18, 24];
+
+/// Upper bounds on the KDF parameters read from a file. The parameters are
+/// only authenticated once the key is derived, so a tampered file could
+/// otherwise exhaust memory or keep a load busy for hours.
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const MAX_ARGON2_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+const MAX_ARGON2_PARALLELISM: u32 = 16;
+
+/// Key derivation function and parameters used to encrypt a mnemonic file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum KdfParams {
+    Pbkdf2Sha256 {
+        iterations: u32,
+    },
+    Argon2id {
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl KdfParams {
+    /// Parameters of files written before the format was versioned.
+    pub fn legacy() -> Self {
+        KdfParams::Pbkdf2Sha256 {
+            iterations: 100_000,
+        }
+    }
+
+    /// Default for new files: Argon2id with 64 MiB, 3 passes, 1 lane.
+    pub fn argon2id() -> Self {
+        KdfParams::Argon2id {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 struct EncryptedMnemonic {
+    #[serde(default)]
+    version: u32,
+    #[serde(default = "KdfParams::legacy")]
+    kdf: KdfParams,
     encrypted_data: Vec<u8>,
     nonce: Vec<u8>,
     salt: Vec<u8>,
 }
 
+impl EncryptedMnemonic {
+    /// Associated data authenticated with the ciphertext, so the version and
+    /// KDF parameters cannot be downgraded without the password, and a file
+    /// of one `kind` cannot pass for another.
+    fn associated_data(&self, kind: &str) -> Vec<u8> {
+        if self.version == 0 {
+            return Vec::new();
+        }
+        format!(
+            "ethserv-{}-v{}:{}",
+            kind,
+            self.version,
+            serde_json::to_string(&self.kdf).unwrap_or_default()
+        )
+        .into_bytes()
+    }
+}
+
 pub struct MnemonicStorage {
     storage_path: PathBuf,
+    kdf: KdfParams,
 }
 
 impl MnemonicStorage {
     pub fn new(storage_path: PathBuf) -> Self {
-        Self { storage_path }
+        Self::with_kdf(storage_path, KdfParams::argon2id())
     }
 
-    pub fn load_or_create_by_password(&self, password: &str) -> Mnemonic<English> {
-        match self.load_mnemonic(password) {
-            Ok(mnemonic_words) => mnemonic_words,
-            Err(_) => {
-                println!("Creating new mnemonic");
-
-                let mut rng = rand::thread_rng();
-
-                let mnemonic: Mnemonic<English> =
-                    Mnemonic::new_with_count(&mut rng, 12usize).unwrap();
+    /// Storage that writes new files with `kdf`. Existing files are always
+    /// read with the parameters recorded in them.
+    pub fn with_kdf(storage_path: PathBuf, kdf: KdfParams) -> Self {
+        Self { storage_path, kdf }
+    }
 
-                let mnemonic_phrase = mnemonic.to_phrase();
+    pub fn exists(&self) -> bool {
+        self.storage_path.exists()
+    }
 
-                self.save_mnemonic(&mnemonic_phrase, password).unwrap();
+    /// Generates and stores a new mnemonic of `word_count` words (12, 18 or
+    /// 24). Refuses to overwrite an existing file.
+    pub fn create(&self, password: &str, word_count: usize) -> Result<Mnemonic<English>> {
+        if !WORD_COUNTS.contains(&word_count) {
+            return Err(Error::InvalidInput(format!(
+                "Mnemonic length must be 12, 18 or 24 words, got {}",
+                word_count
+            ))
+            .into());
+        }
+        if self.exists() {
+            return Err(anyhow!(
+                "Mnemonic file {} already exists",
+                self.storage_path.display()
+            ));
+        }
 
-                println!("New wallet created and saved");
+        let mut rng = rand::thread_rng();
+        let mnemonic: Mnemonic<English> = Mnemonic::new_with_count(&mut rng, word_count)?;
+        self.save_mnemonic(&Zeroizing::new(mnemonic.to_phrase()), password)?;
 
-                self.load_mnemonic(password).unwrap()
-            }
-        }
+        println!("New wallet created and saved");
+        self.load_mnemonic(password)
     }
 
     pub fn save_mnemonic(&self, mnemonic: &str, password: &str) -> Result<()> {
-        // Validate mnemonic by trying to create a wallet from it
+        self.write_secret(MNEMONIC_KIND, mnemonic, password)
+    }
 
+    /// Encrypts `secret` with `password` into the file, replacing it
+    /// atomically.
+    fn write_secret(&self, kind: &str, secret: &str, password: &str) -> Result<()> {
         let salt = {
             let mut salt = [0u8; 32];
             OsRng.fill_bytes(&mut salt);
             salt.to_vec()
         };
 
-        let key = derive_key(password, &salt)?;
+        let key = Zeroizing::new(derive_key(&self.kdf, password, &salt)?);
         let cipher = ChaCha20Poly1305::new(key.as_ref().into());
 
         let nonce = {
@@ -64,52 +162,275 @@ impl MnemonicStorage {
             nonce
         };
 
-        let encrypted_data = cipher
-            .encrypt(Nonce::from_slice(&nonce), mnemonic.as_bytes())
-            .map_err(|e| anyhow!("Encryption error: {}", e))?;
-
-        let encrypted_mnemonic = EncryptedMnemonic {
-            encrypted_data,
+        let mut encrypted_mnemonic = EncryptedMnemonic {
+            version: FORMAT_VERSION,
+            kdf: self.kdf.clone(),
+            encrypted_data: Vec::new(),
             nonce: nonce.to_vec(),
             salt,
         };
 
+        let aad = encrypted_mnemonic.associated_data(kind);
+        encrypted_mnemonic.encrypted_data = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: secret.as_bytes(),
+                    aad: &aad,
+                },
+            )
+            .map_err(|e| anyhow!("Encryption error: {}", e))?;
+
         let json = serde_json::to_string(&encrypted_mnemonic)?;
-        fs::write(&self.storage_path, json)?;
+
+        // Write to a sibling file and rename, so a crash never leaves a
+        // half-written file behind.
+        let tmp_path = self.storage_path.with_extension("dat.tmp");
+        write_private(&tmp_path, json.as_bytes())?;
+        fs::rename(&tmp_path, &self.storage_path)?;
         Ok(())
     }
 
+    /// `true` if the file was written with an older format version or
+    /// different KDF parameters than this storage writes.
+    pub fn needs_migration(&self) -> Result<bool> {
+        let encrypted_mnemonic = self.read_encrypted()?;
+        Ok(encrypted_mnemonic.version < FORMAT_VERSION || encrypted_mnemonic.kdf != self.kdf)
+    }
+
+    /// Re-encrypts the mnemonic in place with the current format and KDF.
+    /// Returns `false` if it was already up to date.
+    pub fn migrate(&self, password: &str) -> Result<bool> {
+        if !self.needs_migration()? {
+            return Ok(false);
+        }
+        let mnemonic = self.load_mnemonic(password)?;
+        self.save_mnemonic(&Zeroizing::new(mnemonic.to_phrase()), password)?;
+        Ok(true)
+    }
+
+    fn read_encrypted(&self) -> Result<EncryptedMnemonic> {
+        let json = match fs::read_to_string(&self.storage_path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(LoadError::Missing(self.storage_path.clone()).into())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let encrypted_mnemonic: EncryptedMnemonic = serde_json::from_str(&json)
+            .map_err(|e| LoadError::Corrupt(format!("invalid file format: {}", e)))?;
+        if encrypted_mnemonic.version > FORMAT_VERSION {
+            return Err(LoadError::Corrupt(format!(
+                "unsupported format version {}",
+                encrypted_mnemonic.version
+            ))
+            .into());
+        }
+        if encrypted_mnemonic.nonce.len() != 12 {
+            return Err(LoadError::Corrupt(String::from("invalid nonce length")).into());
+        }
+        Ok(encrypted_mnemonic)
+    }
+
     pub fn load_mnemonic(&self, password: &str) -> Result<Mnemonic<English>> {
-        let json = fs::read_to_string(&self.storage_path)?;
-        let encrypted_mnemonic: EncryptedMnemonic = serde_json::from_str(&json)?;
+        let decrypted_data = self.read_secret(MNEMONIC_KIND, password)?;
+        let mnemonic_str = std::str::from_utf8(&decrypted_data)
+            .map_err(|_| LoadError::Corrupt(String::from("mnemonic is not valid UTF-8")))?;
+
+        let mnemonic = Mnemonic::<English>::new_from_phrase(mnemonic_str).map_err(|_| {
+            LoadError::Corrupt(String::from("decrypted phrase is not a valid mnemonic"))
+        })?;
+
+        Ok(mnemonic)
+    }
+
+    /// Decrypts the file with `password`.
+    fn read_secret(&self, kind: &str, password: &str) -> Result<Zeroizing<Vec<u8>>> {
+        let encrypted_mnemonic = self.read_encrypted()?;
 
-        let key = derive_key(password, &encrypted_mnemonic.salt)?;
+        let key = Zeroizing::new(derive_key(
+            &encrypted_mnemonic.kdf,
+            password,
+            &encrypted_mnemonic.salt,
+        )?);
         let cipher = ChaCha20Poly1305::new(key.as_ref().into());
 
+        let aad = encrypted_mnemonic.associated_data(kind);
         let decrypted_data = cipher
             .decrypt(
                 Nonce::from_slice(&encrypted_mnemonic.nonce),
-                encrypted_mnemonic.encrypted_data.as_ref(),
+                Payload {
+                    msg: &encrypted_mnemonic.encrypted_data,
+                    aad: &aad,
+                },
             )
-            .map_err(|e| anyhow!("Decryption error: {}", e))?;
+            .map_err(|_| LoadError::DecryptionFailed)?;
 
-        let mnemonic_str = String::from_utf8(decrypted_data)?;
+        Ok(Zeroizing::new(decrypted_data))
+    }
+}
 
-        let mnemonic = Mnemonic::<English>::new_from_phrase(&mnemonic_str).unwrap();
+/// The BIP-39 passphrase of a seed, encrypted with the wallet password in a
+/// file of its own next to the mnemonic, in the mnemonic file's format.
+/// Backups and shares leave it out, so they alone do not give away the seed.
+pub struct PassphraseStorage {
+    storage: MnemonicStorage,
+}
 
-        Ok(mnemonic)
+impl PassphraseStorage {
+    pub fn new(storage_path: PathBuf) -> Self {
+        Self {
+            storage: MnemonicStorage::new(storage_path),
+        }
+    }
+
+    pub fn exists(&self) -> bool {
+        self.storage.exists()
+    }
+
+    pub fn save(&self, passphrase: &str, password: &str) -> Result<()> {
+        self.storage
+            .write_secret(PASSPHRASE_KIND, passphrase, password)
+    }
+
+    /// Stores `passphrase`, or removes the file for a seed without one.
+    pub fn set(&self, passphrase: Option<&str>, password: &str) -> Result<()> {
+        match passphrase {
+            Some(passphrase) => self.save(passphrase, password),
+            None if self.exists() => Ok(fs::remove_file(&self.storage.storage_path)?),
+            None => Ok(()),
+        }
+    }
+
+    /// The stored passphrase, or `None` if there is no file.
+    pub fn load(&self, password: &str) -> Result<Option<Zeroizing<String>>> {
+        if !self.exists() {
+            return Ok(None);
+        }
+        let decrypted_data = self.storage.read_secret(PASSPHRASE_KIND, password)?;
+        let passphrase = std::str::from_utf8(&decrypted_data)
+            .map_err(|_| LoadError::Corrupt(String::from("passphrase is not valid UTF-8")))?;
+        Ok(Some(Zeroizing::new(passphrase.to_string())))
     }
+
+    /// The stored passphrase, or `configured` for a wallet that has none
+    /// stored. Fails if both are set and differ, since either would derive
+    /// different keys.
+    pub fn load_or(
+        &self,
+        password: &str,
+        configured: Option<&str>,
+    ) -> Result<Option<Zeroizing<String>>> {
+        match (self.load(password)?, configured) {
+            (Some(stored), Some(configured)) if stored.as_str() != configured => {
+                Err(Error::Conflict(String::from(
+                    "The configured BIP-39 passphrase differs from the stored one",
+                ))
+                .into())
+            }
+            (Some(stored), _) => Ok(Some(stored)),
+            (None, configured) => {
+                Ok(configured.map(|passphrase| Zeroizing::new(passphrase.to_string())))
+            }
+        }
+    }
+}
+
+/// Why a mnemonic file could not be loaded. Callers must never treat any of
+/// these as "no wallet" and create a new one.
+#[derive(Debug)]
+pub enum LoadError {
+    /// There is no file at the expected path.
+    Missing(PathBuf),
+    /// The authentication tag did not verify: wrong password, or the
+    /// ciphertext was modified.
+    DecryptionFailed,
+    /// The file exists but is not a mnemonic file we can read.
+    Corrupt(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Missing(path) => write!(
+                f,
+                "no mnemonic file at {}, run `ethserv init` or `ethserv import-mnemonic` first",
+                path.display()
+            ),
+            LoadError::DecryptionFailed => {
+                write!(
+                    f,
+                    "could not decrypt mnemonic: wrong password or corrupted file"
+                )
+            }
+            LoadError::Corrupt(reason) => write!(f, "mnemonic file is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Writes `data` to a file readable only by the owner.
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // `mode` only applies to newly created files
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(data)?;
+    Ok(())
 }
 
-fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; 32]> {
-    use ring::pbkdf2;
+pub(crate) fn derive_key(kdf: &KdfParams, password: &str, salt: &[u8]) -> Result<[u8; 32]> {
     let mut key = [0u8; 32];
-    pbkdf2::derive(
-        pbkdf2::PBKDF2_HMAC_SHA256,
-        std::num::NonZeroU32::new(100_000).unwrap(),
-        salt,
-        password.as_bytes(),
-        &mut key,
-    );
+    match *kdf {
+        KdfParams::Pbkdf2Sha256 { iterations } => {
+            use ring::pbkdf2;
+            let iterations = std::num::NonZeroU32::new(iterations)
+                .ok_or_else(|| LoadError::Corrupt(String::from("zero PBKDF2 iterations")))?;
+            if iterations.get() > MAX_PBKDF2_ITERATIONS {
+                return Err(LoadError::Corrupt(format!(
+                    "{} PBKDF2 iterations exceed the maximum of {}",
+                    iterations, MAX_PBKDF2_ITERATIONS
+                ))
+                .into());
+            }
+            pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                iterations,
+                salt,
+                password.as_bytes(),
+                &mut key,
+            );
+        }
+        KdfParams::Argon2id {
+            memory_kib,
+            iterations,
+            parallelism,
+        } => {
+            use argon2::{Algorithm, Argon2, Params, Version};
+            if memory_kib > MAX_ARGON2_MEMORY_KIB
+                || iterations > MAX_ARGON2_ITERATIONS
+                || parallelism > MAX_ARGON2_PARALLELISM
+            {
+                return Err(LoadError::Corrupt(format!(
+                    "Argon2 parameters exceed the maximum of {} KiB, {} passes, {} lanes",
+                    MAX_ARGON2_MEMORY_KIB, MAX_ARGON2_ITERATIONS, MAX_ARGON2_PARALLELISM
+                ))
+                .into());
+            }
+            let params = Params::new(memory_kib, iterations, parallelism, Some(key.len()))
+                .map_err(|e| LoadError::Corrupt(format!("invalid Argon2 parameters: {}", e)))?;
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(password.as_bytes(), salt, &mut key)
+                .map_err(|e| anyhow!("Key derivation error: {}", e))?;
+        }
+    }
     Ok(key)
 }
@@ -1,5 +1,19 @@
+pub mod accounts;
+pub mod backup;
+pub mod blacklist;
 pub mod database;
+pub mod derivation;
+pub mod discovery;
 pub mod ethserv;
+pub mod fees;
+pub mod keys;
 pub mod mnemonic;
+pub mod nonces;
+pub mod offline;
 pub mod paths;
+pub mod policy;
+pub mod restore;
+pub mod shares;
+pub mod signing;
+pub mod tracker;
 pub mod usdt;
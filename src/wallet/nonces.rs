@@ -0,0 +1,155 @@
+//! Hands out nonces for transactions sent from our addresses, so concurrent
+//! withdrawals, sweeps and top-ups from one address never share a nonce.
+//!
+//! The next nonce of each address is kept in the database and taken inside
+//! a write transaction. A nonce is `reserved` while its transaction is being
+//! built and signed, `sent` once broadcast, and `released` if it must be
+//! handed out again: the transaction was never sent or the node dropped it.
+//! Released nonces are handed out before new ones, which fills the gap that
+//! would otherwise hold back every later transaction of the address.
+
+use std::{future::Future, sync::Mutex, time::Duration};
+
+use alloy::{
+    primitives::{Address, B256},
+    providers::{Provider, RootProvider},
+    pubsub::PubSubFrontend,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::database::WalletDatabase;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NonceStatus {
+    Reserved,
+    Sent,
+    Released,
+}
+
+impl NonceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NonceStatus::Reserved => "reserved",
+            NonceStatus::Sent => "sent",
+            NonceStatus::Released => "released",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "reserved" => Some(NonceStatus::Reserved),
+            "sent" => Some(NonceStatus::Sent),
+            "released" => Some(NonceStatus::Released),
+            _ => None,
+        }
+    }
+}
+
+/// A nonce handed out and not yet known to be in the node's pending nonce.
+#[derive(Debug, Clone, Serialize)]
+pub struct NonceReservation {
+    pub nonce: u64,
+    pub status: NonceStatus,
+    pub tx_hash: Option<String>,
+}
+
+/// Nonce bookkeeping of one address.
+#[derive(Debug, Clone, Serialize)]
+pub struct NonceState {
+    pub address: String,
+    /// Lowest nonce never handed out.
+    pub next_nonce: u64,
+    pub reservations: Vec<NonceReservation>,
+}
+
+impl NonceState {
+    /// Nonces waiting to be handed out again.
+    pub fn gaps(&self) -> Vec<u64> {
+        self.reservations
+            .iter()
+            .filter(|r| r.status == NonceStatus::Released)
+            .map(|r| r.nonce)
+            .collect()
+    }
+}
+
+/// What the node knows about an address's transactions.
+pub trait NonceSource {
+    /// Transaction count including the node's pending transactions.
+    fn pending_nonce(&self, address: Address) -> impl Future<Output = Result<u64>> + Send;
+    /// `true` if the node has the transaction, mined or pending.
+    fn is_known(&self, hash: B256) -> impl Future<Output = Result<bool>> + Send;
+}
+
+impl NonceSource for RootProvider<PubSubFrontend> {
+    async fn pending_nonce(&self, address: Address) -> Result<u64> {
+        Ok(self.get_transaction_count(address).pending().await?)
+    }
+
+    async fn is_known(&self, hash: B256) -> Result<bool> {
+        Ok(self.get_transaction_by_hash(hash).await?.is_some())
+    }
+}
+
+/// Hands out the next nonce of `address`. The first time an address is
+/// used its state starts at the node's pending nonce.
+pub async fn reserve<S: NonceSource>(
+    db: &Mutex<WalletDatabase>,
+    source: &S,
+    address: Address,
+) -> Result<u64> {
+    let key = address.to_string();
+    let known = db.lock().unwrap().get_nonce_state(&key)?.is_some();
+    let floor = if known {
+        0
+    } else {
+        source.pending_nonce(address).await?
+    };
+    let nonce = db.lock().unwrap().reserve_nonce(&key, floor)?;
+    println!("Reserved nonce {} for {}", nonce, address);
+    Ok(nonce)
+}
+
+/// Brings the state of `address` in line with the node: nonces the node
+/// has are forgotten, nonces used outside the wallet are skipped, and sent
+/// transactions the node no longer knows, reservations older than
+/// `stale_after` and unaccounted nonces below the next one become gaps.
+pub async fn reconcile<S: NonceSource>(
+    db: &Mutex<WalletDatabase>,
+    source: &S,
+    address: Address,
+    stale_after: Duration,
+) -> Result<NonceState> {
+    let key = address.to_string();
+    let pending = source.pending_nonce(address).await?;
+    let state = db.lock().unwrap().get_nonce_state(&key)?;
+
+    let mut dropped = Vec::new();
+    for reservation in state.iter().flat_map(|state| &state.reservations) {
+        if reservation.nonce < pending || reservation.status != NonceStatus::Sent {
+            continue;
+        }
+        let Some(hash) = &reservation.tx_hash else {
+            continue;
+        };
+        if !source.is_known(hash.parse()?).await? {
+            println!(
+                "Transaction {} with nonce {} from {} was dropped",
+                hash, reservation.nonce, address
+            );
+            dropped.push((reservation.nonce, hash.clone()));
+        }
+    }
+
+    let state = db
+        .lock()
+        .unwrap()
+        .reconcile_nonces(&key, pending, &dropped, stale_after)?;
+    let gaps = state.gaps();
+    if !gaps.is_empty() {
+        println!("Nonces {:?} of {} will be reused", gaps, address);
+    }
+    Ok(state)
+}
@@ -0,0 +1,346 @@
+//! Exchange format between the online service, which prepares transactions,
+//! and an isolated signer, which holds the mnemonic.
+//!
+//! The online side writes an [`UnsignedBundle`], the signer turns it into a
+//! [`SignedBundle`] and the online side verifies and broadcasts that. Both are
+//! plain JSON files.
+
+use std::{fs, path::Path, sync::Mutex};
+
+use alloy::{
+    consensus::{SignableTransaction, TxEip1559, TxEnvelope, TxLegacy},
+    eips::eip2718::{Decodable2718, Encodable2718},
+    network::TxSignerSync,
+    primitives::{Address, Bytes, TxKind, B256, U256},
+    providers::{Provider, RootProvider},
+    pubsub::PubSubFrontend,
+    signers::local::{
+        coins_bip39::{English, Mnemonic},
+        PrivateKeySigner,
+    },
+    sol_types::SolCall,
+};
+use anyhow::{anyhow, Result};
+use coins_bip32::ecdsa::SigningKey;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::error::Error;
+
+use super::{
+    database::WalletDatabase,
+    keys::ProtectedKey,
+    nonces::{self, NonceSource},
+    usdt::contract::IUESDT,
+};
+
+/// Bumped whenever the bundle layout changes incompatibly.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Gas limit of USDT transfers sent by the wallet. A transfer takes about
+/// 65,000 gas; unused gas is not charged.
+pub const USDT_TRANSFER_GAS_LIMIT: u64 = 100_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GasParams {
+    Legacy {
+        gas_price: u128,
+    },
+    Eip1559 {
+        max_fee_per_gas: u128,
+        max_priority_fee_per_gas: u128,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsignedTransaction {
+    /// Address the signer must derive at `derivation_path`.
+    pub from: Address,
+    /// Full derivation path of the signing key, e.g. `m/44'/60'/0'/0/7`.
+    pub derivation_path: String,
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub to: Address,
+    pub value: U256,
+    pub input: Bytes,
+    pub gas_limit: u64,
+    pub gas: GasParams,
+}
+
+/// A USDT transfer to prepare with [`prepare_usdt_transfer`].
+#[derive(Debug, Clone)]
+pub struct UsdtTransfer {
+    pub from: Address,
+    pub to: Address,
+    /// In USDT base units.
+    pub amount: U256,
+    pub gas_limit: u64,
+    pub gas: GasParams,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsignedBundle {
+    pub version: u32,
+    pub transactions: Vec<UnsignedTransaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedTransaction {
+    pub from: Address,
+    pub nonce: u64,
+    pub hash: B256,
+    /// EIP-2718 encoded transaction, ready for `eth_sendRawTransaction`.
+    pub raw: Bytes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedBundle {
+    pub version: u32,
+    pub transactions: Vec<SignedTransaction>,
+}
+
+impl UnsignedBundle {
+    pub fn new(transactions: Vec<UnsignedTransaction>) -> Self {
+        Self {
+            version: BUNDLE_VERSION,
+            transactions,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bundle: Self = read_json(path)?;
+        check_version(bundle.version)?;
+        Ok(bundle)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_json(path, self)
+    }
+}
+
+impl SignedBundle {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bundle: Self = read_json(path)?;
+        check_version(bundle.version)?;
+        Ok(bundle)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_json(path, self)
+    }
+
+    /// Decodes every transaction and checks that its signer, nonce and hash
+    /// match what the bundle claims.
+    pub fn verify(&self) -> Result<Vec<TxEnvelope>> {
+        self.transactions.iter().map(decode_signed).collect()
+    }
+}
+
+impl UnsignedTransaction {
+    /// Signs the transaction with `signer`, which must hold the key of
+    /// `from`.
+    pub fn sign(&self, signer: &PrivateKeySigner) -> Result<TxEnvelope> {
+        let envelope = match self.gas {
+            GasParams::Legacy { gas_price } => {
+                let mut tx = TxLegacy {
+                    chain_id: Some(self.chain_id),
+                    nonce: self.nonce,
+                    gas_price,
+                    gas_limit: self.gas_limit,
+                    to: TxKind::Call(self.to),
+                    value: self.value,
+                    input: self.input.clone(),
+                };
+                let signature = signer.sign_transaction_sync(&mut tx)?;
+                TxEnvelope::from(tx.into_signed(signature))
+            }
+            GasParams::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                let mut tx = TxEip1559 {
+                    chain_id: self.chain_id,
+                    nonce: self.nonce,
+                    gas_limit: self.gas_limit,
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                    to: TxKind::Call(self.to),
+                    value: self.value,
+                    access_list: Default::default(),
+                    input: self.input.clone(),
+                };
+                let signature = signer.sign_transaction_sync(&mut tx)?;
+                TxEnvelope::from(tx.into_signed(signature))
+            }
+        };
+        Ok(envelope)
+    }
+}
+
+/// Signs bundles with keys derived from a mnemonic. Meant to run in a
+/// separate, isolated process.
+pub struct OfflineSigner {
+    master: ProtectedKey,
+}
+
+impl OfflineSigner {
+    pub fn new(mnemonic: &Mnemonic<English>, passphrase: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            master: ProtectedKey::master(mnemonic, passphrase)?,
+        })
+    }
+
+    /// Signs every transaction of `bundle`. Fails without signing anything
+    /// if a derivation path does not lead to the declared `from` address.
+    pub fn sign_bundle(&self, bundle: &UnsignedBundle) -> Result<SignedBundle> {
+        check_version(bundle.version)?;
+
+        let master = self.master.xpriv()?;
+        let signers = bundle
+            .transactions
+            .iter()
+            .map(|tx| {
+                let key = master.derive_path(tx.derivation_path.as_str())?;
+                let signer =
+                    PrivateKeySigner::from_signing_key(AsRef::<SigningKey>::as_ref(&key).clone());
+                if signer.address() != tx.from {
+                    return Err(anyhow!(
+                        "Path {} derives {}, bundle expects {}",
+                        tx.derivation_path,
+                        signer.address(),
+                        tx.from
+                    ));
+                }
+                Ok(signer)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let transactions = bundle
+            .transactions
+            .iter()
+            .zip(&signers)
+            .map(|(tx, signer)| {
+                let envelope = tx.sign(signer)?;
+                Ok(SignedTransaction {
+                    from: tx.from,
+                    nonce: tx.nonce,
+                    hash: *envelope.tx_hash(),
+                    raw: envelope.encoded_2718().into(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(SignedBundle {
+            version: BUNDLE_VERSION,
+            transactions,
+        })
+    }
+}
+
+/// Builds an unsigned `transfer` of the USDT `contract` on chain `chain_id`
+/// from the stored address `transfer.from`, and reserves its nonce, see
+/// [`nonces`]. Needs no keys, so a watch-only instance can prepare transfers
+/// for the offline signer.
+pub async fn prepare_usdt_transfer<S: NonceSource>(
+    db: &Mutex<WalletDatabase>,
+    source: &S,
+    chain_id: u64,
+    contract: Address,
+    transfer: UsdtTransfer,
+) -> Result<UnsignedTransaction> {
+    let UsdtTransfer {
+        from,
+        to,
+        amount,
+        gas_limit,
+        gas,
+    } = transfer;
+    let record = db
+        .lock()
+        .unwrap()
+        .get_address_record(&from.to_string())?
+        .ok_or_else(|| Error::NotFound(format!("Address {} is not ours", from)))?;
+    let nonce = nonces::reserve(db, source, from).await?;
+
+    let input = IUESDT::transferCall {
+        _to: to,
+        _value: amount,
+    }
+    .abi_encode();
+
+    Ok(UnsignedTransaction {
+        from,
+        derivation_path: format!("{}{}", record.path, record.addr_index),
+        chain_id,
+        nonce,
+        to: contract,
+        value: U256::ZERO,
+        input: input.into(),
+        gas_limit,
+        gas,
+    })
+}
+
+/// Verifies `bundle` and submits every transaction. Returns the hashes in
+/// bundle order.
+pub async fn broadcast_bundle(
+    provider: &RootProvider<PubSubFrontend>,
+    bundle: &SignedBundle,
+) -> Result<Vec<B256>> {
+    bundle.verify()?;
+
+    let mut hashes = Vec::with_capacity(bundle.transactions.len());
+    for tx in &bundle.transactions {
+        let pending = provider.send_raw_transaction(&tx.raw).await?;
+        println!("Broadcast {} from {} nonce {}", tx.hash, tx.from, tx.nonce);
+        hashes.push(*pending.tx_hash());
+    }
+    Ok(hashes)
+}
+
+fn decode_signed(tx: &SignedTransaction) -> Result<TxEnvelope> {
+    let envelope = TxEnvelope::decode_2718(&mut tx.raw.as_ref())?;
+
+    let signer = envelope.recover_signer()?;
+    if signer != tx.from {
+        return Err(anyhow!(
+            "Transaction {} is signed by {}, expected {}",
+            tx.hash,
+            signer,
+            tx.from
+        ));
+    }
+    if *envelope.tx_hash() != tx.hash {
+        return Err(anyhow!(
+            "Transaction hash mismatch: {} != {}",
+            envelope.tx_hash(),
+            tx.hash
+        ));
+    }
+    if alloy::consensus::Transaction::nonce(&envelope) != tx.nonce {
+        return Err(anyhow!("Transaction {} nonce mismatch", tx.hash));
+    }
+    Ok(envelope)
+}
+
+fn check_version(version: u32) -> Result<()> {
+    if version != BUNDLE_VERSION {
+        return Err(anyhow!(
+            "Unsupported bundle version {}, expected {}",
+            version,
+            BUNDLE_VERSION
+        ));
+    }
+    Ok(())
+}
+
+fn read_json<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T> {
+    let json = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+fn write_json<T: Serialize, P: AsRef<Path>>(path: P, value: &T) -> Result<()> {
+    let json = serde_json::to_string_pretty(value)?;
+    fs::write(path, json)?;
+    Ok(())
+}
@@ -1,28 +1,221 @@
+use anyhow::{anyhow, Context, Result};
+use fs2::FileExt;
 use sha2::{Digest, Sha256};
-use std::path::PathBuf;
+use std::{
+    fs::{self, File, OpenOptions},
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
 
-#[derive(Debug)]
+use crate::config;
+
+use super::mnemonic::{LoadError, MnemonicStorage, PassphraseStorage};
+
+#[derive(Debug, PartialEq, Eq)]
 pub struct WalletPaths {
     pub mnemonic_path: PathBuf,
+    /// BIP-39 passphrase of the seed, if it has one, see
+    /// [`PassphraseStorage`].
+    pub passphrase_path: PathBuf,
     pub wallet_path: PathBuf,
+    pub lock_path: PathBuf,
+    /// Control socket of a running service, see [`crate::api::unlock`].
+    pub socket_path: PathBuf,
 }
 
-impl WalletPaths {
-    pub fn from_password(password: &str) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(password.as_bytes());
-        let result = hasher.finalize();
-        let hash = hex::encode(&result[..16]); // Use first 16 bytes for shorter filename
+/// Exclusive lock on a wallet's files, released when dropped.
+pub struct WalletLock {
+    _file: File,
+}
 
-        let pers_dir = PathBuf::from("pers");
-        std::fs::create_dir_all(&pers_dir).expect("Failed to create persistence directory");
+impl WalletPaths {
+    /// Resolves the paths with the configured data directory and wallet id.
+    pub fn from_config(secret: &str) -> Result<Self> {
+        Self::resolve(Path::new(config::data_dir()), config::wallet_id(), secret)
+    }
 
-        let mnemonic_path = pers_dir.join(format!("mnemonic_{}.dat", hash));
-        let wallet_path = pers_dir.join(format!("wallet_{}.sqlite", hash));
+    /// Picks the file names for a wallet in `data_dir`. With a `wallet_id`
+    /// the names are stable; otherwise they are derived from `secret` (the
+    /// password, or the xpub in watch-only mode), as older versions did.
+    ///
+    /// Creates `data_dir` if needed and fails if it is not writable.
+    pub fn resolve(data_dir: &Path, wallet_id: Option<&str>, secret: &str) -> Result<Self> {
+        let name = match wallet_id {
+            Some(id) => validate_wallet_id(id)?.to_string(),
+            None => secret_name(secret),
+        };
+        prepare_data_dir(data_dir)?;
+        Ok(Self::from_name(data_dir, &name))
+    }
 
+    fn from_name(data_dir: &Path, name: &str) -> Self {
         Self {
-            mnemonic_path,
-            wallet_path,
+            mnemonic_path: data_dir.join(format!("mnemonic_{}.dat", name)),
+            passphrase_path: data_dir.join(format!("passphrase_{}.dat", name)),
+            wallet_path: data_dir.join(format!("wallet_{}.sqlite", name)),
+            lock_path: data_dir.join(format!("wallet_{}.lock", name)),
+            socket_path: data_dir.join(format!("wallet_{}.sock", name)),
+        }
+    }
+
+    fn data_dir(&self) -> &Path {
+        self.wallet_path.parent().unwrap_or(Path::new("."))
+    }
+
+    /// Takes an exclusive lock so no other process opens the same wallet.
+    pub fn lock(&self) -> Result<WalletLock> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.lock_path)
+            .with_context(|| format!("Failed to open lock file {}", self.lock_path.display()))?;
+        file.try_lock_exclusive().map_err(|_| {
+            anyhow!(
+                "Wallet {} is already in use by another process",
+                self.wallet_path.display()
+            )
+        })?;
+        Ok(WalletLock { _file: file })
+    }
+
+    /// Re-encrypts the mnemonic and the stored passphrase with `new_password`
+    /// and returns the paths the wallet lives at afterwards. The caller must
+    /// hold the wallet lock.
+    ///
+    /// Without a wallet id the file names change with the password, so the
+    /// files are copied to their new names before the old ones are removed.
+    /// At every point either the old or the new password opens a complete
+    /// wallet.
+    pub fn rotate_password(
+        &self,
+        wallet_id: Option<&str>,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<Self> {
+        let old_storage = MnemonicStorage::new(self.mnemonic_path.clone());
+        let mnemonic = old_storage.load_mnemonic(old_password)?;
+        let phrase = Zeroizing::new(mnemonic.to_phrase());
+        let old_passphrase_storage = PassphraseStorage::new(self.passphrase_path.clone());
+        // An interrupted rotation may have re-encrypted it already
+        let passphrase = match old_passphrase_storage.load(old_password) {
+            Err(e) if matches!(e.downcast_ref(), Some(LoadError::DecryptionFailed)) => {
+                old_passphrase_storage.load(new_password)?
+            }
+            result => result?,
+        };
+
+        let target = Self::resolve(self.data_dir(), wallet_id, new_password)?;
+        let new_passphrase_storage = PassphraseStorage::new(target.passphrase_path.clone());
+        if target.mnemonic_path == self.mnemonic_path {
+            if let Some(passphrase) = &passphrase {
+                new_passphrase_storage.save(passphrase, new_password)?;
+            }
+            old_storage.save_mnemonic(&phrase, new_password)?;
+            return Ok(target);
+        }
+
+        // A database or passphrase without a mnemonic next to it is left over
+        // from an interrupted rotation and is overwritten.
+        if target.mnemonic_path.exists() {
+            return Err(anyhow!(
+                "A wallet for the new password already exists, refusing to overwrite it"
+            ));
+        }
+
+        if self.wallet_path.exists() {
+            fs::copy(&self.wallet_path, &target.wallet_path)?;
         }
+        if let Some(passphrase) = &passphrase {
+            new_passphrase_storage.save(passphrase, new_password)?;
+        }
+        MnemonicStorage::new(target.mnemonic_path.clone()).save_mnemonic(&phrase, new_password)?;
+
+        fs::remove_file(&self.mnemonic_path)?;
+        if self.wallet_path.exists() {
+            fs::remove_file(&self.wallet_path)?;
+        }
+        if self.passphrase_path.exists() {
+            fs::remove_file(&self.passphrase_path)?;
+        }
+        // The lock file stays: the caller still holds the lock on it, and
+        // another process could lock a fresh file in its place
+        Ok(target)
+    }
+
+    /// Renames password-derived wallet files to the names for `wallet_id`.
+    /// The caller must hold the wallet lock.
+    pub fn adopt_wallet_id(&self, wallet_id: &str) -> Result<Self> {
+        let target = Self::resolve(self.data_dir(), Some(wallet_id), "")?;
+        let exists = |path: &PathBuf| -> Result<()> {
+            if path.exists() {
+                return Err(anyhow!(
+                    "{} already exists, refusing to overwrite it",
+                    path.display()
+                ));
+            }
+            Ok(())
+        };
+
+        // Database first: if interrupted, the wallet fails to open instead of
+        // starting over on an empty database. Rerunning completes the move.
+        exists(&target.mnemonic_path)?;
+        if self.wallet_path.exists() {
+            exists(&target.wallet_path)?;
+            fs::rename(&self.wallet_path, &target.wallet_path)?;
+        }
+        if self.passphrase_path.exists() {
+            exists(&target.passphrase_path)?;
+            fs::rename(&self.passphrase_path, &target.passphrase_path)?;
+        }
+        fs::rename(&self.mnemonic_path, &target.mnemonic_path)?;
+        // The lock file stays, see `rotate_password`
+        Ok(target)
     }
 }
+
+fn validate_wallet_id(wallet_id: &str) -> Result<&str> {
+    let valid = !wallet_id.is_empty()
+        && wallet_id.len() <= 64
+        && wallet_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "Wallet id must be 1-64 characters of [A-Za-z0-9_-], got {:?}",
+            wallet_id
+        ));
+    }
+    Ok(wallet_id)
+}
+
+fn secret_name(secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(secret.as_bytes());
+    let result = hasher.finalize();
+    hex::encode(&result[..16]) // Use first 16 bytes for shorter filename
+}
+
+/// Creates `data_dir` with mode 0700, tightens the mode of an existing one,
+/// and checks that files can be created in it.
+fn prepare_data_dir(data_dir: &Path) -> Result<()> {
+    fs::create_dir_all(data_dir)
+        .with_context(|| format!("Failed to create data directory {}", data_dir.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(data_dir, fs::Permissions::from_mode(0o700)).with_context(|| {
+            format!(
+                "Failed to restrict permissions of data directory {}",
+                data_dir.display()
+            )
+        })?;
+    }
+
+    let probe = data_dir.join(".write_test");
+    File::create(&probe)
+        .and_then(|_| fs::remove_file(&probe))
+        .with_context(|| format!("Data directory {} is not writable", data_dir.display()))?;
+    Ok(())
+}
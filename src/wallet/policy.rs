@@ -0,0 +1,516 @@
+//! Guardrails for outbound USDT transfers.
+//!
+//! Every withdrawal is first recorded as a request of the operator asking
+//! for it. A request is `denied` if its destination is on
+//! `WITHDRAWAL_DENYLIST` or off a configured `WITHDRAWAL_ALLOWLIST`, if its
+//! amount exceeds `WITHDRAWAL_MAX_AMOUNT`, or if it would take the
+//! withdrawals of the last 24 hours above `WITHDRAWAL_DAILY_LIMIT`. A request
+//! above `WITHDRAWAL_APPROVAL_THRESHOLD` is `awaiting_approval` until enough
+//! operators other than the requester approve it, and `rejected` as soon as
+//! one refuses. The last approval checks the request again, so it is
+//! `denied` if the limits no longer leave room for it. Any other request is
+//! `approved` at once. Approved requests are sent and end up `executed` as
+//! soon as their transaction is recorded, or `failed` if sending did not
+//! get that far. Until then an operator can still reject or send them
+//! again, and a restart fails those a previous run left behind. A request
+//! whose address was blacklisted while it awaited approval is `denied`.
+//! The daily limit counts a request from when it was sent or approved.
+//!
+//! Each step is written to the audit log together with the operator who
+//! took it.
+
+use std::{collections::HashSet, sync::Mutex, time::Duration};
+
+use alloy::primitives::{Address, U256};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, error::Error};
+
+use super::{database::WalletDatabase, fees::Urgency};
+
+/// Operator name of `ADMIN_TOKEN`.
+pub const ADMIN_OPERATOR: &str = "admin";
+
+/// Actor recorded for decisions the policy takes on its own.
+pub const POLICY_ACTOR: &str = "policy";
+
+/// Window of `WITHDRAWAL_DAILY_LIMIT`.
+pub const DAILY_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalStatus {
+    AwaitingApproval,
+    Approved,
+    Rejected,
+    Denied,
+    Executed,
+    Failed,
+}
+
+impl WithdrawalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WithdrawalStatus::AwaitingApproval => "awaiting_approval",
+            WithdrawalStatus::Approved => "approved",
+            WithdrawalStatus::Rejected => "rejected",
+            WithdrawalStatus::Denied => "denied",
+            WithdrawalStatus::Executed => "executed",
+            WithdrawalStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "awaiting_approval" => Some(WithdrawalStatus::AwaitingApproval),
+            "approved" => Some(WithdrawalStatus::Approved),
+            "rejected" => Some(WithdrawalStatus::Rejected),
+            "denied" => Some(WithdrawalStatus::Denied),
+            "executed" => Some(WithdrawalStatus::Executed),
+            "failed" => Some(WithdrawalStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// One operator's decision on a request awaiting approval.
+#[derive(Debug, Clone, Serialize)]
+pub struct Approval {
+    pub approver: String,
+    /// `false` for a rejection.
+    pub approved: bool,
+    pub comment: Option<String>,
+    pub created_at: String,
+}
+
+/// A withdrawal as requested, with the decisions taken on it.
+#[derive(Debug, Clone, Serialize)]
+pub struct WithdrawalRequest {
+    pub id: i64,
+    pub from: String,
+    pub to: String,
+    /// USDT base units.
+    pub amount: String,
+    pub urgency: Option<Urgency>,
+    pub requested_by: String,
+    pub status: WithdrawalStatus,
+    /// Why the request was denied, rejected or failed, or how sending it
+    /// went wrong.
+    pub reason: Option<String>,
+    /// The tracked transaction, once sent.
+    pub transaction_id: Option<i64>,
+    pub approvals: Vec<Approval>,
+    pub created_at: String,
+}
+
+/// A withdrawal about to be requested.
+#[derive(Debug, Clone)]
+pub struct NewWithdrawal {
+    pub from: Address,
+    pub to: Address,
+    pub amount: U256,
+    pub urgency: Option<Urgency>,
+    pub requested_by: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Requested,
+    Denied,
+    Approved,
+    Rejected,
+    Executed,
+    Failed,
+    SpedUp,
+    Cancelled,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Requested => "requested",
+            AuditAction::Denied => "denied",
+            AuditAction::Approved => "approved",
+            AuditAction::Rejected => "rejected",
+            AuditAction::Executed => "executed",
+            AuditAction::Failed => "failed",
+            AuditAction::SpedUp => "sped_up",
+            AuditAction::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "requested" => Some(AuditAction::Requested),
+            "denied" => Some(AuditAction::Denied),
+            "approved" => Some(AuditAction::Approved),
+            "rejected" => Some(AuditAction::Rejected),
+            "executed" => Some(AuditAction::Executed),
+            "failed" => Some(AuditAction::Failed),
+            "sped_up" => Some(AuditAction::SpedUp),
+            "cancelled" => Some(AuditAction::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// Who did what to which withdrawal, and when.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor: String,
+    pub action: AuditAction,
+    pub withdrawal_id: Option<i64>,
+    pub transaction_id: Option<i64>,
+    pub details: Option<String>,
+    pub created_at: String,
+}
+
+/// What the policy makes of a new request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Approve,
+    AwaitApproval,
+    Deny(String),
+}
+
+impl Decision {
+    pub fn status(&self) -> WithdrawalStatus {
+        match self {
+            Decision::Approve => WithdrawalStatus::Approved,
+            Decision::AwaitApproval => WithdrawalStatus::AwaitingApproval,
+            Decision::Deny(_) => WithdrawalStatus::Denied,
+        }
+    }
+}
+
+/// Limits and lists withdrawals are checked against. Amounts are in USDT
+/// base units; `None` means no limit.
+#[derive(Debug, Clone)]
+pub struct WithdrawalPolicy {
+    pub max_amount: Option<U256>,
+    pub daily_limit: Option<U256>,
+    pub approval_threshold: Option<U256>,
+    pub required_approvals: u32,
+    /// Destinations allowed, any when `None`.
+    pub allowlist: Option<HashSet<Address>>,
+    pub denylist: HashSet<Address>,
+}
+
+impl WithdrawalPolicy {
+    pub fn from_config() -> Result<Self> {
+        let required_approvals = config::withdrawal_required_approvals();
+        if required_approvals == 0 {
+            return Err(anyhow!("WITHDRAWAL_REQUIRED_APPROVALS must be at least 1"));
+        }
+        Ok(Self {
+            max_amount: config::withdrawal_max_amount().map(U256::from),
+            daily_limit: config::withdrawal_daily_limit().map(U256::from),
+            approval_threshold: config::withdrawal_approval_threshold().map(U256::from),
+            required_approvals,
+            allowlist: config::withdrawal_allowlist()
+                .map(parse_addresses)
+                .transpose()
+                .context("Invalid WITHDRAWAL_ALLOWLIST")?,
+            denylist: parse_addresses(config::withdrawal_denylist().unwrap_or(""))
+                .context("Invalid WITHDRAWAL_DENYLIST")?,
+        })
+    }
+
+    /// Decides on a withdrawal of `amount` to `to`, given the total
+    /// `withdrawn` in the last 24 hours.
+    pub fn evaluate(&self, to: Address, amount: U256, withdrawn: U256) -> Decision {
+        if self.denylist.contains(&to) {
+            return Decision::Deny(format!("Destination {} is on the denylist", to));
+        }
+        if self
+            .allowlist
+            .as_ref()
+            .is_some_and(|allowlist| !allowlist.contains(&to))
+        {
+            return Decision::Deny(format!("Destination {} is not on the allowlist", to));
+        }
+        if let Some(max_amount) = self.max_amount.filter(|max| amount > *max) {
+            return Decision::Deny(format!(
+                "Amount {} exceeds the limit of {} per withdrawal",
+                amount, max_amount
+            ));
+        }
+        if let Some(daily_limit) = self
+            .daily_limit
+            .filter(|limit| withdrawn.saturating_add(amount) > *limit)
+        {
+            return Decision::Deny(format!(
+                "Amount {} on top of {} withdrawn in the last 24 hours exceeds the daily limit of {}",
+                amount, withdrawn, daily_limit
+            ));
+        }
+        if self
+            .approval_threshold
+            .is_some_and(|threshold| amount > threshold)
+        {
+            return Decision::AwaitApproval;
+        }
+        Decision::Approve
+    }
+}
+
+/// Records a request for `withdrawal` and decides on it. A denied request
+/// is recorded too, but returned as [`Error::PolicyViolation`].
+pub fn request(
+    db: &Mutex<WalletDatabase>,
+    policy: &WithdrawalPolicy,
+    withdrawal: &NewWithdrawal,
+) -> Result<WithdrawalRequest> {
+    // Held until the request is stored, so concurrent requests cannot both
+    // fit under the daily limit
+    let db = db.lock().unwrap();
+    let withdrawn = db.get_withdrawn_amount(DAILY_WINDOW, None)?;
+    let decision = policy.evaluate(withdrawal.to, withdrawal.amount, withdrawn);
+    let reason = match &decision {
+        Decision::Deny(reason) => Some(reason.as_str()),
+        _ => None,
+    };
+    let id = db.insert_withdrawal_request(withdrawal, decision.status(), reason)?;
+    println!(
+        "Withdrawal request {} by {} is {}",
+        id,
+        withdrawal.requested_by,
+        decision.status().as_str()
+    );
+
+    if let Decision::Deny(reason) = decision {
+        return Err(Error::PolicyViolation(reason).into());
+    }
+    get(&db, id)
+}
+
+/// Records a request for `withdrawal` that was denied for `reason` before
+/// the policy was asked, and returns the [`Error::PolicyViolation`] to fail
+/// with.
+pub fn deny(
+    db: &Mutex<WalletDatabase>,
+    withdrawal: &NewWithdrawal,
+    reason: String,
+) -> anyhow::Error {
+    let recorded = db.lock().unwrap().insert_withdrawal_request(
+        withdrawal,
+        WithdrawalStatus::Denied,
+        Some(&reason),
+    );
+    match recorded {
+        Ok(id) => {
+            println!(
+                "Withdrawal request {} by {} is denied: {}",
+                id, withdrawal.requested_by, reason
+            );
+            Error::PolicyViolation(reason).into()
+        }
+        Err(e) => e,
+    }
+}
+
+/// Denies the approved request `id` for `reason` found before sending it,
+/// e.g. an address blacklisted while it awaited approval, and returns the
+/// [`Error::PolicyViolation`] to fail with.
+pub fn revoke(db: &Mutex<WalletDatabase>, id: i64, reason: String) -> anyhow::Error {
+    match db.lock().unwrap().deny_withdrawal_request(id, &reason) {
+        Ok(()) => {
+            println!("Withdrawal request {} is denied: {}", id, reason);
+            Error::PolicyViolation(reason).into()
+        }
+        Err(e) => e,
+    }
+}
+
+/// Records `approver`'s approval of request `id`. The request is
+/// `approved` once it has the approvals the policy requires, unless the
+/// policy, asked again, now denies it.
+pub fn approve(
+    db: &Mutex<WalletDatabase>,
+    policy: &WithdrawalPolicy,
+    id: i64,
+    approver: &str,
+    comment: Option<&str>,
+) -> Result<WithdrawalRequest> {
+    let db = db.lock().unwrap();
+    let request = awaiting_approval(&db, id)?;
+    if request.requested_by == approver {
+        return Err(Error::PolicyViolation(String::from(
+            "Operators cannot approve their own withdrawals",
+        ))
+        .into());
+    }
+    if request
+        .approvals
+        .iter()
+        .any(|approval| approval.approver == approver)
+    {
+        return Err(Error::Conflict(format!(
+            "{} already approved withdrawal request {}",
+            approver, id
+        ))
+        .into());
+    }
+
+    let approvals = request.approvals.len() as u32 + 1;
+    let last = approvals >= policy.required_approvals;
+    if last {
+        // What was withdrawn while the request waited may leave no room for it
+        let withdrawn = db.get_withdrawn_amount(DAILY_WINDOW, Some(id))?;
+        let amount = U256::from_str_radix(&request.amount, 10)?;
+        if let Decision::Deny(reason) = policy.evaluate(request.to.parse()?, amount, withdrawn) {
+            let denied = WithdrawalStatus::Denied;
+            db.add_withdrawal_approval(id, approver, true, comment, denied, Some(&reason))?;
+            println!("Withdrawal request {} is denied: {}", id, reason);
+            return Err(Error::PolicyViolation(reason).into());
+        }
+    }
+    let status = if last {
+        WithdrawalStatus::Approved
+    } else {
+        WithdrawalStatus::AwaitingApproval
+    };
+    db.add_withdrawal_approval(id, approver, true, comment, status, None)?;
+    println!(
+        "Withdrawal request {} approved by {} ({} of {})",
+        id, approver, approvals, policy.required_approvals
+    );
+    get(&db, id)
+}
+
+/// Rejects request `id` for good on behalf of `operator`, who may be its
+/// requester withdrawing it. An approved request can be rejected until it
+/// is sent.
+pub fn reject(
+    db: &Mutex<WalletDatabase>,
+    id: i64,
+    operator: &str,
+    comment: Option<&str>,
+) -> Result<WithdrawalRequest> {
+    let db = db.lock().unwrap();
+    let request = get(&db, id)?;
+    if !matches!(
+        request.status,
+        WithdrawalStatus::AwaitingApproval | WithdrawalStatus::Approved
+    ) {
+        return Err(Error::Conflict(format!(
+            "Withdrawal request {} is {}, it can no longer be rejected",
+            id,
+            request.status.as_str()
+        ))
+        .into());
+    }
+    db.add_withdrawal_approval(
+        id,
+        operator,
+        false,
+        comment,
+        WithdrawalStatus::Rejected,
+        None,
+    )?;
+    println!("Withdrawal request {} rejected by {}", id, operator);
+    get(&db, id)
+}
+
+/// Records on behalf of `actor` that sending the approved request `id`
+/// failed with `error`. A request whose transaction was recorded is
+/// already `executed` and stays so, as the funds may still move.
+pub fn fail(db: &Mutex<WalletDatabase>, id: i64, actor: &str, error: &anyhow::Error) -> Result<()> {
+    let db = db.lock().unwrap();
+    if get(&db, id)?.status != WithdrawalStatus::Approved {
+        return Ok(());
+    }
+    db.settle_withdrawal_request(id, actor, None, Some(&format!("{:#}", error)))?;
+    println!("Withdrawal request {} failed: {:#}", id, error);
+    Ok(())
+}
+
+/// Fails the requests a previous run approved but stopped before sending.
+/// None of them has a transaction, so none moved any funds.
+pub fn recover(db: &Mutex<WalletDatabase>) -> Result<Vec<i64>> {
+    let db = db.lock().unwrap();
+    let mut failed = Vec::new();
+    for request in db.list_withdrawal_requests(Some(WithdrawalStatus::Approved), u32::MAX)? {
+        let reason = "Interrupted before it was sent";
+        db.settle_withdrawal_request(request.id, POLICY_ACTOR, None, Some(reason))?;
+        println!("Withdrawal request {} failed: {}", request.id, reason);
+        failed.push(request.id);
+    }
+    Ok(failed)
+}
+
+/// An operator allowed to request and approve withdrawals.
+pub struct Operator {
+    pub name: String,
+    pub token: String,
+}
+
+/// Parses `spec`, a comma separated list of `name=token` pairs.
+pub fn parse_operators(spec: Option<&str>) -> Result<Vec<Operator>> {
+    let mut operators: Vec<Operator> = Vec::new();
+    for entry in spec.unwrap_or("").split(',').map(str::trim) {
+        if entry.is_empty() {
+            continue;
+        }
+        let (name, token) = entry
+            .split_once('=')
+            .map(|(name, token)| (name.trim(), token.trim()))
+            .filter(|(name, token)| !name.is_empty() && !token.is_empty())
+            .ok_or_else(|| anyhow!("Operators must have the form name=token"))?;
+        if name == ADMIN_OPERATOR || name == POLICY_ACTOR {
+            return Err(anyhow!("Operator name {} is reserved", name));
+        }
+        if operators
+            .iter()
+            .any(|other| other.name == name || other.token == token)
+        {
+            return Err(anyhow!(
+                "Operator {} must have a distinct name and token",
+                name
+            ));
+        }
+        operators.push(Operator {
+            name: name.to_string(),
+            token: token.to_string(),
+        });
+    }
+    Ok(operators)
+}
+
+/// The operators configured with `OPERATORS`.
+pub fn operators_from_config() -> Result<Vec<Operator>> {
+    parse_operators(config::operators())
+}
+
+fn awaiting_approval(db: &WalletDatabase, id: i64) -> Result<WithdrawalRequest> {
+    let request = get(db, id)?;
+    if request.status != WithdrawalStatus::AwaitingApproval {
+        return Err(Error::Conflict(format!(
+            "Withdrawal request {} is {}, not awaiting approval",
+            id,
+            request.status.as_str()
+        ))
+        .into());
+    }
+    Ok(request)
+}
+
+fn get(db: &WalletDatabase, id: i64) -> Result<WithdrawalRequest> {
+    db.get_withdrawal_request(id)?
+        .ok_or_else(|| Error::NotFound(format!("Withdrawal request {} not found", id)).into())
+}
+
+fn parse_addresses(spec: &str) -> Result<HashSet<Address>> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| {
+            address
+                .parse()
+                .map_err(|_| anyhow!("{} is not an address", address))
+        })
+        .collect()
+}
@@ -0,0 +1,206 @@
+use alloy::signers::local::coins_bip39::{English, Mnemonic};
+use anyhow::{anyhow, Result};
+use zeroize::Zeroizing;
+
+use crate::error::Error;
+
+use super::{
+    accounts::Account,
+    database::{AddressRecord, WalletDatabase, FIRST_ADDRESS_INDEX},
+    derivation::AddressDeriver,
+    keys::ProtectedKey,
+    mnemonic::MnemonicStorage,
+};
+
+/// `wallet_meta` key of the seed fingerprint.
+const FINGERPRINT_KEY: &str = "fingerprint";
+
+/// Upper bound on the number of addresses re-derived by one rescan.
+pub const MAX_RESCAN_COUNT: u32 = 100_000;
+
+/// Parses a user supplied BIP-39 phrase, tolerating extra whitespace and
+/// upper case letters. The checksum word is verified.
+pub fn parse_phrase(phrase: &str) -> Result<Mnemonic<English>> {
+    // Built in place so no intermediate copies of the words are left behind
+    let mut normalized = Zeroizing::new(String::with_capacity(phrase.len()));
+    for word in phrase.split_whitespace() {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        normalized.extend(word.chars().flat_map(char::to_lowercase));
+    }
+
+    match normalized.split(' ').count() {
+        12 | 15 | 18 | 21 | 24 => {}
+        n => {
+            return Err(Error::InvalidInput(format!(
+                "Mnemonic must have 12, 15, 18, 21 or 24 words, got {}",
+                n
+            ))
+            .into())
+        }
+    }
+
+    Mnemonic::<English>::new_from_phrase(&normalized).map_err(|_| {
+        Error::InvalidInput(String::from(
+            "Mnemonic contains unknown words or has an invalid checksum",
+        ))
+        .into()
+    })
+}
+
+/// Fails if any address already stored under `path` is not derived by
+/// `deriver`, i.e. if the database belongs to a different seed.
+pub fn verify_stored_addresses(
+    db: &WalletDatabase,
+    deriver: &AddressDeriver,
+    path: &str,
+) -> Result<()> {
+    for (address, index) in db.get_all_addresses_by_path(path)? {
+        if deriver.derive(index)?.to_string() != address {
+            return Err(Error::Conflict(format!(
+                "Stored address {} at index {} was not derived from this mnemonic and passphrase",
+                address, index
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Re-derives the first `count` addresses of `account` and stores the ones
+/// missing from the database. Returns the number of addresses added.
+pub fn rebuild_addresses(
+    db: &WalletDatabase,
+    deriver: &AddressDeriver,
+    account: &Account,
+    count: u32,
+) -> Result<u32> {
+    if count > MAX_RESCAN_COUNT {
+        return Err(Error::InvalidInput(format!(
+            "Rescan count must not exceed {}",
+            MAX_RESCAN_COUNT
+        ))
+        .into());
+    }
+
+    let mut missing = Vec::new();
+    for index in FIRST_ADDRESS_INDEX..FIRST_ADDRESS_INDEX + count {
+        if db
+            .get_address_by_path_and_index(&account.path, index)?
+            .is_some()
+        {
+            continue;
+        }
+        missing.push(AddressRecord {
+            address: deriver.derive(index)?.to_string(),
+            account: account.name.clone(),
+            path: account.path.clone(),
+            addr_index: index,
+            customer_id: None,
+            label: None,
+            idempotency_key: None,
+        });
+    }
+
+    db.store_addresses(&missing)?;
+    Ok(missing.len() as u32)
+}
+
+/// Validates `phrase`, checks the seed of `phrase` and `passphrase` against
+/// the fingerprint and addresses already in `db`, encrypts the phrase into
+/// `storage` and re-derives the first `rescan_count` addresses of every
+/// account. Storing the passphrase is up to the caller, see
+/// [`PassphraseStorage`]. The first account is the receiving one the seed is
+/// fingerprinted with.
+///
+/// [`PassphraseStorage`]: super::mnemonic::PassphraseStorage
+///
+/// Returns the keys of `accounts`, in order, and the number of addresses
+/// added.
+pub fn import_mnemonic(
+    storage: &MnemonicStorage,
+    db: &WalletDatabase,
+    password: &str,
+    phrase: &str,
+    passphrase: Option<&str>,
+    accounts: &[Account],
+    rescan_count: u32,
+) -> Result<(Vec<ProtectedKey>, u32)> {
+    let mnemonic = parse_phrase(phrase)?;
+    let keys = accounts
+        .iter()
+        .map(|account| ProtectedKey::account(&mnemonic, passphrase, &account.path))
+        .collect::<Result<Vec<_>>>()?;
+    let derivers = keys
+        .iter()
+        .map(ProtectedKey::deriver)
+        .collect::<Result<Vec<_>>>()?;
+    let receiving = derivers
+        .first()
+        .ok_or_else(|| anyhow!("No derivation account configured"))?;
+
+    for (account, deriver) in accounts.iter().zip(&derivers) {
+        verify_stored_addresses(db, deriver, &account.path)?;
+    }
+    record_fingerprint(db, receiving)?;
+    storage.save_mnemonic(&Zeroizing::new(mnemonic.to_phrase()), password)?;
+    let mut restored = 0;
+    for (account, deriver) in accounts.iter().zip(&derivers) {
+        restored += rebuild_addresses(db, deriver, account, rescan_count)?;
+    }
+
+    Ok((keys, restored))
+}
+
+/// The address at index 0 of the receiving path. Identifies the seed a
+/// database belongs to without revealing anything about issued addresses.
+pub fn fingerprint(deriver: &AddressDeriver) -> Result<String> {
+    Ok(deriver.derive(0)?.to_string())
+}
+
+/// Records the fingerprint of the seed behind `deriver` in a database that
+/// has none. Fails with [`Error::Conflict`] if `db` belongs to another seed,
+/// so neither `init` nor an import can switch a database to a new seed.
+pub fn record_fingerprint(db: &WalletDatabase, deriver: &AddressDeriver) -> Result<()> {
+    let expected = fingerprint(deriver)?;
+    match db.get_meta(FINGERPRINT_KEY)? {
+        Some(stored) if stored == expected => Ok(()),
+        Some(stored) => Err(Error::Conflict(format!(
+            "Wallet database belongs to seed {}, but the loaded seed is {}. Check the mnemonic and BIP-39 passphrase",
+            stored, expected
+        ))
+        .into()),
+        None => db.set_meta(FINGERPRINT_KEY, &expected),
+    }
+}
+
+/// Fails with [`Error::Conflict`] if `db` already belongs to a seed, so a
+/// new seed is never created for it.
+pub fn check_unclaimed(db: &WalletDatabase) -> Result<()> {
+    if let Some(stored) = db.get_meta(FINGERPRINT_KEY)? {
+        return Err(Error::Conflict(format!(
+            "Wallet database belongs to seed {}, import its mnemonic instead",
+            stored
+        ))
+        .into());
+    }
+    if !db.get_all_addresses()?.is_empty() {
+        return Err(Error::Conflict(String::from(
+            "Wallet database holds addresses of another seed, import its mnemonic instead",
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Checks at startup that `db` was created for the seed behind `deriver`.
+///
+/// Databases from before fingerprints were recorded are checked against
+/// their stored addresses once and then fingerprinted.
+pub fn check_fingerprint(db: &WalletDatabase, deriver: &AddressDeriver, path: &str) -> Result<()> {
+    if db.get_meta(FINGERPRINT_KEY)?.is_none() {
+        verify_stored_addresses(db, deriver, path)?;
+    }
+    record_fingerprint(db, deriver)
+}
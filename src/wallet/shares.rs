@@ -0,0 +1,346 @@
+//! Shamir secret sharing of the seed, so no single file and password can
+//! lose or leak the wallet.
+//!
+//! The format is specific to ethserv. It is not SLIP-39, so the shares only
+//! work with the `recover-ethserv-shares` command, not with other wallets or
+//! hardware devices. Their words are from the BIP-39 list but are no
+//! mnemonic.
+//!
+//! The BIP-39 entropy is split byte-wise over GF(256). Each share is
+//! serialized as
+//!
+//! ```text
+//! version (1) | set id (2) | threshold (1) | index (1) | length (1) | value (length) | checksum (4)
+//! ```
+//!
+//! where the checksum is the start of the SHA-256 of everything before it,
+//! and printed either as BIP-39 English words or as an upper case hex string
+//! that fits the QR alphanumeric mode.
+
+use std::{fmt, str::FromStr};
+
+use alloy::signers::local::coins_bip39::{English, Mnemonic, Wordlist};
+use anyhow::{anyhow, Result};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::error::Error;
+
+pub const SHARE_VERSION: u8 = 1;
+
+const QR_PREFIX: &str = "ETHSERV-SHARE:";
+const HEADER_LEN: usize = 6;
+const CHECKSUM_LEN: usize = 4;
+
+/// One share of a seed split with [`split`].
+pub struct Share {
+    set_id: [u8; 2],
+    threshold: u8,
+    index: u8,
+    value: Zeroizing<Vec<u8>>,
+}
+
+impl Share {
+    /// Number of shares needed to recover the seed.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Position of the share in its set, starting at 1.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// The share as BIP-39 English words.
+    pub fn to_words(&self) -> Zeroizing<String> {
+        let words = to_indices(&self.to_bytes())
+            .into_iter()
+            .map(|index| English::get_all()[index as usize])
+            .collect::<Vec<_>>();
+        Zeroizing::new(words.join(" "))
+    }
+
+    /// The share as a string for QR codes.
+    pub fn to_qr_string(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "{}{}",
+            QR_PREFIX,
+            hex::encode_upper(self.to_bytes().as_slice())
+        ))
+    }
+
+    fn to_bytes(&self) -> Zeroizing<Vec<u8>> {
+        let mut bytes = Zeroizing::new(Vec::with_capacity(
+            HEADER_LEN + self.value.len() + CHECKSUM_LEN,
+        ));
+        bytes.push(SHARE_VERSION);
+        bytes.extend_from_slice(&self.set_id);
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.push(self.value.len() as u8);
+        bytes.extend_from_slice(&self.value);
+        let checksum = Sha256::digest(bytes.as_slice());
+        bytes.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let invalid = |msg: &str| -> anyhow::Error { Error::InvalidInput(msg.to_string()).into() };
+
+        if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+            return Err(invalid("Share is too short"));
+        }
+        if bytes[0] != SHARE_VERSION {
+            return Err(invalid("Unsupported share version"));
+        }
+        let len = bytes[5] as usize;
+        if bytes.len() != HEADER_LEN + len + CHECKSUM_LEN {
+            return Err(invalid("Share has the wrong length"));
+        }
+        let (body, checksum) = bytes.split_at(HEADER_LEN + len);
+        if Sha256::digest(body)[..CHECKSUM_LEN] != *checksum {
+            return Err(invalid("Share checksum does not match, check for typos"));
+        }
+
+        if bytes[3] < 2 || bytes[4] == 0 {
+            return Err(invalid("Share has an invalid threshold or index"));
+        }
+
+        Ok(Self {
+            set_id: [bytes[1], bytes[2]],
+            threshold: bytes[3],
+            index: bytes[4],
+            value: Zeroizing::new(body[HEADER_LEN..].to_vec()),
+        })
+    }
+}
+
+impl FromStr for Share {
+    type Err = anyhow::Error;
+
+    /// Parses either form written by [`Share::to_words`] and
+    /// [`Share::to_qr_string`].
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.len() > QR_PREFIX.len() && s[..QR_PREFIX.len()].eq_ignore_ascii_case(QR_PREFIX) {
+            let bytes = Zeroizing::new(
+                hex::decode(&s[QR_PREFIX.len()..])
+                    .map_err(|_| Error::InvalidInput(String::from("Share is not valid hex")))?,
+            );
+            return Self::from_bytes(&bytes);
+        }
+
+        let indices = s
+            .split_whitespace()
+            .map(|word| {
+                English::get_index(&word.to_lowercase())
+                    .map(|index| index as u16)
+                    .map_err(|_| Error::InvalidInput(format!("Unknown word in share: {}", word)))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        // The header fixes the length; decoding it again rejects extra words
+        // and non-zero padding.
+        let header = from_indices(&indices, HEADER_LEN)?;
+        let bytes = from_indices(&indices, HEADER_LEN + header[5] as usize + CHECKSUM_LEN)?;
+        if to_indices(&bytes) != indices {
+            return Err(
+                Error::InvalidInput(String::from("Share has the wrong number of words")).into(),
+            );
+        }
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl fmt::Debug for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Share")
+            .field("set_id", &hex::encode(self.set_id))
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Splits the entropy of `mnemonic` into `count` shares, any `threshold` of
+/// which recover it.
+pub fn split(mnemonic: &Mnemonic<English>, threshold: u8, count: u8) -> Result<Vec<Share>> {
+    if threshold < 2 || threshold > count {
+        return Err(Error::InvalidInput(format!(
+            "Threshold must be between 2 and the number of shares, got {} of {}",
+            threshold, count
+        ))
+        .into());
+    }
+
+    let secret = entropy(mnemonic)?;
+    let mut set_id = [0u8; 2];
+    OsRng.fill_bytes(&mut set_id);
+
+    let mut shares = (1..=count)
+        .map(|index| Share {
+            set_id,
+            threshold,
+            index,
+            value: Zeroizing::new(Vec::with_capacity(secret.len())),
+        })
+        .collect::<Vec<_>>();
+
+    // A random polynomial per byte with the secret byte as constant term
+    let mut coefficients = Zeroizing::new(vec![0u8; threshold as usize]);
+    for &byte in secret.iter() {
+        coefficients[0] = byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+        for share in &mut shares {
+            let y = coefficients
+                .iter()
+                .rev()
+                .fold(0u8, |acc, &c| gf_mul(acc, share.index) ^ c);
+            share.value.push(y);
+        }
+    }
+    Ok(shares)
+}
+
+/// Recovers the mnemonic from at least `threshold` shares of one set.
+pub fn combine(shares: &[Share]) -> Result<Mnemonic<English>> {
+    let first = shares
+        .first()
+        .ok_or_else(|| Error::InvalidInput(String::from("No shares given")))?;
+    for share in shares {
+        if share.set_id != first.set_id
+            || share.threshold != first.threshold
+            || share.value.len() != first.value.len()
+        {
+            return Err(
+                Error::InvalidInput(String::from("Shares belong to different sets")).into(),
+            );
+        }
+    }
+    for (i, share) in shares.iter().enumerate() {
+        if shares[..i].iter().any(|other| other.index == share.index) {
+            return Err(
+                Error::InvalidInput(format!("Share {} was given twice", share.index)).into(),
+            );
+        }
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(Error::InvalidInput(format!(
+            "{} shares are needed, got {}",
+            first.threshold,
+            shares.len()
+        ))
+        .into());
+    }
+
+    // Lagrange interpolation at x = 0
+    let shares = &shares[..first.threshold as usize];
+    let mut secret = Zeroizing::new(vec![0u8; first.value.len()]);
+    for (i, share) in shares.iter().enumerate() {
+        let mut basis = 1u8;
+        for (j, other) in shares.iter().enumerate() {
+            if i != j {
+                basis = gf_mul(
+                    basis,
+                    gf_mul(other.index, gf_inv(other.index ^ share.index)),
+                );
+            }
+        }
+        for (byte, &y) in secret.iter_mut().zip(share.value.iter()) {
+            *byte ^= gf_mul(y, basis);
+        }
+    }
+
+    from_entropy(&secret)
+}
+
+/// The BIP-39 entropy of `mnemonic`, i.e. the phrase without its checksum.
+fn entropy(mnemonic: &Mnemonic<English>) -> Result<Zeroizing<Vec<u8>>> {
+    let phrase = Zeroizing::new(mnemonic.to_phrase());
+    let indices = phrase
+        .split(' ')
+        .map(|word| Ok(English::get_index(word)? as u16))
+        .collect::<Result<Vec<_>>>()?;
+    from_indices(&indices, indices.len() * 4 / 3)
+}
+
+fn from_entropy(entropy: &[u8]) -> Result<Mnemonic<English>> {
+    let mut bytes = Zeroizing::new(entropy.to_vec());
+    bytes.push(Sha256::digest(entropy)[0]);
+    let word_count = entropy.len() * 3 / 4;
+    let words = to_indices(&bytes)
+        .into_iter()
+        .take(word_count)
+        .map(|index| English::get_all()[index as usize])
+        .collect::<Vec<_>>();
+    let phrase = Zeroizing::new(words.join(" "));
+    Mnemonic::<English>::new_from_phrase(&phrase)
+        .map_err(|_| anyhow!("Recovered secret is not a valid seed"))
+}
+
+/// Splits `bytes` into big-endian 11-bit word indices, zero padding the
+/// last one.
+fn to_indices(bytes: &[u8]) -> Vec<u16> {
+    let mut indices = Vec::with_capacity((bytes.len() * 8).div_ceil(11));
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for &byte in bytes {
+        acc = (acc << 8) | byte as u32;
+        bits += 8;
+        if bits >= 11 {
+            bits -= 11;
+            indices.push(((acc >> bits) & 0x7ff) as u16);
+        }
+        acc &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        indices.push(((acc << (11 - bits)) & 0x7ff) as u16);
+    }
+    indices
+}
+
+/// Reads the first `len` bytes back from 11-bit word indices.
+fn from_indices(indices: &[u16], len: usize) -> Result<Zeroizing<Vec<u8>>> {
+    let mut bytes = Zeroizing::new(Vec::with_capacity(len));
+    let (mut acc, mut bits) = (0u32, 0u32);
+    for &index in indices {
+        if bytes.len() == len {
+            break;
+        }
+        acc = (acc << 11) | index as u32;
+        bits += 11;
+        while bits >= 8 && bytes.len() < len {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+        acc &= (1 << bits) - 1;
+    }
+    if bytes.len() < len {
+        return Err(Error::InvalidInput(String::from("Share is missing words")).into());
+    }
+    Ok(bytes)
+}
+
+/// Multiplication in GF(256) with the AES polynomial, without branching on
+/// the operands.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// Inverse in GF(256), computed as `a^254`.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut power = a;
+    for _ in 0..7 {
+        power = gf_mul(power, power);
+        result = gf_mul(result, power);
+    }
+    result
+}
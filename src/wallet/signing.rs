@@ -0,0 +1,90 @@
+//! Message signing with the key of an issued address, to prove control of a
+//! deposit address to exchanges and compliance partners.
+//!
+//! Supports EIP-191 `personal_sign` messages and EIP-712 typed data.
+
+use alloy::{
+    primitives::{eip191_hash_message, Address, Bytes, PrimitiveSignature, B256},
+    signers::{local::PrivateKeySigner, SignerSync},
+};
+use alloy_dyn_abi::TypedData;
+use anyhow::{anyhow, Result};
+use coins_bip32::{ecdsa::SigningKey, xkeys::Parent};
+use serde::Serialize;
+
+use crate::error::Error;
+
+use super::{database::AddressRecord, keys::ProtectedKey};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SignedMessage {
+    pub address: Address,
+    /// The digest that was signed.
+    pub hash: B256,
+    /// 65 byte `r || s || v` signature with `v` of 27 or 28.
+    pub signature: Bytes,
+}
+
+/// Signer for the address of `record`, derived from `account_key`, the key
+/// at the record's account path.
+pub fn signer_for(account_key: &ProtectedKey, record: &AddressRecord) -> Result<PrivateKeySigner> {
+    let key = account_key.xpriv()?.derive_child(record.addr_index)?;
+    let signer = PrivateKeySigner::from_signing_key(AsRef::<SigningKey>::as_ref(&key).clone());
+    if signer.address().to_string() != record.address {
+        return Err(anyhow!(
+            "Path {}{} derives {}, database has {}",
+            record.path,
+            record.addr_index,
+            signer.address(),
+            record.address
+        ));
+    }
+    Ok(signer)
+}
+
+/// Signs `message` as EIP-191 `personal_sign` does.
+pub fn sign_message(signer: &PrivateKeySigner, message: &[u8]) -> Result<SignedMessage> {
+    let signature = signer.sign_message_sync(message)?;
+    Ok(signed(signer, eip191_hash_message(message), signature))
+}
+
+/// Signs EIP-712 `typed_data`.
+pub fn sign_typed_data(signer: &PrivateKeySigner, typed_data: &TypedData) -> Result<SignedMessage> {
+    let hash = typed_data
+        .eip712_signing_hash()
+        .map_err(|e| Error::InvalidInput(format!("Invalid typed data: {}", e)))?;
+    let signature = signer.sign_hash_sync(&hash)?;
+    Ok(signed(signer, hash, signature))
+}
+
+/// Recovers the signer of an EIP-191 `personal_sign` signature.
+pub fn recover_message_signer(message: &[u8], signature: &[u8]) -> Result<Address> {
+    recover(eip191_hash_message(message), signature)
+}
+
+/// Recovers the signer of an EIP-712 signature over `typed_data`.
+pub fn recover_typed_data_signer(typed_data: &TypedData, signature: &[u8]) -> Result<Address> {
+    let hash = typed_data
+        .eip712_signing_hash()
+        .map_err(|e| Error::InvalidInput(format!("Invalid typed data: {}", e)))?;
+    recover(hash, signature)
+}
+
+fn signed(signer: &PrivateKeySigner, hash: B256, signature: PrimitiveSignature) -> SignedMessage {
+    SignedMessage {
+        address: signer.address(),
+        hash,
+        signature: signature.as_bytes().to_vec().into(),
+    }
+}
+
+fn recover(hash: B256, signature: &[u8]) -> Result<Address> {
+    let invalid = || Error::InvalidInput(String::from("Invalid signature"));
+    if signature.len() != 65 {
+        return Err(invalid().into());
+    }
+    let signature = PrimitiveSignature::try_from(signature).map_err(|_| invalid())?;
+    Ok(signature
+        .recover_address_from_prehash(&hash)
+        .map_err(|_| invalid())?)
+}
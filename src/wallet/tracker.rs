@@ -0,0 +1,309 @@
+//! Follows the transactions the service broadcasts until they are final.
+//!
+//! A transaction is `pending` until one of its attempts (the original or a
+//! speed-up or cancel replacing it) has a receipt, then `mined`, and
+//! `confirmed` once buried under enough blocks. It ends up `cancelled`
+//! instead if the mined attempt was a cancel, and `failed` if it reverted.
+//! A transaction is `dropped` when another transaction took its nonce, or
+//! when the node forgot it and re-broadcasting did not bring it back.
+
+use std::{future::Future, sync::Mutex};
+
+use alloy::{
+    primitives::{Address, Bytes, B256},
+    providers::{Provider, RootProvider},
+    pubsub::PubSubFrontend,
+    transports::TransportError,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::pubsub::{ChainEvent, WithdrawalEvent};
+
+use super::{database::WalletDatabase, nonces::NonceSource, offline::UnsignedTransaction};
+
+/// Broadcasts of one attempt after which a transaction the node keeps
+/// forgetting is given up as dropped.
+pub const MAX_BROADCASTS: u32 = 5;
+
+/// Gas limit of a cancel, a plain transfer to self.
+pub const CANCEL_GAS_LIMIT: u64 = 21_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxStatus {
+    Pending,
+    Mined,
+    Confirmed,
+    Failed,
+    Dropped,
+    Cancelled,
+}
+
+impl TxStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxStatus::Pending => "pending",
+            TxStatus::Mined => "mined",
+            TxStatus::Confirmed => "confirmed",
+            TxStatus::Failed => "failed",
+            TxStatus::Dropped => "dropped",
+            TxStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "pending" => Some(TxStatus::Pending),
+            "mined" => Some(TxStatus::Mined),
+            "confirmed" => Some(TxStatus::Confirmed),
+            "failed" => Some(TxStatus::Failed),
+            "dropped" => Some(TxStatus::Dropped),
+            "cancelled" => Some(TxStatus::Cancelled),
+            _ => None,
+        }
+    }
+
+    /// `true` once the status can no longer change.
+    pub fn is_final(&self) -> bool {
+        !matches!(self, TxStatus::Pending | TxStatus::Mined)
+    }
+}
+
+/// A transaction the service broadcast, with its latest attempt.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackedTransaction {
+    pub id: i64,
+    /// The latest attempt. A cancel replaces it with a transfer to self.
+    pub tx: UnsignedTransaction,
+    pub tx_hash: String,
+    /// Receiver and amount in USDT base units of a withdrawal.
+    pub recipient: Option<String>,
+    pub amount: Option<String>,
+    pub status: TxStatus,
+    pub block_number: Option<u64>,
+    /// Broadcasts of the latest attempt.
+    pub broadcasts: u32,
+    /// `true` once a cancel was sent.
+    pub cancel_requested: bool,
+}
+
+impl TrackedTransaction {
+    pub fn event(&self) -> ChainEvent {
+        let event = self.event_payload();
+        match self.status {
+            TxStatus::Pending => ChainEvent::WithdrawalPending(event),
+            TxStatus::Mined => ChainEvent::WithdrawalMined(event),
+            TxStatus::Confirmed => ChainEvent::WithdrawalConfirmed(event),
+            TxStatus::Failed => ChainEvent::WithdrawalFailed(event),
+            TxStatus::Dropped => ChainEvent::WithdrawalDropped(event),
+            TxStatus::Cancelled => ChainEvent::WithdrawalCancelled(event),
+        }
+    }
+
+    /// Event for a speed-up or cancel that replaced the previous attempt.
+    pub fn replaced_event(&self) -> ChainEvent {
+        ChainEvent::WithdrawalReplaced(self.event_payload())
+    }
+
+    fn event_payload(&self) -> WithdrawalEvent {
+        WithdrawalEvent {
+            id: self.id,
+            tx_hash: self.tx_hash.clone(),
+            from: self.tx.from.to_string(),
+            nonce: self.tx.nonce,
+            recipient: self.recipient.clone(),
+            amount: self.amount.clone(),
+            status: self.status.as_str().to_string(),
+            block_number: self.block_number,
+        }
+    }
+}
+
+/// One signed version of a tracked transaction.
+#[derive(Debug, Clone)]
+pub struct TxAttempt {
+    pub tx_hash: String,
+    pub cancel: bool,
+}
+
+/// Receipt data that decides a transaction's status.
+#[derive(Debug, Clone, Copy)]
+pub struct TxOutcome {
+    pub block_number: u64,
+    pub success: bool,
+}
+
+/// What the tracker needs from the node.
+pub trait TxChain: NonceSource {
+    fn block_number(&self) -> impl Future<Output = Result<u64>> + Send;
+    /// Transaction count of `address` in the latest block.
+    fn latest_nonce(&self, address: Address) -> impl Future<Output = Result<u64>> + Send;
+    fn receipt(&self, hash: B256) -> impl Future<Output = Result<Option<TxOutcome>>> + Send;
+    fn send_raw(&self, raw: &[u8]) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl TxChain for RootProvider<PubSubFrontend> {
+    async fn block_number(&self) -> Result<u64> {
+        Ok(self.get_block_number().await?)
+    }
+
+    async fn latest_nonce(&self, address: Address) -> Result<u64> {
+        Ok(self.get_transaction_count(address).latest().await?)
+    }
+
+    async fn receipt(&self, hash: B256) -> Result<Option<TxOutcome>> {
+        let receipt = self.get_transaction_receipt(hash).await?;
+        Ok(receipt.and_then(|receipt| {
+            Some(TxOutcome {
+                block_number: receipt.block_number?,
+                success: receipt.status(),
+            })
+        }))
+    }
+
+    async fn send_raw(&self, raw: &[u8]) -> Result<()> {
+        // Receipts are polled separately, so the pending handle is not needed
+        let _ = self.send_raw_transaction(raw).await?;
+        Ok(())
+    }
+}
+
+/// `true` if the node answered a broadcast with an error, so it will never
+/// include the transaction. Timeouts and dropped connections leave that open,
+/// and a transaction the node already knows was broadcast before.
+pub fn is_rejected(e: &anyhow::Error) -> bool {
+    error_response(e).is_some_and(|message| {
+        !["already known", "known transaction", "already imported"]
+            .iter()
+            .any(|known| message.contains(known))
+    })
+}
+
+/// `true` if the node rejected a broadcast because its nonce is already
+/// used, so the nonce must not be handed out again.
+pub fn is_nonce_used(e: &anyhow::Error) -> bool {
+    error_response(e).is_some_and(|message| message.contains("nonce too low"))
+}
+
+/// The lowercased message of the node's error response, if `e` is one.
+fn error_response(e: &anyhow::Error) -> Option<String> {
+    let payload = e.downcast_ref::<TransportError>()?.as_error_resp()?;
+    Some(payload.message.to_lowercase())
+}
+
+/// Checks every open transaction and returns those whose status changed.
+/// Transactions the node forgot are broadcast again. A dropped
+/// transaction's nonce is released if nothing else used it.
+pub async fn poll<C: TxChain>(
+    db: &Mutex<WalletDatabase>,
+    chain: &C,
+    confirmations: u64,
+) -> Result<Vec<TrackedTransaction>> {
+    let open = db.lock().unwrap().get_open_transactions()?;
+    if open.is_empty() {
+        return Ok(Vec::new());
+    }
+    let head = chain.block_number().await?;
+
+    let mut changed = Vec::new();
+    for tx in open {
+        match check(db, chain, &tx, head, confirmations).await {
+            Ok(Some(updated)) => {
+                println!(
+                    "Transaction {} ({}) is {}",
+                    updated.id,
+                    updated.tx_hash,
+                    updated.status.as_str()
+                );
+                changed.push(updated);
+            }
+            Ok(None) => {}
+            Err(e) => println!("Failed to check transaction {}: {:?}", tx.id, e),
+        }
+    }
+    Ok(changed)
+}
+
+async fn check<C: TxChain>(
+    db: &Mutex<WalletDatabase>,
+    chain: &C,
+    tx: &TrackedTransaction,
+    head: u64,
+    confirmations: u64,
+) -> Result<Option<TrackedTransaction>> {
+    let from = tx.tx.from;
+    // Taken before the receipts: if the nonce is used but none of our
+    // attempts has a receipt, another transaction took it
+    let latest_nonce = chain.latest_nonce(from).await?;
+    let attempts = db.lock().unwrap().get_transaction_attempts(tx.id)?;
+
+    let mut mined = None;
+    for attempt in &attempts {
+        if let Some(outcome) = chain.receipt(attempt.tx_hash.parse()?).await? {
+            mined = Some((attempt, outcome));
+            break;
+        }
+    }
+
+    let (status, tx_hash, block_number) = match mined {
+        Some((attempt, outcome)) => {
+            let status = if head + 1 < outcome.block_number + confirmations {
+                TxStatus::Mined
+            } else if !outcome.success {
+                TxStatus::Failed
+            } else if attempt.cancel {
+                TxStatus::Cancelled
+            } else {
+                TxStatus::Confirmed
+            };
+            (status, attempt.tx_hash.clone(), Some(outcome.block_number))
+        }
+        None if latest_nonce > tx.tx.nonce => (TxStatus::Dropped, tx.tx_hash.clone(), None),
+        None => {
+            if !chain.is_known(tx.tx_hash.parse()?).await? {
+                rebroadcast(db, chain, tx).await?
+            } else {
+                // A mined transaction whose block was reorged out is pending again
+                (TxStatus::Pending, tx.tx_hash.clone(), None)
+            }
+        }
+    };
+
+    if status == tx.status && tx_hash == tx.tx_hash && block_number == tx.block_number {
+        return Ok(None);
+    }
+    let db_lock = db.lock().unwrap();
+    db_lock.set_transaction_status(tx.id, status, &tx_hash, block_number)?;
+    if status == TxStatus::Dropped && latest_nonce <= tx.tx.nonce {
+        if let Err(e) = db_lock.release_nonce(&from.to_string(), tx.tx.nonce) {
+            println!(
+                "Failed to release nonce {} of {}: {:?}",
+                tx.tx.nonce, from, e
+            );
+        }
+    }
+    db_lock.get_transaction(tx.id)
+}
+
+async fn rebroadcast<C: TxChain>(
+    db: &Mutex<WalletDatabase>,
+    chain: &C,
+    tx: &TrackedTransaction,
+) -> Result<(TxStatus, String, Option<u64>)> {
+    if tx.broadcasts >= MAX_BROADCASTS {
+        println!(
+            "Transaction {} ({}) was broadcast {} times, giving up",
+            tx.id, tx.tx_hash, tx.broadcasts
+        );
+        return Ok((TxStatus::Dropped, tx.tx_hash.clone(), None));
+    }
+
+    let raw: Bytes = db.lock().unwrap().get_transaction_raw(tx.id)?.parse()?;
+    println!("Re-broadcasting transaction {} ({})", tx.id, tx.tx_hash);
+    if let Err(e) = chain.send_raw(&raw).await {
+        println!("Re-broadcast of {} failed: {:?}", tx.tx_hash, e);
+    }
+    db.lock().unwrap().record_broadcast(tx.id)?;
+    Ok((TxStatus::Pending, tx.tx_hash.clone(), None))
+}
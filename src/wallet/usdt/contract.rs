@@ -1,11 +1,8 @@
-use std::{
-    str::FromStr,
-    sync::{Arc, Mutex},
-};
+use std::sync::{Arc, Mutex};
 
 use alloy::{
     eips::BlockNumberOrTag,
-    primitives::{address, keccak256, Address, Uint, B256, U256},
+    primitives::{Address, B256, U256},
     providers::{Provider, RootProvider},
     pubsub::PubSubFrontend,
     rpc::types::Filter,
@@ -27,18 +24,18 @@ sol!(
     "src/wallet/usdt/USDT.json"
 );
 
-pub async fn get_balance(provider: RootProvider<PubSubFrontend>, owner: &str) -> Result<U256> {
+pub async fn get_balance(provider: RootProvider<PubSubFrontend>, owner: Address) -> Result<U256> {
     let usdt_contract_address = config::usdt_contract_address();
     let contract = IUESDT::new(usdt_contract_address, provider);
-    let balance = contract
-        .balanceOf(Address::from_str(owner)?)
-        .call()
-        .await?
-        ._0;
+    let balance = contract.balanceOf(owner).call().await?._0;
 
-    return Ok(balance);
+    Ok(balance)
 }
 
+/// Block the mainnet USDT contract was deployed in, before which it has no
+/// transfers.
+pub const USDT_DEPLOYMENT_BLOCK: u64 = 4_634_748;
+
 // Event signature for Transfer(address,address,uint256)
 const TRANSFER_EVENT_SIGNATURE: &str = "Transfer(address,address,uint256)";
 
@@ -53,10 +50,9 @@ pub async fn get_receive_logs(
     provider: &RootProvider<PubSubFrontend>,
     from_block: Option<u64>,
     to_block: Option<u64>,
-    reciever: String,
+    reciever: Address,
 ) -> Result<Vec<TransferLog>> {
-    let address = reciever.parse::<Address>()?;
-    let topic2 = address_to_topic(address);
+    let topic2 = address_to_topic(reciever);
 
     let from_block = match from_block {
         Some(bn) => BlockNumberOrTag::from(bn),
@@ -82,14 +78,31 @@ pub async fn get_receive_logs(
 
     let logs = provider.get_logs(&filter).await?;
 
-    let transfer_logs: Vec<TransferLog> = logs
-        .iter()
-        .filter_map(|log| parse_transfer_event(log))
-        .collect();
+    let transfer_logs: Vec<TransferLog> = logs.iter().filter_map(parse_transfer_event).collect();
 
     Ok(transfer_logs)
 }
 
+/// Transfers to any of `receivers` in the blocks `from_block..=to_block`,
+/// in a single query.
+pub async fn get_receive_logs_of(
+    provider: &RootProvider<PubSubFrontend>,
+    from_block: u64,
+    to_block: u64,
+    receivers: &[Address],
+) -> Result<Vec<TransferLog>> {
+    let topics: Vec<B256> = receivers.iter().copied().map(address_to_topic).collect();
+    let filter = Filter::new()
+        .address(config::usdt_contract_address())
+        .event(TRANSFER_EVENT_SIGNATURE)
+        .topic2(topics)
+        .from_block(from_block)
+        .to_block(to_block);
+
+    let logs = provider.get_logs(&filter).await?;
+    Ok(logs.iter().filter_map(parse_transfer_event).collect())
+}
+
 pub async fn subscribe_to_transfer_logs(
     provider: &RootProvider<PubSubFrontend>,
     db: Arc<Mutex<WalletDatabase>>,
@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use ethserv::wallet::{
+        accounts::{check_accounts, parse_accounts, Account},
+        database::WalletDatabase,
+    };
+    use rusqlite::Connection;
+
+    const PATH: &str = "m/44'/60'/0'/0/";
+
+    #[test]
+    fn parses_path_templates() {
+        let accounts = parse_accounts(
+            PATH,
+            Some("hot=m/44'/60'/1'/0/{index}, gas=m/44'/60'/2'/0/"),
+        )
+        .unwrap();
+        let names: Vec<_> = accounts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["default", "hot", "gas"]);
+        assert_eq!(accounts[1].path, "m/44'/60'/1'/0/");
+        assert_eq!(accounts[2].derivation_path(7), "m/44'/60'/2'/0/7");
+
+        assert!(Account::new("hot", "m/44'/60'/{index}'/0/0").is_err());
+        assert!(Account::new("hot", "m/44'/60'/1'/0/{index}'").is_err());
+        assert!(Account::new("hot", "m/44'/60'/1'/0").is_err());
+        assert!(Account::new("Hot Wallet", "m/44'/60'/1'/0/").is_err());
+        assert!(parse_accounts(PATH, Some("hot")).is_err());
+        assert!(parse_accounts(PATH, Some("default=m/44'/60'/1'/0/")).is_err());
+        assert!(parse_accounts(PATH, Some("hot=m/44'/60'/0'/0/{index}")).is_err());
+    }
+
+    #[test]
+    fn legacy_addresses_join_the_default_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.sqlite");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE addresses (
+                id INTEGER PRIMARY KEY,
+                address TEXT NOT NULL UNIQUE,
+                path TEXT NOT NULL,
+                addr_index INTEGER NOT NULL,
+                last_used DATETIME,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(path, addr_index)
+            );
+            INSERT INTO addresses (address, path, addr_index)
+                VALUES ('0xabc', 'm/44''/60''/0''/0/', 1);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let db = WalletDatabase::new(&path).unwrap();
+        assert_eq!(
+            db.get_address_record("0xabc").unwrap().unwrap().account,
+            "default"
+        );
+        let accounts = parse_accounts(PATH, Some("hot=m/44'/60'/1'/0/")).unwrap();
+        check_accounts(&db, &accounts).unwrap();
+
+        // Moving an account to another path would reissue indexes
+        let moved = parse_accounts("m/44'/60'/5'/0/", None).unwrap();
+        assert!(check_accounts(&db, &moved).is_err());
+        let renamed = parse_accounts("m/44'/60'/5'/0/", Some("deposits=m/44'/60'/0'/0/")).unwrap();
+        assert!(check_accounts(&db, &renamed).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod tests {
+    use ethserv::api::extract::{check_address, ChecksumStatus, ValidAddress};
+
+    const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn reports_checksum_status() {
+        let check = check_address(CHECKSUMMED).unwrap();
+        assert_eq!(check.checksum, ChecksumStatus::Valid);
+
+        let check = check_address(&CHECKSUMMED.to_lowercase()).unwrap();
+        assert_eq!(check.checksum, ChecksumStatus::Missing);
+        assert_eq!(check.address.to_checksum(None), CHECKSUMMED);
+
+        let check = check_address("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").unwrap();
+        assert_eq!(check.checksum, ChecksumStatus::Invalid);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert!(check_address("5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_err());
+        assert!(check_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA").is_err());
+        assert!(check_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAzz").is_err());
+    }
+
+    #[test]
+    fn valid_address_normalizes_to_checksum() {
+        let address: ValidAddress = CHECKSUMMED.to_lowercase().parse().unwrap();
+        assert_eq!(address.to_string(), CHECKSUMMED);
+
+        assert!("0x5AAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+            .parse::<ValidAddress>()
+            .is_err());
+    }
+}
@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use ethserv::wallet::{
+        accounts::Account,
+        backup::{create_backup, restore_backup, verify_backup},
+        database::WalletDatabase,
+        mnemonic::MnemonicStorage,
+        paths::WalletPaths,
+        restore::import_mnemonic,
+    };
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const OTHER_PHRASE: &str =
+        "legal winner thank year wave sausage worth useful legal winner thank yellow";
+    const PATH: &str = "m/44'/60'/0'/0/";
+
+    fn accounts() -> Vec<Account> {
+        vec![Account::new("default", PATH).unwrap()]
+    }
+
+    fn wallet(dir: &Path, phrase: &str) -> WalletPaths {
+        let paths = WalletPaths::resolve(dir, Some("main"), "pw").unwrap();
+        let storage = MnemonicStorage::new(paths.mnemonic_path.clone());
+        let db = WalletDatabase::new(&paths.wallet_path).unwrap();
+        import_mnemonic(&storage, &db, "pw", phrase, None, &accounts(), 5).unwrap();
+        paths
+    }
+
+    #[test]
+    fn backup_restores_into_an_empty_data_dir() {
+        let source = tempfile::tempdir().unwrap();
+        let paths = wallet(source.path(), PHRASE);
+        let bundle = source.path().join("backup.json");
+
+        let manifest = create_backup(&paths, "pw", None, PATH, &bundle).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        assert!(create_backup(&paths, "pw", None, PATH, &bundle).is_err());
+
+        let target_dir = tempfile::tempdir().unwrap();
+        let target = WalletPaths::resolve(target_dir.path(), Some("main"), "pw").unwrap();
+        verify_backup(&bundle, "pw", None, PATH, &target).unwrap();
+        assert!(!target.mnemonic_path.exists());
+
+        let restored = restore_backup(&bundle, "pw", None, PATH, &target).unwrap();
+        assert_eq!(restored.fingerprint, manifest.fingerprint);
+        let mnemonic = MnemonicStorage::new(target.mnemonic_path.clone())
+            .load_mnemonic("pw")
+            .unwrap();
+        assert_eq!(mnemonic.to_phrase(), PHRASE);
+        let db = WalletDatabase::new(&target.wallet_path).unwrap();
+        assert_eq!(db.get_all_addresses_by_path(PATH).unwrap().len(), 5);
+
+        // Never replaces an existing wallet
+        assert!(restore_backup(&bundle, "pw", None, PATH, &target).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_password_and_tampering() {
+        let source = tempfile::tempdir().unwrap();
+        let paths = wallet(source.path(), PHRASE);
+        let bundle = source.path().join("backup.json");
+        create_backup(&paths, "pw", None, PATH, &bundle).unwrap();
+
+        let target_dir = tempfile::tempdir().unwrap();
+        let target = WalletPaths::resolve(target_dir.path(), Some("main"), "pw").unwrap();
+        assert!(verify_backup(&bundle, "wrong", None, PATH, &target).is_err());
+        assert!(verify_backup(&bundle, "pw", None, "m/44'/60'/1'/0/", &target).is_err());
+
+        let json = fs::read_to_string(&bundle).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let ciphertext = value["ciphertext"].as_str().unwrap().to_string();
+        let flipped = if ciphertext.ends_with('0') { "1" } else { "0" };
+        value["ciphertext"] = format!("{}{}", &ciphertext[..ciphertext.len() - 1], flipped).into();
+        fs::write(&bundle, value.to_string()).unwrap();
+        assert!(verify_backup(&bundle, "pw", None, PATH, &target).is_err());
+        assert_eq!(fs::read_dir(target_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn refuses_to_back_up_a_mismatched_wallet() {
+        let source = tempfile::tempdir().unwrap();
+        let paths = wallet(source.path(), PHRASE);
+        MnemonicStorage::new(paths.mnemonic_path.clone())
+            .save_mnemonic(OTHER_PHRASE, "pw")
+            .unwrap();
+
+        let bundle = source.path().join("backup.json");
+        assert!(create_backup(&paths, "pw", None, PATH, &bundle).is_err());
+        assert!(!bundle.exists());
+    }
+}
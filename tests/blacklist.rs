@@ -0,0 +1,231 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Mutex};
+
+    use alloy::{
+        primitives::{address, Address, Log as PrimitiveLog, B256, U256},
+        rpc::types::Log,
+        sol_types::SolEvent,
+    };
+    use anyhow::Result;
+    use ethserv::{
+        error::Error,
+        wallet::{
+            blacklist::{self, BlacklistChange, BlacklistSource, SYNCED_BLOCK_KEY},
+            database::WalletDatabase,
+            usdt::contract::IUESDT,
+        },
+    };
+
+    const OURS: Address = address!("9858EfFD232B4033E47d90003D41EC34EcaEda94");
+    const ALSO_OURS: Address = address!("00000000000000000000000000000000000000aa");
+    const FOREIGN: Address = address!("00000000000000000000000000000000000000bb");
+    const USDT: Address = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+    const LOG_RANGE: u64 = 1_000;
+
+    #[derive(Default)]
+    struct FakeContract {
+        head: u64,
+        blacklisted: HashSet<Address>,
+        changes: Vec<BlacklistChange>,
+        /// Log queries starting at or after this block fail.
+        failing_from: Option<u64>,
+        queries: Mutex<Vec<(u64, u64)>>,
+    }
+
+    impl BlacklistSource for FakeContract {
+        async fn block_number(&self) -> Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn is_blacklisted(&self, address: Address) -> Result<bool> {
+            Ok(self.blacklisted.contains(&address))
+        }
+
+        async fn blacklist_changes(
+            &self,
+            from_block: u64,
+            to_block: u64,
+        ) -> Result<Vec<BlacklistChange>> {
+            self.queries.lock().unwrap().push((from_block, to_block));
+            if self.failing_from.is_some_and(|block| from_block >= block) {
+                return Err(anyhow::anyhow!("query returned more than 10000 results"));
+            }
+            Ok(self
+                .changes
+                .iter()
+                .filter(|change| (from_block..=to_block).contains(&change.block_number.unwrap()))
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn change(address: Address, blacklisted: bool, block_number: u64) -> BlacklistChange {
+        BlacklistChange {
+            address,
+            blacklisted,
+            block_number: Some(block_number),
+            tx_hash: Some(B256::repeat_byte(block_number as u8).to_string()),
+        }
+    }
+
+    fn log(data: alloy::primitives::LogData) -> Log {
+        Log {
+            inner: PrimitiveLog {
+                address: USDT,
+                data,
+            },
+            block_number: Some(7),
+            transaction_hash: Some(B256::repeat_byte(1)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn syncs_in_block_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        {
+            let db = db.lock().unwrap();
+            db.store_address(&OURS.to_string(), "m/44'/60'/0'/0/", 1)
+                .unwrap();
+            db.set_meta(SYNCED_BLOCK_KEY, "100").unwrap();
+        }
+
+        let mut contract = FakeContract {
+            head: 110,
+            changes: vec![change(OURS, true, 103), change(OURS, false, 109)],
+            failing_from: Some(105),
+            ..Default::default()
+        };
+        assert!(blacklist::sync(&db, &contract, 4).await.is_err());
+        // The range before the failure stays applied and synced
+        assert_eq!(db.lock().unwrap().get_frozen_addresses().unwrap().len(), 1);
+        assert_eq!(
+            db.lock().unwrap().get_meta(SYNCED_BLOCK_KEY).unwrap(),
+            Some(String::from("104"))
+        );
+
+        contract.failing_from = None;
+        contract.queries.lock().unwrap().clear();
+        let applied = blacklist::sync(&db, &contract, 4).await.unwrap();
+        assert_eq!(applied.len(), 1);
+        assert!(db
+            .lock()
+            .unwrap()
+            .get_frozen_addresses()
+            .unwrap()
+            .is_empty());
+        assert_eq!(*contract.queries.lock().unwrap(), [(105, 108), (109, 110)]);
+        assert_eq!(
+            db.lock().unwrap().get_meta(SYNCED_BLOCK_KEY).unwrap(),
+            Some(String::from("110"))
+        );
+    }
+
+    #[tokio::test]
+    async fn flags_our_frozen_addresses() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        {
+            let db = db.lock().unwrap();
+            db.store_address(&OURS.to_string(), "m/44'/60'/0'/0/", 1)
+                .unwrap();
+            db.store_address(&ALSO_OURS.to_string(), "m/44'/60'/0'/0/", 2)
+                .unwrap();
+        }
+
+        // The first sync has no synced block and checks every address
+        let mut contract = FakeContract {
+            head: 100,
+            blacklisted: [OURS, FOREIGN].into(),
+            ..Default::default()
+        };
+        let applied = blacklist::sync(&db, &contract, LOG_RANGE).await.unwrap();
+        assert_eq!(applied.len(), 1);
+        assert_eq!(applied[0].1.address, OURS.to_string());
+        assert_eq!(applied[0].0.event(&applied[0].1).topic(), "address.frozen");
+        assert_eq!(
+            db.lock().unwrap().get_meta(SYNCED_BLOCK_KEY).unwrap(),
+            Some(String::from("100"))
+        );
+
+        // Later syncs only apply the events since, and only to our addresses
+        contract.head = 110;
+        contract.changes = vec![
+            change(ALSO_OURS, true, 100),
+            change(OURS, false, 104),
+            change(FOREIGN, false, 105),
+            change(ALSO_OURS, true, 108),
+        ];
+        let applied = blacklist::sync(&db, &contract, LOG_RANGE).await.unwrap();
+        let flags: Vec<_> = applied
+            .iter()
+            .map(|(change, _)| (change.address, change.blacklisted))
+            .collect();
+        assert_eq!(flags, [(OURS, false), (ALSO_OURS, true)]);
+        assert!(blacklist::sync(&db, &contract, LOG_RANGE)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let frozen = db.lock().unwrap().get_frozen_addresses().unwrap();
+        assert_eq!(frozen.len(), 1);
+        assert_eq!(frozen[0].record.address, ALSO_OURS.to_string());
+        assert_eq!(frozen[0].block_number, Some(108));
+
+        // A scan catches up on missed events
+        contract.blacklisted.clear();
+        let applied = blacklist::scan(&db, &contract).await.unwrap();
+        assert_eq!(
+            applied[0].0.event(&applied[0].1).topic(),
+            "address.unfrozen"
+        );
+        assert!(db
+            .lock()
+            .unwrap()
+            .get_frozen_addresses()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn parses_blacklist_events() {
+        let added = IUESDT::AddedBlackList { _user: OURS }.encode_log_data();
+        let change = blacklist::parse_blacklist_event(&log(added)).unwrap();
+        assert_eq!(change.address, OURS);
+        assert!(change.blacklisted);
+        assert_eq!(change.block_number, Some(7));
+
+        let removed = IUESDT::RemovedBlackList { _user: OURS }.encode_log_data();
+        assert!(
+            !blacklist::parse_blacklist_event(&log(removed))
+                .unwrap()
+                .blacklisted
+        );
+
+        let transfer = IUESDT::Transfer {
+            from: OURS,
+            to: FOREIGN,
+            value: U256::from(1),
+        }
+        .encode_log_data();
+        assert!(blacklist::parse_blacklist_event(&log(transfer)).is_none());
+    }
+
+    #[tokio::test]
+    async fn screens_both_sides_of_a_transfer() {
+        let contract = FakeContract {
+            blacklisted: [FOREIGN].into(),
+            ..Default::default()
+        };
+        assert!(blacklist::screen(&contract, OURS, ALSO_OURS).await.is_ok());
+
+        for (from, to, role) in [(OURS, FOREIGN, "Destination"), (FOREIGN, OURS, "Source")] {
+            match Error::from(blacklist::screen(&contract, from, to).await.unwrap_err()) {
+                Error::PolicyViolation(msg) => assert!(msg.starts_with(role)),
+                e => panic!("expected a policy violation, got {}", e),
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use std::{io::Read, str::FromStr, sync::Arc};
+    use std::str::FromStr;
 
     use alloy::{
         eips::BlockNumberOrTag,
@@ -8,7 +8,6 @@ mod tests {
         providers::{Provider, ProviderBuilder, RootProvider, WsConnect},
         pubsub::PubSubFrontend,
         rpc::types::{Filter, Topic},
-        sol_types::TopicList,
     };
     use futures_util::StreamExt;
     use once_cell::sync::Lazy;
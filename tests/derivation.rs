@@ -0,0 +1,215 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc, thread};
+
+    use alloy::signers::local::{
+        coins_bip39::{English, Mnemonic},
+        MnemonicBuilder,
+    };
+    use ethserv::wallet::{
+        database::{AddressRecord, WalletDatabase, FIRST_ADDRESS_INDEX},
+        derivation::{account_xpub, AddressDeriver},
+    };
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const PATH: &str = "m/44'/60'/0'/0/";
+
+    fn deriver() -> AddressDeriver {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        AddressDeriver::new(&mnemonic, None, PATH).unwrap()
+    }
+
+    #[test]
+    fn derives_same_addresses_as_mnemonic_builder() {
+        let deriver = deriver();
+        for index in [0, 1, 42] {
+            let expected = MnemonicBuilder::<English>::default()
+                .phrase(PHRASE)
+                .derivation_path(format!("{}{}", PATH, index))
+                .unwrap()
+                .build()
+                .unwrap()
+                .address();
+            assert_eq!(deriver.derive(index).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn passphrase_derives_a_different_wallet() {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let deriver = AddressDeriver::new(&mnemonic, Some("TREZOR"), PATH).unwrap();
+        let expected = MnemonicBuilder::<English>::default()
+            .phrase(PHRASE)
+            .password("TREZOR")
+            .derivation_path(format!("{}0", PATH))
+            .unwrap()
+            .build()
+            .unwrap()
+            .address();
+        assert_eq!(deriver.derive(0).unwrap(), expected);
+        assert_ne!(
+            deriver.derive(0).unwrap(),
+            self::deriver().derive(0).unwrap()
+        );
+    }
+
+    #[test]
+    fn watch_only_deriver_matches_mnemonic() {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let xpub = account_xpub(&mnemonic, None, PATH).unwrap();
+        assert!(xpub.starts_with("xpub"));
+
+        let watch_only = AddressDeriver::from_account_xpub(&xpub, PATH).unwrap();
+        let deriver = deriver();
+        for index in 0..5 {
+            assert_eq!(
+                watch_only.derive(index).unwrap(),
+                deriver.derive(index).unwrap()
+            );
+        }
+
+        // An xpub at the wrong depth must not silently derive other addresses
+        assert!(AddressDeriver::from_account_xpub(&xpub, "m/44'/60'/0'/0/0/").is_err());
+    }
+
+    #[test]
+    fn allocates_a_batch_with_customer_id_and_label() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap();
+        let deriver = deriver();
+        let build = |index| {
+            Ok(AddressRecord {
+                address: deriver.derive(index)?.to_string(),
+                account: String::from("default"),
+                path: PATH.to_string(),
+                addr_index: index,
+                customer_id: Some(String::from("customer-7")),
+                label: Some(String::from("savings")),
+                idempotency_key: None,
+            })
+        };
+
+        let records = db.allocate_addresses(PATH, 3, build).unwrap();
+        let indexes: Vec<u32> = records.iter().map(|record| record.addr_index).collect();
+        assert_eq!(
+            indexes,
+            (FIRST_ADDRESS_INDEX..FIRST_ADDRESS_INDEX + 3).collect::<Vec<_>>()
+        );
+        for record in &records {
+            let stored = db.get_address_record(&record.address).unwrap().unwrap();
+            assert_eq!(stored.addr_index, record.addr_index);
+            assert_eq!(stored.customer_id.as_deref(), Some("customer-7"));
+            assert_eq!(stored.label.as_deref(), Some("savings"));
+        }
+
+        // A batch is stored completely or not at all
+        assert!(db
+            .allocate_addresses(PATH, 3, |index| {
+                if index == FIRST_ADDRESS_INDEX + 4 {
+                    return Err(anyhow::anyhow!("derivation failed"));
+                }
+                build(index)
+            })
+            .is_err());
+        assert_eq!(db.get_all_addresses_by_path(PATH).unwrap().len(), 3);
+        let next = db.allocate_addresses(PATH, 1, build).unwrap();
+        assert_eq!(next[0].addr_index, FIRST_ADDRESS_INDEX + 3);
+    }
+
+    #[test]
+    fn idempotency_key_identifies_one_address() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap();
+        let deriver = deriver();
+        let record = |index, key: Option<&str>| {
+            Ok(AddressRecord {
+                address: deriver.derive(index)?.to_string(),
+                account: String::from("default"),
+                path: PATH.to_string(),
+                addr_index: index,
+                customer_id: Some(String::from("customer-7")),
+                label: None,
+                idempotency_key: key.map(String::from),
+            })
+        };
+
+        assert!(db
+            .get_address_by_idempotency_key("req-1")
+            .unwrap()
+            .is_none());
+        let issued = db
+            .allocate_addresses(PATH, 1, |index| record(index, Some("req-1")))
+            .unwrap();
+        let found = db.get_address_by_idempotency_key("req-1").unwrap().unwrap();
+        assert_eq!(found.address, issued[0].address);
+        assert_eq!(found.customer_id.as_deref(), Some("customer-7"));
+
+        // A retry racing the first call cannot store a second address
+        assert!(db
+            .allocate_addresses(PATH, 1, |index| record(index, Some("req-1")))
+            .is_err());
+        assert_eq!(db.get_all_addresses_by_path(PATH).unwrap().len(), 1);
+
+        // Addresses issued without a key do not collide with each other
+        db.allocate_addresses(PATH, 2, |index| record(index, None))
+            .unwrap();
+        db.allocate_addresses(PATH, 1, |index| record(index, Some("req-2")))
+            .unwrap();
+        assert_eq!(db.get_all_addresses_by_path(PATH).unwrap().len(), 4);
+        let found = db.get_address_by_idempotency_key("req-1").unwrap().unwrap();
+        assert_eq!(found.address, issued[0].address);
+    }
+
+    #[test]
+    fn concurrent_allocation_has_no_collisions_or_gaps() {
+        const THREADS: u32 = 8;
+        const PER_THREAD: u32 = 250;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("wallet.sqlite");
+        // Create the schema once before the workers race on it
+        WalletDatabase::new(&db_path).unwrap();
+
+        let deriver = Arc::new(deriver());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let db_path = db_path.clone();
+                let deriver = deriver.clone();
+                thread::spawn(move || {
+                    // Separate connections, as separate processes would have
+                    let db = WalletDatabase::new(&db_path).unwrap();
+                    for _ in 0..PER_THREAD {
+                        db.allocate_addresses(PATH, 1, |index| {
+                            Ok(AddressRecord {
+                                address: deriver.derive(index)?.to_string(),
+                                account: String::from("default"),
+                                path: PATH.to_string(),
+                                addr_index: index,
+                                customer_id: None,
+                                label: None,
+                                idempotency_key: None,
+                            })
+                        })
+                        .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let db = WalletDatabase::new(&db_path).unwrap();
+        let stored = db.get_all_addresses_by_path(PATH).unwrap();
+        let total = THREADS * PER_THREAD;
+        assert_eq!(stored.len() as u32, total);
+
+        let indexes: Vec<u32> = stored.iter().map(|(_, index)| *index).collect();
+        let expected: Vec<u32> = (FIRST_ADDRESS_INDEX..FIRST_ADDRESS_INDEX + total).collect();
+        assert_eq!(indexes, expected);
+
+        let unique: HashSet<_> = stored.iter().map(|(address, _)| address).collect();
+        assert_eq!(unique.len() as u32, total);
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Mutex};
+
+    use alloy::{
+        primitives::Address,
+        signers::local::coins_bip39::{English, Mnemonic},
+    };
+    use anyhow::Result;
+    use ethserv::wallet::{
+        accounts::Account,
+        database::WalletDatabase,
+        derivation::AddressDeriver,
+        discovery::{block_ranges, discover, ActivityProbe},
+        restore::rebuild_addresses,
+    };
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const PATH: &str = "m/44'/60'/0'/0/";
+
+    /// Reports the given addresses as used and records the batches probed.
+    struct FakeChain {
+        used: HashSet<Address>,
+        batches: Mutex<Vec<usize>>,
+    }
+
+    impl ActivityProbe for FakeChain {
+        async fn used(&self, addresses: &[Address]) -> Result<Vec<bool>> {
+            self.batches.lock().unwrap().push(addresses.len());
+            Ok(addresses
+                .iter()
+                .map(|address| self.used.contains(address))
+                .collect())
+        }
+    }
+
+    fn setup(used: &[u32]) -> (AddressDeriver, Account, FakeChain) {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let deriver = AddressDeriver::new(&mnemonic, None, PATH).unwrap();
+        let chain = FakeChain {
+            used: used.iter().map(|&i| deriver.derive(i).unwrap()).collect(),
+            batches: Mutex::new(Vec::new()),
+        };
+        (deriver, Account::new("default", PATH).unwrap(), chain)
+    }
+
+    #[tokio::test]
+    async fn stops_after_the_gap_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let (deriver, account, chain) = setup(&[1, 3, 23, 30]);
+
+        let report = discover(&db, &deriver, &account, 20, &chain).await.unwrap();
+        assert_eq!(report.last_used, Some(30));
+        assert_eq!(report.scanned, 50);
+        assert_eq!(report.added, 30);
+        let stored = db.lock().unwrap().get_all_addresses_by_path(PATH).unwrap();
+        assert_eq!(stored.len(), 30);
+        assert_eq!(stored[29].0, deriver.derive(30).unwrap().to_string());
+
+        // A smaller gap misses the later addresses
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let report = discover(&db, &deriver, &account, 5, &chain).await.unwrap();
+        assert_eq!(report.last_used, Some(3));
+        assert_eq!(report.added, 3);
+    }
+
+    #[tokio::test]
+    async fn continues_after_stored_addresses() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let (deriver, account, chain) = setup(&[12]);
+
+        let report = discover(&db, &deriver, &account, 3, &chain).await.unwrap();
+        assert_eq!((report.last_used, report.added), (None, 0));
+
+        rebuild_addresses(&db.lock().unwrap(), &deriver, &account, 10).unwrap();
+        let report = discover(&db, &deriver, &account, 3, &chain).await.unwrap();
+        assert_eq!(report.last_used, Some(12));
+        assert_eq!(report.scanned, 5);
+        assert_eq!(report.added, 2);
+        assert!(discover(&db, &deriver, &account, 0, &chain).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn probes_each_gap_as_one_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let (deriver, account, chain) = setup(&[5]);
+
+        let report = discover(&db, &deriver, &account, 20, &chain).await.unwrap();
+        assert_eq!(report.scanned, 25);
+        // The first gap, then what activity in it added to the gap
+        assert_eq!(*chain.batches.lock().unwrap(), [20, 5]);
+    }
+
+    #[test]
+    fn splits_log_queries_into_block_ranges() {
+        let ranges: Vec<_> = block_ranges(100, 125, 10).collect();
+        assert_eq!(ranges, [(100, 109), (110, 119), (120, 125)]);
+        assert_eq!(block_ranges(7, 7, 10).collect::<Vec<_>>(), [(7, 7)]);
+        assert_eq!(block_ranges(8, 7, 10).count(), 0);
+    }
+}
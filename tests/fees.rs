@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod tests {
+    use alloy::rpc::types::FeeHistory;
+    use ethserv::wallet::{
+        fees::{bump_fees, estimate_from_history, estimate_legacy, FeeCaps, Urgency},
+        offline::GasParams,
+    };
+
+    const GWEI: u128 = 1_000_000_000;
+
+    const CAPS: FeeCaps = FeeCaps {
+        max_fee_per_gas: 200 * GWEI,
+        max_priority_fee_per_gas: 5 * GWEI,
+    };
+
+    fn history(next_base_fee: u128, rewards: &[u128]) -> FeeHistory {
+        FeeHistory {
+            base_fee_per_gas: vec![GWEI; rewards.len()]
+                .into_iter()
+                .chain([next_base_fee])
+                .collect(),
+            gas_used_ratio: vec![0.5; rewards.len()],
+            reward: Some(rewards.iter().map(|reward| vec![*reward]).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn eip1559(gas: &GasParams) -> (u128, u128) {
+        match gas {
+            GasParams::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => (*max_fee_per_gas, *max_priority_fee_per_gas),
+            GasParams::Legacy { .. } => panic!("expected EIP-1559 fees"),
+        }
+    }
+
+    #[test]
+    fn prices_from_fee_history() {
+        // Empty blocks report a zero reward and are ignored
+        let rewards = [0, 2 * GWEI, GWEI, 3 * GWEI, 0];
+        let estimate =
+            estimate_from_history(&history(10 * GWEI, &rewards), Urgency::Normal, CAPS).unwrap();
+        assert_eq!(eip1559(&estimate.gas), (22 * GWEI, 2 * GWEI));
+        assert_eq!(estimate.base_fee_per_gas, Some(10 * GWEI));
+        assert!(!estimate.capped);
+
+        let estimate =
+            estimate_from_history(&history(10 * GWEI, &[0, 0]), Urgency::Slow, CAPS).unwrap();
+        assert_eq!(eip1559(&estimate.gas), (12_600_000_000, GWEI / 10));
+
+        // Pre-London blocks have no base fee
+        assert!(estimate_from_history(&history(0, &[GWEI]), Urgency::Fast, CAPS).is_err());
+    }
+
+    #[test]
+    fn enforces_caps() {
+        let estimate =
+            estimate_from_history(&history(80 * GWEI, &[9 * GWEI]), Urgency::Fast, CAPS).unwrap();
+        assert_eq!(eip1559(&estimate.gas), (200 * GWEI, 5 * GWEI));
+        assert!(estimate.capped);
+
+        // A base fee above the cap could never be paid
+        assert!(estimate_from_history(&history(201 * GWEI, &[GWEI]), Urgency::Slow, CAPS).is_err());
+    }
+
+    #[test]
+    fn keeps_the_priority_fee_under_a_low_max_fee_cap() {
+        let caps = FeeCaps {
+            max_fee_per_gas: 3 * GWEI,
+            max_priority_fee_per_gas: 5 * GWEI,
+        };
+        let estimate =
+            estimate_from_history(&history(GWEI, &[4 * GWEI]), Urgency::Normal, caps).unwrap();
+        assert_eq!(eip1559(&estimate.gas), (3 * GWEI, 3 * GWEI));
+        assert!(estimate.capped);
+
+        let old = GasParams::Eip1559 {
+            max_fee_per_gas: 2 * GWEI,
+            max_priority_fee_per_gas: GWEI,
+        };
+        let estimate = GasParams::Eip1559 {
+            max_fee_per_gas: 3 * GWEI,
+            max_priority_fee_per_gas: 4 * GWEI,
+        };
+        let bumped = bump_fees(&old, &estimate, caps).unwrap();
+        assert_eq!(eip1559(&bumped), (3 * GWEI, 3 * GWEI));
+    }
+
+    #[test]
+    fn falls_back_to_legacy_gas_price() {
+        let estimate = estimate_legacy(20 * GWEI, Urgency::Fast, CAPS).unwrap();
+        assert!(matches!(estimate.gas, GasParams::Legacy { gas_price } if gas_price == 25 * GWEI));
+        assert_eq!(estimate.base_fee_per_gas, None);
+
+        let estimate = estimate_legacy(190 * GWEI, Urgency::Fast, CAPS).unwrap();
+        assert!(matches!(estimate.gas, GasParams::Legacy { gas_price } if gas_price == 200 * GWEI));
+        assert!(estimate.capped);
+        assert!(estimate_legacy(250 * GWEI, Urgency::Slow, CAPS).is_err());
+    }
+
+    #[test]
+    fn bumps_replacements_by_more_than_ten_percent() {
+        let old = GasParams::Eip1559 {
+            max_fee_per_gas: 30 * GWEI,
+            max_priority_fee_per_gas: 2 * GWEI,
+        };
+        let cheaper = GasParams::Eip1559 {
+            max_fee_per_gas: 20 * GWEI,
+            max_priority_fee_per_gas: GWEI,
+        };
+        let bumped = bump_fees(&old, &cheaper, CAPS).unwrap();
+        assert_eq!(eip1559(&bumped), (33 * GWEI + 1, 2_200_000_001));
+
+        let pricier = GasParams::Eip1559 {
+            max_fee_per_gas: 50 * GWEI,
+            max_priority_fee_per_gas: 3 * GWEI,
+        };
+        assert_eq!(
+            eip1559(&bump_fees(&old, &pricier, CAPS).unwrap()),
+            (50 * GWEI, 3 * GWEI)
+        );
+
+        let at_cap = GasParams::Eip1559 {
+            max_fee_per_gas: 100 * GWEI,
+            max_priority_fee_per_gas: 5 * GWEI,
+        };
+        assert!(bump_fees(&at_cap, &cheaper, CAPS).is_err());
+    }
+}
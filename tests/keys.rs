@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        primitives::Address,
+        signers::{
+            k256::ecdsa::SigningKey,
+            local::coins_bip39::{English, Mnemonic},
+        },
+    };
+    use ethserv::wallet::{
+        derivation::AddressDeriver,
+        keys::{LockedBuffer, ProtectedKey},
+    };
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const PATH: &str = "m/44'/60'/0'/0/";
+
+    #[test]
+    fn locked_buffer_holds_its_contents() {
+        let mut buffer = LockedBuffer::new(64);
+        buffer.as_mut_slice().copy_from_slice(&[7u8; 64]);
+        assert_eq!(buffer.as_slice(), &[7u8; 64]);
+    }
+
+    #[test]
+    fn protected_keys_derive_the_wallet_addresses() {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let expected = AddressDeriver::new(&mnemonic, None, PATH).unwrap();
+
+        let account = ProtectedKey::account(&mnemonic, None, PATH).unwrap();
+        let deriver = account.deriver().unwrap();
+        let master = ProtectedKey::master(&mnemonic, None)
+            .unwrap()
+            .xpriv()
+            .unwrap();
+        for index in [0, 1, 42] {
+            assert_eq!(
+                deriver.derive(index).unwrap(),
+                expected.derive(index).unwrap()
+            );
+
+            let key = master
+                .derive_path(format!("{}{}", PATH, index).as_str())
+                .unwrap();
+            let signing_key: &SigningKey = key.as_ref();
+            assert_eq!(
+                Address::from_private_key(signing_key),
+                expected.derive(index).unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,180 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use ethserv::wallet::mnemonic::{KdfParams, LoadError, MnemonicStorage, PassphraseStorage};
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    fn load_error(storage: &MnemonicStorage, password: &str) -> LoadError {
+        storage
+            .load_mnemonic(password)
+            .err()
+            .unwrap()
+            .downcast::<LoadError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn distinguishes_missing_wrong_password_and_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mnemonic.dat");
+        let storage = MnemonicStorage::new(path.clone());
+
+        assert!(matches!(load_error(&storage, "pw"), LoadError::Missing(_)));
+
+        storage.save_mnemonic(PHRASE, "pw").unwrap();
+        assert_eq!(storage.load_mnemonic("pw").unwrap().to_phrase(), PHRASE);
+        assert!(matches!(
+            load_error(&storage, "wrong"),
+            LoadError::DecryptionFailed
+        ));
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(load_error(&storage, "pw"), LoadError::Corrupt(_)));
+    }
+
+    #[test]
+    fn create_refuses_to_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MnemonicStorage::new(dir.path().join("mnemonic.dat"));
+
+        let created = storage.create("pw", 12).unwrap();
+        assert!(storage.create("pw", 12).is_err());
+        assert_eq!(
+            storage.load_mnemonic("pw").unwrap().to_phrase(),
+            created.to_phrase()
+        );
+    }
+
+    #[test]
+    fn create_uses_the_requested_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MnemonicStorage::new(dir.path().join("mnemonic.dat"));
+
+        assert!(storage.create("pw", 15).is_err());
+        assert!(!storage.exists());
+        let created = storage.create("pw", 24).unwrap();
+        assert_eq!(created.to_phrase().split(' ').count(), 24);
+    }
+
+    #[test]
+    fn reads_and_migrates_legacy_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mnemonic.dat");
+        // Written before the format was versioned, with password "password"
+        fs::copy("pers/mnemonic_5e884898da28047151d0e56f8dc62927.dat", &path).unwrap();
+
+        let storage = MnemonicStorage::new(path.clone());
+        let phrase = storage.load_mnemonic("password").unwrap().to_phrase();
+        assert!(storage.needs_migration().unwrap());
+
+        assert!(storage.migrate("password").unwrap());
+        assert!(!storage.needs_migration().unwrap());
+        assert!(!storage.migrate("password").unwrap());
+        assert_eq!(
+            storage.load_mnemonic("password").unwrap().to_phrase(),
+            phrase
+        );
+
+        let json = fs::read_to_string(&path).unwrap();
+        assert!(json.contains("\"algorithm\":\"argon2id\""));
+    }
+
+    #[test]
+    fn kdf_parameters_are_authenticated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mnemonic.dat");
+        let kdf = KdfParams::Argon2id {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+        MnemonicStorage::with_kdf(path.clone(), kdf)
+            .save_mnemonic(PHRASE, "pw")
+            .unwrap();
+
+        let storage = MnemonicStorage::new(path.clone());
+        assert_eq!(storage.load_mnemonic("pw").unwrap().to_phrase(), PHRASE);
+
+        let tampered = fs::read_to_string(&path)
+            .unwrap()
+            .replace("\"iterations\":1", "\"iterations\":2");
+        fs::write(&path, tampered).unwrap();
+        assert!(matches!(
+            load_error(&storage, "pw"),
+            LoadError::DecryptionFailed
+        ));
+    }
+
+    #[test]
+    fn rejects_excessive_kdf_parameters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mnemonic.dat");
+        let kdf = KdfParams::Argon2id {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+        MnemonicStorage::with_kdf(path.clone(), kdf)
+            .save_mnemonic(PHRASE, "pw")
+            .unwrap();
+        let storage = MnemonicStorage::new(path.clone());
+        let saved = fs::read_to_string(&path).unwrap();
+
+        for (from, to) in [
+            ("\"memory_kib\":1024", "\"memory_kib\":4294967295"),
+            ("\"iterations\":1", "\"iterations\":4294967295"),
+            ("\"parallelism\":1", "\"parallelism\":16777215"),
+        ] {
+            fs::write(&path, saved.replace(from, to)).unwrap();
+            assert!(matches!(load_error(&storage, "pw"), LoadError::Corrupt(_)));
+        }
+
+        MnemonicStorage::with_kdf(path.clone(), KdfParams::legacy())
+            .save_mnemonic(PHRASE, "pw")
+            .unwrap();
+        let tampered = fs::read_to_string(&path)
+            .unwrap()
+            .replace("\"iterations\":100000", "\"iterations\":4294967295");
+        fs::write(&path, tampered).unwrap();
+        assert!(matches!(load_error(&storage, "pw"), LoadError::Corrupt(_)));
+    }
+
+    #[test]
+    fn stores_the_passphrase_separately() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("passphrase.dat");
+        let storage = PassphraseStorage::new(path.clone());
+        assert!(storage.load("pw").unwrap().is_none());
+        assert_eq!(
+            storage
+                .load_or("pw", Some("env"))
+                .unwrap()
+                .unwrap()
+                .as_str(),
+            "env"
+        );
+
+        storage.set(Some("secret"), "pw").unwrap();
+        assert_eq!(storage.load("pw").unwrap().unwrap().as_str(), "secret");
+        assert!(storage.load("wrong").is_err());
+        assert_eq!(
+            storage.load_or("pw", None).unwrap().unwrap().as_str(),
+            "secret"
+        );
+        assert!(storage.load_or("pw", Some("other")).is_err());
+
+        // Authenticated as a passphrase, so it cannot pass for a mnemonic
+        let mnemonic_path = dir.path().join("mnemonic.dat");
+        fs::copy(&path, &mnemonic_path).unwrap();
+        assert!(matches!(
+            load_error(&MnemonicStorage::new(mnemonic_path), "pw"),
+            LoadError::DecryptionFailed
+        ));
+
+        storage.set(None, "pw").unwrap();
+        assert!(!storage.exists());
+    }
+}
@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Mutex, thread, time::Duration};
+
+    use alloy::primitives::{address, Address, B256};
+    use anyhow::Result;
+    use ethserv::wallet::{
+        database::WalletDatabase,
+        nonces::{self, NonceSource, NonceStatus},
+    };
+
+    const FROM: Address = address!("9858EfFD232B4033E47d90003D41EC34EcaEda94");
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    /// A node with a fixed pending nonce that knows the given transactions.
+    #[derive(Default)]
+    struct FakeNode {
+        pending: u64,
+        known: HashSet<B256>,
+    }
+
+    impl NonceSource for FakeNode {
+        async fn pending_nonce(&self, _address: Address) -> Result<u64> {
+            Ok(self.pending)
+        }
+
+        async fn is_known(&self, hash: B256) -> Result<bool> {
+            Ok(self.known.contains(&hash))
+        }
+    }
+
+    fn hash(n: u8) -> B256 {
+        B256::repeat_byte(n)
+    }
+
+    #[tokio::test]
+    async fn hands_out_each_nonce_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.sqlite");
+        let db = Mutex::new(WalletDatabase::new(&path).unwrap());
+        let node = FakeNode {
+            pending: 7,
+            ..Default::default()
+        };
+
+        assert_eq!(nonces::reserve(&db, &node, FROM).await.unwrap(), 7);
+
+        // Separate connections, as from concurrent requests or processes
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    let db = WalletDatabase::new(path).unwrap();
+                    (0..10)
+                        .map(|_| db.reserve_nonce(&FROM.to_string(), 0).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let mut handed_out: Vec<u64> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        handed_out.sort();
+        assert_eq!(handed_out, (8..48).collect::<Vec<_>>());
+
+        // A nonce given back is handed out before new ones
+        let db = db.lock().unwrap();
+        db.release_nonce(&FROM.to_string(), 20).unwrap();
+        assert_eq!(db.reserve_nonce(&FROM.to_string(), 0).unwrap(), 20);
+        assert_eq!(db.reserve_nonce(&FROM.to_string(), 0).unwrap(), 48);
+        db.release_nonce(&FROM.to_string(), 48).unwrap();
+        assert_eq!(
+            db.get_nonce_state(&FROM.to_string())
+                .unwrap()
+                .unwrap()
+                .next_nonce,
+            48
+        );
+    }
+
+    #[tokio::test]
+    async fn reconcile_fills_gaps_of_dropped_transactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let mut node = FakeNode::default();
+
+        for n in 0..5u8 {
+            let nonce = nonces::reserve(&db, &node, FROM).await.unwrap();
+            db.lock()
+                .unwrap()
+                .mark_nonce_sent(&FROM.to_string(), nonce, &hash(n).to_string())
+                .unwrap();
+        }
+
+        // Nonce 0 was mined, 1 and 3 were dropped, 2 and 4 wait behind 1
+        node.pending = 1;
+        node.known = [hash(2), hash(4)].into();
+        let state = nonces::reconcile(&db, &node, FROM, HOUR).await.unwrap();
+        assert_eq!(state.next_nonce, 5);
+        assert_eq!(state.gaps(), vec![1, 3]);
+        assert_eq!(state.reservations[0].nonce, 1);
+
+        assert_eq!(nonces::reserve(&db, &node, FROM).await.unwrap(), 1);
+        assert_eq!(nonces::reserve(&db, &node, FROM).await.unwrap(), 3);
+        assert_eq!(nonces::reserve(&db, &node, FROM).await.unwrap(), 5);
+
+        // Everything was mined, and 12 more were sent from elsewhere
+        node.pending = 18;
+        let state = nonces::reconcile(&db, &node, FROM, HOUR).await.unwrap();
+        assert_eq!(state.next_nonce, 18);
+        assert!(state.reservations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reconcile_releases_stale_reservations() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let node = FakeNode {
+            pending: 3,
+            known: [hash(1)].into(),
+        };
+
+        for _ in 0..3 {
+            nonces::reserve(&db, &node, FROM).await.unwrap();
+        }
+        db.lock()
+            .unwrap()
+            .mark_nonce_sent(&FROM.to_string(), 4, &hash(1).to_string())
+            .unwrap();
+
+        let state = nonces::reconcile(&db, &node, FROM, HOUR).await.unwrap();
+        assert_eq!(state.gaps(), Vec::<u64>::new());
+        assert_eq!(state.reservations[0].status, NonceStatus::Reserved);
+
+        // 3 and 5 were never sent. 5 is the last one, so only 3 is a gap
+        let state = nonces::reconcile(&db, &node, FROM, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(state.next_nonce, 5);
+        assert_eq!(state.gaps(), vec![3]);
+        assert_eq!(state.reservations[1].status, NonceStatus::Sent);
+    }
+}
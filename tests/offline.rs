@@ -0,0 +1,208 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use alloy::{
+        consensus::Transaction,
+        primitives::{address, Address, Bytes, B256, U256},
+        signers::local::coins_bip39::{English, Mnemonic},
+    };
+    use anyhow::Result;
+    use ethserv::wallet::{
+        database::WalletDatabase,
+        derivation::AddressDeriver,
+        nonces::{NonceSource, NonceStatus},
+        offline::{
+            self, GasParams, OfflineSigner, SignedBundle, UnsignedBundle, UnsignedTransaction,
+            UsdtTransfer, USDT_TRANSFER_GAS_LIMIT,
+        },
+    };
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const PATH: &str = "m/44'/60'/0'/0/";
+    const USDT: Address = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+
+    /// A node whose pending nonce is 7 for every address.
+    struct FakeNode;
+
+    impl NonceSource for FakeNode {
+        async fn pending_nonce(&self, _address: Address) -> Result<u64> {
+            Ok(7)
+        }
+
+        async fn is_known(&self, _hash: B256) -> Result<bool> {
+            Ok(false)
+        }
+    }
+
+    fn unsigned_tx(index: u32, nonce: u64, gas: GasParams) -> UnsignedTransaction {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let from = AddressDeriver::new(&mnemonic, None, PATH)
+            .unwrap()
+            .derive(index)
+            .unwrap();
+        UnsignedTransaction {
+            from,
+            derivation_path: format!("{}{}", PATH, index),
+            chain_id: 1,
+            nonce,
+            to: USDT,
+            value: U256::ZERO,
+            input: Bytes::from_static(&[0xa9, 0x05, 0x9c, 0xbb]),
+            gas_limit: 60_000,
+            gas,
+        }
+    }
+
+    #[test]
+    fn signs_bundle_through_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let unsigned_path = dir.path().join("unsigned.json");
+        let signed_path = dir.path().join("signed.json");
+
+        UnsignedBundle::new(vec![
+            unsigned_tx(
+                1,
+                0,
+                GasParams::Eip1559 {
+                    max_fee_per_gas: 30_000_000_000,
+                    max_priority_fee_per_gas: 1_000_000_000,
+                },
+            ),
+            unsigned_tx(
+                2,
+                5,
+                GasParams::Legacy {
+                    gas_price: 20_000_000_000,
+                },
+            ),
+        ])
+        .save(&unsigned_path)
+        .unwrap();
+
+        // Offline side
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let bundle = UnsignedBundle::load(&unsigned_path).unwrap();
+        OfflineSigner::new(&mnemonic, None)
+            .unwrap()
+            .sign_bundle(&bundle)
+            .unwrap()
+            .save(&signed_path)
+            .unwrap();
+
+        // Online side
+        let signed = SignedBundle::load(&signed_path).unwrap();
+        let envelopes = signed.verify().unwrap();
+        assert_eq!(envelopes.len(), 2);
+        assert_eq!(envelopes[0].nonce(), 0);
+        assert_eq!(envelopes[1].nonce(), 5);
+        assert_eq!(
+            envelopes[0].recover_signer().unwrap(),
+            bundle.transactions[0].from
+        );
+    }
+
+    #[tokio::test]
+    async fn prepares_signs_and_broadcasts_through_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let unsigned_path = dir.path().join("unsigned.json");
+        let signed_path = dir.path().join("signed.json");
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let from = unsigned_tx(3, 0, GasParams::Legacy { gas_price: 1 }).from;
+        let to = address!("00000000000000000000000000000000000000bb");
+        let gas = GasParams::Eip1559 {
+            max_fee_per_gas: 30_000_000_000,
+            max_priority_fee_per_gas: 1_000_000_000,
+        };
+
+        // Online side, which holds no keys
+        assert!(offline::prepare_usdt_transfer(
+            &db,
+            &FakeNode,
+            1,
+            USDT,
+            UsdtTransfer {
+                from: to,
+                to: from,
+                amount: U256::from(1),
+                gas_limit: USDT_TRANSFER_GAS_LIMIT,
+                gas: gas.clone(),
+            },
+        )
+        .await
+        .is_err());
+        db.lock()
+            .unwrap()
+            .store_address(&from.to_string(), PATH, 3)
+            .unwrap();
+        let tx = offline::prepare_usdt_transfer(
+            &db,
+            &FakeNode,
+            1,
+            USDT,
+            UsdtTransfer {
+                from,
+                to,
+                amount: U256::from(2_500_000),
+                gas_limit: USDT_TRANSFER_GAS_LIMIT,
+                gas,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(tx.derivation_path, format!("{}3", PATH));
+        let input = tx.input.clone();
+        UnsignedBundle::new(vec![tx]).save(&unsigned_path).unwrap();
+
+        // Offline side
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        OfflineSigner::new(&mnemonic, None)
+            .unwrap()
+            .sign_bundle(&UnsignedBundle::load(&unsigned_path).unwrap())
+            .unwrap()
+            .save(&signed_path)
+            .unwrap();
+
+        // Online side again, as `broadcast` reads it
+        let signed = SignedBundle::load(&signed_path).unwrap();
+        let envelopes = signed.verify().unwrap();
+        assert_eq!(envelopes[0].recover_signer().unwrap(), from);
+        assert_eq!(envelopes[0].nonce(), 7);
+        assert_eq!(envelopes[0].to(), Some(USDT));
+        assert_eq!(envelopes[0].input(), &input);
+        let state = db
+            .lock()
+            .unwrap()
+            .get_nonce_state(&from.to_string())
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.reservations[0].nonce, 7);
+        assert_eq!(state.reservations[0].status, NonceStatus::Reserved);
+    }
+
+    #[test]
+    fn rejects_mismatched_derivation_path() {
+        let mut tx = unsigned_tx(1, 0, GasParams::Legacy { gas_price: 1 });
+        tx.derivation_path = format!("{}{}", PATH, 2);
+
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let result = OfflineSigner::new(&mnemonic, None)
+            .unwrap()
+            .sign_bundle(&UnsignedBundle::new(vec![tx]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_signed_transaction() {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let bundle =
+            UnsignedBundle::new(vec![unsigned_tx(1, 0, GasParams::Legacy { gas_price: 1 })]);
+        let mut signed = OfflineSigner::new(&mnemonic, None)
+            .unwrap()
+            .sign_bundle(&bundle)
+            .unwrap();
+        signed.transactions[0].from = unsigned_tx(2, 0, GasParams::Legacy { gas_price: 1 }).from;
+        assert!(signed.verify().is_err());
+    }
+}
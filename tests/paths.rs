@@ -0,0 +1,176 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use ethserv::wallet::{
+        mnemonic::{MnemonicStorage, PassphraseStorage},
+        paths::WalletPaths,
+    };
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn second_lock_on_same_wallet_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = WalletPaths::resolve(dir.path(), Some("main"), "pw").unwrap();
+
+        let lock = paths.lock().unwrap();
+        let err = paths.lock().err().unwrap();
+        assert!(err.to_string().contains("already in use"));
+
+        drop(lock);
+        paths.lock().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn data_dir_and_mnemonic_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("data");
+        let paths = WalletPaths::resolve(&data_dir, Some("main"), "pw").unwrap();
+        MnemonicStorage::new(paths.mnemonic_path.clone())
+            .save_mnemonic(PHRASE, "pw")
+            .unwrap();
+
+        let mode =
+            |path: &std::path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&data_dir), 0o700);
+        assert_eq!(mode(&paths.mnemonic_path), 0o600);
+    }
+
+    #[test]
+    fn unusable_data_dir_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+
+        let err = WalletPaths::resolve(&file.join("data"), None, "pw")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("data directory"));
+    }
+
+    #[test]
+    fn rotate_password_moves_password_named_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = WalletPaths::resolve(dir.path(), None, "old").unwrap();
+        MnemonicStorage::new(paths.mnemonic_path.clone())
+            .save_mnemonic(PHRASE, "old")
+            .unwrap();
+
+        let new_paths = {
+            let _lock = paths.lock().unwrap();
+            let new_paths = paths.rotate_password(None, "old", "new").unwrap();
+            // Still held, so nobody else opens the old files meanwhile
+            assert!(paths.lock().is_err());
+            new_paths
+        };
+
+        assert!(!paths.mnemonic_path.exists());
+        assert_eq!(
+            new_paths,
+            WalletPaths::resolve(dir.path(), None, "new").unwrap()
+        );
+        let mnemonic = MnemonicStorage::new(new_paths.mnemonic_path)
+            .load_mnemonic("new")
+            .unwrap();
+        assert_eq!(mnemonic.to_phrase(), PHRASE);
+    }
+
+    #[test]
+    fn rotate_password_keeps_wallet_id_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = WalletPaths::resolve(dir.path(), Some("main"), "old").unwrap();
+        let storage = MnemonicStorage::new(paths.mnemonic_path.clone());
+        storage.save_mnemonic(PHRASE, "old").unwrap();
+        fs::write(&paths.wallet_path, "db").unwrap();
+
+        let new_paths = {
+            let _lock = paths.lock().unwrap();
+            paths.rotate_password(Some("main"), "old", "new").unwrap()
+        };
+
+        assert_eq!(new_paths, paths);
+        assert!(storage.load_mnemonic("old").is_err());
+        assert_eq!(storage.load_mnemonic("new").unwrap().to_phrase(), PHRASE);
+        assert_eq!(fs::read_to_string(&paths.wallet_path).unwrap(), "db");
+    }
+
+    #[test]
+    fn rotate_password_moves_the_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = WalletPaths::resolve(dir.path(), None, "old").unwrap();
+        MnemonicStorage::new(paths.mnemonic_path.clone())
+            .save_mnemonic(PHRASE, "old")
+            .unwrap();
+        fs::write(&paths.wallet_path, "db").unwrap();
+        PassphraseStorage::new(paths.passphrase_path.clone())
+            .save("secret", "old")
+            .unwrap();
+
+        let new_paths = paths.rotate_password(None, "old", "new").unwrap();
+
+        assert!(!paths.wallet_path.exists());
+        assert!(!paths.passphrase_path.exists());
+        assert_eq!(fs::read_to_string(&new_paths.wallet_path).unwrap(), "db");
+        let passphrase = PassphraseStorage::new(new_paths.passphrase_path)
+            .load("new")
+            .unwrap();
+        assert_eq!(passphrase.unwrap().as_str(), "secret");
+    }
+
+    #[test]
+    fn rotate_password_refuses_an_existing_wallet() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = WalletPaths::resolve(dir.path(), None, "old").unwrap();
+        MnemonicStorage::new(paths.mnemonic_path.clone())
+            .save_mnemonic(PHRASE, "old")
+            .unwrap();
+        let taken = WalletPaths::resolve(dir.path(), None, "new").unwrap();
+        MnemonicStorage::new(taken.mnemonic_path.clone())
+            .save_mnemonic(PHRASE, "new")
+            .unwrap();
+
+        let err = paths.rotate_password(None, "old", "new").err().unwrap();
+        assert!(err.to_string().contains("already exists"));
+        let mnemonic = MnemonicStorage::new(paths.mnemonic_path.clone())
+            .load_mnemonic("old")
+            .unwrap();
+        assert_eq!(mnemonic.to_phrase(), PHRASE);
+    }
+
+    #[test]
+    fn adopt_wallet_id_renames_password_named_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = WalletPaths::resolve(dir.path(), None, "pw").unwrap();
+        MnemonicStorage::new(paths.mnemonic_path.clone())
+            .save_mnemonic(PHRASE, "pw")
+            .unwrap();
+        fs::write(&paths.wallet_path, "db").unwrap();
+
+        let new_paths = paths.adopt_wallet_id("main").unwrap();
+
+        assert_eq!(
+            new_paths,
+            WalletPaths::resolve(dir.path(), Some("main"), "").unwrap()
+        );
+        assert!(!paths.mnemonic_path.exists());
+        assert!(!paths.wallet_path.exists());
+        assert_eq!(fs::read_to_string(&new_paths.wallet_path).unwrap(), "db");
+        let mnemonic = MnemonicStorage::new(new_paths.mnemonic_path.clone())
+            .load_mnemonic("pw")
+            .unwrap();
+        assert_eq!(mnemonic.to_phrase(), PHRASE);
+
+        // Another wallet adopting the same id is refused
+        let other = WalletPaths::resolve(dir.path(), None, "other").unwrap();
+        MnemonicStorage::new(other.mnemonic_path.clone())
+            .save_mnemonic(PHRASE, "other")
+            .unwrap();
+        assert!(other.adopt_wallet_id("main").is_err());
+        assert!(other.mnemonic_path.exists());
+    }
+}
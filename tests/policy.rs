@@ -0,0 +1,398 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Mutex};
+
+    use alloy::primitives::{address, Address, Bytes, U256};
+    use ethserv::{
+        error::Error,
+        wallet::{
+            database::WalletDatabase,
+            offline::{GasParams, UnsignedTransaction},
+            policy::{
+                self, AuditAction, Decision, NewWithdrawal, WithdrawalPolicy, WithdrawalStatus,
+                DAILY_WINDOW,
+            },
+            tracker::TxStatus,
+        },
+    };
+
+    const FROM: Address = address!("9858EfFD232B4033E47d90003D41EC34EcaEda94");
+    const TO: Address = address!("dAC17F958D2ee523a2206206994597C13D831ec7");
+    const DENIED: Address = address!("0000000000000000000000000000000000000bad");
+
+    fn policy() -> WithdrawalPolicy {
+        WithdrawalPolicy {
+            max_amount: Some(U256::from(1_000)),
+            daily_limit: Some(U256::from(1_500)),
+            approval_threshold: Some(U256::from(100)),
+            required_approvals: 2,
+            allowlist: None,
+            denylist: [DENIED].into(),
+        }
+    }
+
+    fn withdrawal(amount: u64) -> NewWithdrawal {
+        NewWithdrawal {
+            from: FROM,
+            to: TO,
+            amount: U256::from(amount),
+            urgency: None,
+            requested_by: String::from("carol"),
+        }
+    }
+
+    fn violation(e: anyhow::Error) -> String {
+        match Error::from(e) {
+            Error::PolicyViolation(msg) => msg,
+            e => panic!("expected a policy violation, got {}", e),
+        }
+    }
+
+    fn transaction() -> UnsignedTransaction {
+        UnsignedTransaction {
+            from: FROM,
+            derivation_path: String::from("m/44'/60'/0'/0/1"),
+            chain_id: 1,
+            nonce: 0,
+            to: TO,
+            value: U256::ZERO,
+            input: Bytes::new(),
+            gas_limit: 100_000,
+            gas: GasParams::Legacy {
+                gas_price: 1_000_000_000,
+            },
+        }
+    }
+
+    /// Records a sent transaction for request `id` and settles it.
+    fn execute(db: &Mutex<WalletDatabase>, id: i64, actor: &str) -> i64 {
+        let tx = UnsignedTransaction {
+            nonce: id as u64,
+            ..transaction()
+        };
+        let db = db.lock().unwrap();
+        let request = db.get_withdrawal_request(id).unwrap().unwrap();
+        let hash = format!("0x{:064x}", id);
+        db.insert_withdrawal_transaction(&request, actor, &tx, &hash, "0x01")
+            .unwrap()
+    }
+
+    #[test]
+    fn checks_limits_and_lists() {
+        let mut policy = policy();
+        let nothing = U256::ZERO;
+        assert_eq!(
+            policy.evaluate(TO, U256::from(100), nothing),
+            Decision::Approve
+        );
+        assert_eq!(
+            policy.evaluate(TO, U256::from(101), nothing),
+            Decision::AwaitApproval
+        );
+        assert!(matches!(
+            policy.evaluate(DENIED, U256::from(1), nothing),
+            Decision::Deny(_)
+        ));
+        assert!(matches!(
+            policy.evaluate(TO, U256::from(1_001), nothing),
+            Decision::Deny(_)
+        ));
+        assert_eq!(
+            policy.evaluate(TO, U256::from(500), U256::from(1_000)),
+            Decision::AwaitApproval
+        );
+        assert!(matches!(
+            policy.evaluate(TO, U256::from(501), U256::from(1_000)),
+            Decision::Deny(_)
+        ));
+
+        policy.allowlist = Some(HashSet::from([FROM]));
+        assert!(matches!(
+            policy.evaluate(TO, U256::from(1), nothing),
+            Decision::Deny(_)
+        ));
+        assert_eq!(
+            policy.evaluate(FROM, U256::from(1), nothing),
+            Decision::Approve
+        );
+    }
+
+    #[test]
+    fn requires_approvals_of_other_operators() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let policy = policy();
+
+        let small = policy::request(&db, &policy, &withdrawal(100)).unwrap();
+        assert_eq!(small.status, WithdrawalStatus::Approved);
+
+        let request = policy::request(&db, &policy, &withdrawal(500)).unwrap();
+        assert_eq!(request.status, WithdrawalStatus::AwaitingApproval);
+        let id = request.id;
+
+        let e = policy::approve(&db, &policy, id, "carol", None).unwrap_err();
+        assert!(violation(e).contains("own withdrawals"));
+        let request = policy::approve(&db, &policy, id, "alice", Some("checked")).unwrap();
+        assert_eq!(request.status, WithdrawalStatus::AwaitingApproval);
+        assert!(matches!(
+            Error::from(policy::approve(&db, &policy, id, "alice", None).unwrap_err()),
+            Error::Conflict(_)
+        ));
+        let request = policy::approve(&db, &policy, id, "bob", None).unwrap();
+        assert_eq!(request.status, WithdrawalStatus::Approved);
+        assert_eq!(request.approvals.len(), 2);
+        assert_eq!(request.approvals[0].comment.as_deref(), Some("checked"));
+
+        let tx_id = execute(&db, id, "bob");
+        let request = db
+            .lock()
+            .unwrap()
+            .get_withdrawal_request(id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.status, WithdrawalStatus::Executed);
+        assert_eq!(request.transaction_id, Some(tx_id));
+        assert!(policy::reject(&db, id, "alice", None).is_err());
+
+        // Speed-ups are filed under the request their transaction was sent for
+        db.lock()
+            .unwrap()
+            .record_audit_entry("alice", AuditAction::SpedUp, Some(tx_id), None)
+            .unwrap();
+        let trail: Vec<_> = db
+            .lock()
+            .unwrap()
+            .list_audit_entries(Some(id), 100)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.actor, entry.action))
+            .collect();
+        assert_eq!(
+            trail,
+            [
+                (String::from("carol"), AuditAction::Requested),
+                (String::from("alice"), AuditAction::Approved),
+                (String::from("bob"), AuditAction::Approved),
+                (String::from("bob"), AuditAction::Executed),
+                (String::from("alice"), AuditAction::SpedUp),
+            ]
+        );
+    }
+
+    #[test]
+    fn enforces_the_daily_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let policy = policy();
+
+        let first = policy::request(&db, &policy, &withdrawal(1_000)).unwrap();
+        let second = policy::request(&db, &policy, &withdrawal(400)).unwrap();
+        let e = policy::request(&db, &policy, &withdrawal(200)).unwrap_err();
+        assert!(violation(e).contains("daily limit"));
+
+        // The denied request is on record, with the policy as its actor
+        let denied = db
+            .lock()
+            .unwrap()
+            .list_withdrawal_requests(Some(WithdrawalStatus::Denied), 10)
+            .unwrap();
+        assert_eq!(denied.len(), 1);
+        let trail = db
+            .lock()
+            .unwrap()
+            .list_audit_entries(Some(denied[0].id), 10)
+            .unwrap();
+        assert_eq!(trail[1].actor, policy::POLICY_ACTOR);
+        assert_eq!(trail[1].action, AuditAction::Denied);
+
+        // Rejected requests and withdrawals that moved nothing free the limit
+        policy::reject(&db, second.id, "carol", Some("wrong amount")).unwrap();
+        policy::approve(&db, &policy, first.id, "alice", None).unwrap();
+        policy::approve(&db, &policy, first.id, "bob", None).unwrap();
+        let tx_id = execute(&db, first.id, "bob");
+        assert_eq!(
+            db.lock()
+                .unwrap()
+                .get_withdrawn_amount(DAILY_WINDOW, None)
+                .unwrap(),
+            U256::from(1_000)
+        );
+        db.lock()
+            .unwrap()
+            .set_transaction_status(tx_id, TxStatus::Dropped, "0x01", None)
+            .unwrap();
+        assert_eq!(
+            db.lock()
+                .unwrap()
+                .get_withdrawn_amount(DAILY_WINDOW, None)
+                .unwrap(),
+            U256::ZERO
+        );
+        assert_eq!(
+            policy::request(&db, &policy, &withdrawal(600))
+                .unwrap()
+                .status,
+            WithdrawalStatus::AwaitingApproval
+        );
+    }
+
+    #[test]
+    fn checks_the_daily_limit_again_on_the_last_approval() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let mut policy = policy();
+        let first = policy::request(&db, &policy, &withdrawal(1_000)).unwrap();
+        let second = policy::request(&db, &policy, &withdrawal(500)).unwrap();
+
+        // The limit was lowered while both waited, the first no longer fits
+        policy.daily_limit = Some(U256::from(1_200));
+        policy::approve(&db, &policy, first.id, "alice", None).unwrap();
+        let e = policy::approve(&db, &policy, first.id, "bob", None).unwrap_err();
+        assert!(violation(e).contains("daily limit"));
+        let denied = db
+            .lock()
+            .unwrap()
+            .get_withdrawal_request(first.id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(denied.status, WithdrawalStatus::Denied);
+        assert_eq!(denied.approvals.len(), 2);
+        let trail = db
+            .lock()
+            .unwrap()
+            .list_audit_entries(Some(first.id), 10)
+            .unwrap();
+        let last = trail.last().unwrap();
+        assert_eq!(
+            (last.actor.as_str(), last.action),
+            (policy::POLICY_ACTOR, AuditAction::Denied)
+        );
+
+        // A request does not count against itself
+        policy::approve(&db, &policy, second.id, "alice", None).unwrap();
+        let approved = policy::approve(&db, &policy, second.id, "bob", None).unwrap();
+        assert_eq!(approved.status, WithdrawalStatus::Approved);
+        assert_eq!(
+            db.lock()
+                .unwrap()
+                .get_withdrawn_amount(DAILY_WINDOW, Some(second.id))
+                .unwrap(),
+            U256::ZERO
+        );
+    }
+
+    #[test]
+    fn fails_only_requests_without_a_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let policy = policy();
+        let sent = policy::request(&db, &policy, &withdrawal(50)).unwrap();
+        let unsent = policy::request(&db, &policy, &withdrawal(60)).unwrap();
+
+        // The transaction was recorded, then its broadcast failed
+        let tx_id = execute(&db, sent.id, "carol");
+        let error = anyhow::anyhow!("connection reset");
+        policy::fail(&db, sent.id, "carol", &error).unwrap();
+        policy::fail(&db, unsent.id, "carol", &error).unwrap();
+
+        let db_lock = db.lock().unwrap();
+        let request = db_lock.get_withdrawal_request(sent.id).unwrap().unwrap();
+        assert_eq!(request.status, WithdrawalStatus::Executed);
+        assert_eq!(request.transaction_id, Some(tx_id));
+        let request = db_lock.get_withdrawal_request(unsent.id).unwrap().unwrap();
+        assert_eq!(request.status, WithdrawalStatus::Failed);
+        assert_eq!(request.reason.as_deref(), Some("connection reset"));
+
+        // The live transfer still counts against the limit
+        assert_eq!(
+            db_lock.get_withdrawn_amount(DAILY_WINDOW, None).unwrap(),
+            U256::from(50)
+        );
+        // And its request cannot be sent again
+        assert!(db_lock
+            .insert_withdrawal_transaction(&sent, "carol", &transaction(), "0x02", "0x02")
+            .is_err());
+    }
+
+    #[test]
+    fn recovers_approved_requests_left_unsent() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let policy = policy();
+        let rejected = policy::request(&db, &policy, &withdrawal(50)).unwrap();
+        let interrupted = policy::request(&db, &policy, &withdrawal(60)).unwrap();
+        let sent = policy::request(&db, &policy, &withdrawal(70)).unwrap();
+        execute(&db, sent.id, "carol");
+
+        // An operator can still stop an approved request that was not sent
+        let request = policy::reject(&db, rejected.id, "alice", Some("stuck")).unwrap();
+        assert_eq!(request.status, WithdrawalStatus::Rejected);
+        assert!(matches!(
+            Error::from(policy::reject(&db, sent.id, "alice", None).unwrap_err()),
+            Error::Conflict(_)
+        ));
+        assert!(db
+            .lock()
+            .unwrap()
+            .insert_withdrawal_transaction(&rejected, "carol", &transaction(), "0x03", "0x03")
+            .is_err());
+
+        assert_eq!(policy::recover(&db).unwrap(), [interrupted.id]);
+        let request = db
+            .lock()
+            .unwrap()
+            .get_withdrawal_request(interrupted.id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.status, WithdrawalStatus::Failed);
+        assert!(policy::recover(&db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn denies_an_approved_request_before_sending() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let policy = policy();
+        let frozen = policy::request(&db, &policy, &withdrawal(50)).unwrap();
+        let sent = policy::request(&db, &policy, &withdrawal(60)).unwrap();
+        execute(&db, sent.id, "carol");
+
+        let reason = String::from("Destination is on the USDT blacklist");
+        let e = policy::revoke(&db, frozen.id, reason.clone());
+        assert_eq!(violation(e), reason);
+        let request = db
+            .lock()
+            .unwrap()
+            .get_withdrawal_request(frozen.id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.status, WithdrawalStatus::Denied);
+        let trail = db
+            .lock()
+            .unwrap()
+            .list_audit_entries(Some(frozen.id), 10)
+            .unwrap();
+        let last = trail.last().unwrap();
+        assert_eq!(
+            (last.actor.as_str(), last.action),
+            (policy::POLICY_ACTOR, AuditAction::Denied)
+        );
+
+        // A request already sent is left alone
+        let e = policy::revoke(&db, sent.id, reason);
+        assert!(matches!(Error::from(e), Error::Internal(_)));
+    }
+
+    #[test]
+    fn parses_operators() {
+        let operators = policy::parse_operators(Some("alice=t1, bob = t2,")).unwrap();
+        let names: Vec<_> = operators.iter().map(|op| op.name.as_str()).collect();
+        assert_eq!(names, ["alice", "bob"]);
+        assert_eq!(operators[1].token, "t2");
+
+        assert!(policy::parse_operators(None).unwrap().is_empty());
+        assert!(policy::parse_operators(Some("alice")).is_err());
+        assert!(policy::parse_operators(Some("admin=t1")).is_err());
+        assert!(policy::parse_operators(Some("alice=t1,bob=t1")).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use alloy::signers::local::coins_bip39::{English, Mnemonic};
+    use ethserv::wallet::{
+        accounts::Account,
+        database::WalletDatabase,
+        derivation::AddressDeriver,
+        mnemonic::MnemonicStorage,
+        restore::{check_fingerprint, check_unclaimed, import_mnemonic, parse_phrase},
+    };
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const OTHER_PHRASE: &str =
+        "legal winner thank year wave sausage worth useful legal winner thank yellow";
+    const PATH: &str = "m/44'/60'/0'/0/";
+
+    fn accounts() -> Vec<Account> {
+        vec![Account::new("default", PATH).unwrap()]
+    }
+
+    #[test]
+    fn parses_and_validates_phrases() {
+        let messy = format!("  {}  \n", PHRASE.to_uppercase().replace(' ', "   "));
+        assert_eq!(parse_phrase(&messy).unwrap().to_phrase(), PHRASE);
+
+        // Valid words, wrong checksum
+        assert!(parse_phrase(&PHRASE.replace("about", "abandon")).is_err());
+        assert!(parse_phrase("abandon abandon abandon").is_err());
+    }
+
+    #[test]
+    fn imports_and_rebuilds_addresses() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MnemonicStorage::new(dir.path().join("mnemonic.dat"));
+        let db = WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap();
+
+        let (_, restored) =
+            import_mnemonic(&storage, &db, "pw", PHRASE, None, &accounts(), 10).unwrap();
+        assert_eq!(restored, 10);
+        assert_eq!(storage.load_mnemonic("pw").unwrap().to_phrase(), PHRASE);
+
+        // Rescanning again only fills in what is missing
+        let (_, restored) =
+            import_mnemonic(&storage, &db, "pw", PHRASE, None, &accounts(), 15).unwrap();
+        assert_eq!(restored, 5);
+
+        // A different seed must not be imported over issued addresses
+        assert!(import_mnemonic(&storage, &db, "pw", OTHER_PHRASE, None, &accounts(), 0).is_err());
+        assert_eq!(storage.load_mnemonic("pw").unwrap().to_phrase(), PHRASE);
+    }
+
+    #[test]
+    fn fingerprint_detects_foreign_seed() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap();
+        let deriver = |phrase| {
+            AddressDeriver::new(
+                &Mnemonic::<English>::new_from_phrase(phrase).unwrap(),
+                None,
+                PATH,
+            )
+            .unwrap()
+        };
+
+        // First start records the fingerprint, later starts verify it
+        check_fingerprint(&db, &deriver(PHRASE), PATH).unwrap();
+        check_fingerprint(&db, &deriver(PHRASE), PATH).unwrap();
+        assert!(check_fingerprint(&db, &deriver(OTHER_PHRASE), PATH).is_err());
+    }
+
+    #[test]
+    fn import_keeps_the_seed_of_an_empty_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MnemonicStorage::new(dir.path().join("mnemonic.dat"));
+        let db = WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap();
+        check_unclaimed(&db).unwrap();
+
+        // Fingerprinted for the first seed, but no address issued yet
+        let deriver = AddressDeriver::new(
+            &Mnemonic::<English>::new_from_phrase(PHRASE).unwrap(),
+            None,
+            PATH,
+        )
+        .unwrap();
+        check_fingerprint(&db, &deriver, PATH).unwrap();
+        assert!(check_unclaimed(&db).is_err());
+
+        let err = import_mnemonic(&storage, &db, "pw", OTHER_PHRASE, None, &accounts(), 0)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("belongs to seed"));
+        assert!(!storage.exists());
+        import_mnemonic(&storage, &db, "pw", PHRASE, None, &accounts(), 0).unwrap();
+    }
+
+    #[test]
+    fn import_requires_the_same_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = MnemonicStorage::new(dir.path().join("mnemonic.dat"));
+        let db = WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap();
+
+        import_mnemonic(&storage, &db, "pw", PHRASE, Some("secret"), &accounts(), 3).unwrap();
+        let err = import_mnemonic(&storage, &db, "pw", PHRASE, None, &accounts(), 0)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("passphrase"));
+        import_mnemonic(&storage, &db, "pw", PHRASE, Some("secret"), &accounts(), 0).unwrap();
+    }
+}
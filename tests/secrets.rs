@@ -0,0 +1,26 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use ethserv::config::secrets::{password_from, read_password_file};
+
+    #[test]
+    fn password_file_uses_first_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pw");
+        fs::write(&path, "correct horse\r\nsecond line\n").unwrap();
+
+        assert_eq!(read_password_file(&path).unwrap().as_str(), "correct horse");
+        let password = password_from(Some(path.to_str().unwrap())).unwrap();
+        assert_eq!(password.unwrap().as_str(), "correct horse");
+    }
+
+    #[test]
+    fn missing_password_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing");
+
+        let err = read_password_file(&path).err().unwrap();
+        assert!(err.to_string().contains("Failed to read password file"));
+    }
+}
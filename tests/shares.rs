@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod tests {
+    use alloy::signers::local::coins_bip39::{English, Mnemonic};
+    use ethserv::wallet::shares::{combine, split, Share};
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const LONG_PHRASE: &str = "legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth useful legal winner thank year wave sausage worth title";
+
+    fn reparse(share: &Share, words: bool) -> Share {
+        let text = if words {
+            share.to_words()
+        } else {
+            share.to_qr_string()
+        };
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn any_threshold_of_shares_recovers_the_seed() {
+        for phrase in [PHRASE, LONG_PHRASE] {
+            let mnemonic = Mnemonic::<English>::new_from_phrase(phrase).unwrap();
+            let shares = split(&mnemonic, 3, 5).unwrap();
+
+            for picked in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+                let subset = picked
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &p)| reparse(&shares[p], i % 2 == 0))
+                    .collect::<Vec<_>>();
+                assert_eq!(combine(&subset).unwrap().to_phrase(), phrase);
+            }
+
+            let too_few = vec![reparse(&shares[0], true), reparse(&shares[1], true)];
+            assert!(combine(&too_few).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_typos_and_mixed_sets() {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let shares = split(&mnemonic, 2, 3).unwrap();
+        let other = split(&mnemonic, 2, 3).unwrap();
+
+        let words = shares[0].to_words();
+        let mut typo = words.split(' ').map(String::from).collect::<Vec<_>>();
+        typo[7] = if typo[7] == "zoo" { "zone" } else { "zoo" }.to_string();
+        assert!(typo.join(" ").parse::<Share>().is_err());
+        assert!(format!("{} abandon", words.as_str())
+            .parse::<Share>()
+            .is_err());
+
+        let mixed = vec![reparse(&shares[0], true), reparse(&other[1], true)];
+        assert!(combine(&mixed).is_err());
+        let repeated = vec![reparse(&shares[0], true), reparse(&shares[0], false)];
+        assert!(combine(&repeated).is_err());
+
+        assert!(split(&mnemonic, 1, 3).is_err());
+        assert!(split(&mnemonic, 4, 3).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod tests {
+    use alloy::{
+        primitives::b256,
+        signers::{
+            local::{
+                coins_bip39::{English, Mnemonic},
+                MnemonicBuilder,
+            },
+            SignerSync,
+        },
+    };
+    use alloy_dyn_abi::TypedData;
+    use ethserv::wallet::{
+        database::AddressRecord, derivation::AddressDeriver, keys::ProtectedKey, signing,
+    };
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    const PATH: &str = "m/44'/60'/0'/0/";
+
+    /// The `Mail` example of EIP-712.
+    const MAIL: &str = r#"{
+        "types": {
+            "EIP712Domain": [
+                {"name": "name", "type": "string"},
+                {"name": "version", "type": "string"},
+                {"name": "chainId", "type": "uint256"},
+                {"name": "verifyingContract", "type": "address"}
+            ],
+            "Person": [
+                {"name": "name", "type": "string"},
+                {"name": "wallet", "type": "address"}
+            ],
+            "Mail": [
+                {"name": "from", "type": "Person"},
+                {"name": "to", "type": "Person"},
+                {"name": "contents", "type": "string"}
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+            "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    fn record(index: u32) -> AddressRecord {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        let deriver = AddressDeriver::new(&mnemonic, None, PATH).unwrap();
+        AddressRecord {
+            address: deriver.derive(index).unwrap().to_string(),
+            account: String::from("default"),
+            path: PATH.to_string(),
+            addr_index: index,
+            customer_id: None,
+            label: None,
+            idempotency_key: None,
+        }
+    }
+
+    fn account_key() -> ProtectedKey {
+        let mnemonic = Mnemonic::<English>::new_from_phrase(PHRASE).unwrap();
+        ProtectedKey::account(&mnemonic, None, PATH).unwrap()
+    }
+
+    #[test]
+    fn signs_messages_like_personal_sign() {
+        let signer = signing::signer_for(&account_key(), &record(3)).unwrap();
+        let expected = MnemonicBuilder::<English>::default()
+            .phrase(PHRASE)
+            .derivation_path(format!("{}3", PATH))
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(signer.address(), expected.address());
+
+        let signed = signing::sign_message(&signer, b"I control this address").unwrap();
+        assert_eq!(
+            signed.signature.as_ref(),
+            expected
+                .sign_message_sync(b"I control this address")
+                .unwrap()
+                .as_bytes()
+        );
+        assert_eq!(
+            signing::recover_message_signer(b"I control this address", &signed.signature).unwrap(),
+            signer.address()
+        );
+        assert_ne!(
+            signing::recover_message_signer(b"Something else", &signed.signature).unwrap(),
+            signer.address()
+        );
+        assert!(signing::recover_message_signer(b"x", &signed.signature[..64]).is_err());
+    }
+
+    #[test]
+    fn signs_typed_data() {
+        let signer = signing::signer_for(&account_key(), &record(1)).unwrap();
+        let typed_data: TypedData = serde_json::from_str(MAIL).unwrap();
+
+        let signed = signing::sign_typed_data(&signer, &typed_data).unwrap();
+        assert_eq!(
+            signed.hash,
+            b256!("be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2")
+        );
+        assert_eq!(
+            signing::recover_typed_data_signer(&typed_data, &signed.signature).unwrap(),
+            signer.address()
+        );
+    }
+
+    #[test]
+    fn rejects_a_record_with_the_wrong_index() {
+        let mut forged = record(2);
+        forged.addr_index = 5;
+        assert!(signing::signer_for(&account_key(), &forged).is_err());
+    }
+}
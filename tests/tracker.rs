@@ -0,0 +1,269 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::Mutex,
+    };
+
+    use alloy::primitives::{address, Address, Bytes, B256, U256};
+    use alloy::transports::{TransportError, TransportErrorKind};
+    use anyhow::{anyhow, Result};
+    use ethserv::wallet::{
+        database::WalletDatabase,
+        nonces::NonceSource,
+        offline::{GasParams, UnsignedTransaction},
+        tracker::{is_nonce_used, is_rejected, poll, TxChain, TxOutcome, TxStatus, MAX_BROADCASTS},
+    };
+
+    const FROM: Address = address!("9858EfFD232B4033E47d90003D41EC34EcaEda94");
+    const CONFIRMATIONS: u64 = 3;
+
+    #[derive(Default)]
+    struct FakeChain {
+        head: u64,
+        latest_nonce: u64,
+        receipts: HashMap<B256, TxOutcome>,
+        known: HashSet<B256>,
+        sent: Mutex<Vec<Bytes>>,
+    }
+
+    impl NonceSource for FakeChain {
+        async fn pending_nonce(&self, _address: Address) -> Result<u64> {
+            Ok(self.latest_nonce)
+        }
+
+        async fn is_known(&self, hash: B256) -> Result<bool> {
+            Ok(self.known.contains(&hash) || self.receipts.contains_key(&hash))
+        }
+    }
+
+    impl TxChain for FakeChain {
+        async fn block_number(&self) -> Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn latest_nonce(&self, _address: Address) -> Result<u64> {
+            Ok(self.latest_nonce)
+        }
+
+        async fn receipt(&self, hash: B256) -> Result<Option<TxOutcome>> {
+            Ok(self.receipts.get(&hash).copied())
+        }
+
+        async fn send_raw(&self, raw: &[u8]) -> Result<()> {
+            self.sent.lock().unwrap().push(Bytes::copy_from_slice(raw));
+            Ok(())
+        }
+    }
+
+    fn hash(n: u8) -> B256 {
+        B256::repeat_byte(n)
+    }
+
+    fn unsigned(nonce: u64) -> UnsignedTransaction {
+        UnsignedTransaction {
+            from: FROM,
+            derivation_path: String::from("m/44'/60'/0'/0/1"),
+            chain_id: 1,
+            nonce,
+            to: address!("dAC17F958D2ee523a2206206994597C13D831ec7"),
+            value: U256::ZERO,
+            input: Bytes::new(),
+            gas_limit: 100_000,
+            gas: GasParams::Legacy {
+                gas_price: 1_000_000_000,
+            },
+        }
+    }
+
+    fn track(db: &Mutex<WalletDatabase>, nonce: u64, n: u8) -> i64 {
+        let db = db.lock().unwrap();
+        let id = db
+            .insert_transaction(&unsigned(nonce), &hash(n).to_string(), "0x01", None, None)
+            .unwrap();
+        db.record_broadcast(id).unwrap();
+        id
+    }
+
+    fn mined(block_number: u64, success: bool) -> TxOutcome {
+        TxOutcome {
+            block_number,
+            success,
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_replacements_until_final() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let sped_up = track(&db, 0, 1);
+        let cancelled = track(&db, 1, 3);
+        let reverted = track(&db, 2, 5);
+        {
+            let db = db.lock().unwrap();
+            db.replace_transaction(sped_up, &unsigned(0), &hash(2).to_string(), "0x02", false)
+                .unwrap();
+            db.replace_transaction(cancelled, &unsigned(1), &hash(4).to_string(), "0x04", true)
+                .unwrap();
+        }
+
+        let mut chain = FakeChain {
+            head: 100,
+            latest_nonce: 3,
+            known: [hash(2)].into(),
+            ..Default::default()
+        };
+        // The original of the sped up transaction won the race after all
+        chain.receipts.insert(hash(1), mined(100, true));
+        chain.receipts.insert(hash(4), mined(100, true));
+        chain.receipts.insert(hash(5), mined(100, false));
+
+        let changed = poll(&db, &chain, CONFIRMATIONS).await.unwrap();
+        assert_eq!(changed.len(), 3);
+        assert!(changed.iter().all(|tx| tx.status == TxStatus::Mined));
+        assert_eq!(changed[0].tx_hash, hash(1).to_string());
+        assert_eq!(changed[0].event().topic(), "withdrawal.mined");
+        assert!(poll(&db, &chain, CONFIRMATIONS).await.unwrap().is_empty());
+
+        chain.head = 102;
+        let changed = poll(&db, &chain, CONFIRMATIONS).await.unwrap();
+        let statuses: Vec<_> = changed.iter().map(|tx| (tx.id, tx.status)).collect();
+        assert_eq!(
+            statuses,
+            [
+                (sped_up, TxStatus::Confirmed),
+                (cancelled, TxStatus::Cancelled),
+                (reverted, TxStatus::Failed)
+            ]
+        );
+        assert!(db
+            .lock()
+            .unwrap()
+            .get_open_transactions()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn rebroadcasts_forgotten_transactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let nonce = db
+            .lock()
+            .unwrap()
+            .reserve_nonce(&FROM.to_string(), 4)
+            .unwrap();
+        let id = track(&db, nonce, 1);
+        db.lock()
+            .unwrap()
+            .mark_nonce_sent(&FROM.to_string(), nonce, &hash(1).to_string())
+            .unwrap();
+
+        let chain = FakeChain {
+            head: 100,
+            latest_nonce: 4,
+            ..Default::default()
+        };
+        for _ in 1..MAX_BROADCASTS {
+            assert!(poll(&db, &chain, CONFIRMATIONS).await.unwrap().is_empty());
+        }
+        assert_eq!(chain.sent.lock().unwrap().len() as u32, MAX_BROADCASTS - 1);
+        assert_eq!(chain.sent.lock().unwrap()[0].as_ref(), [1]);
+
+        // Given up, and its nonce goes to the next transaction
+        let changed = poll(&db, &chain, CONFIRMATIONS).await.unwrap();
+        assert_eq!(changed[0].id, id);
+        assert_eq!(changed[0].status, TxStatus::Dropped);
+        assert_eq!(
+            db.lock()
+                .unwrap()
+                .reserve_nonce(&FROM.to_string(), 0)
+                .unwrap(),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn detects_reorgs_and_taken_nonces() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let reorged = track(&db, 0, 1);
+        let replaced = track(&db, 1, 2);
+
+        let mut chain = FakeChain {
+            head: 100,
+            latest_nonce: 1,
+            known: [hash(1)].into(),
+            ..Default::default()
+        };
+        chain.receipts.insert(hash(1), mined(100, true));
+        let changed = poll(&db, &chain, CONFIRMATIONS).await.unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].block_number, Some(100));
+
+        // The block was reorged out, the transaction is back in the mempool
+        chain.receipts.clear();
+        chain.latest_nonce = 0;
+        let changed = poll(&db, &chain, CONFIRMATIONS).await.unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].status, TxStatus::Pending);
+        assert_eq!(changed[0].block_number, None);
+
+        // Mined again, while another transaction took nonce 1
+        chain.receipts.insert(hash(1), mined(101, true));
+        chain.latest_nonce = 2;
+        let changed = poll(&db, &chain, CONFIRMATIONS).await.unwrap();
+        let statuses: Vec<_> = changed.iter().map(|tx| (tx.id, tx.status)).collect();
+        assert_eq!(
+            statuses,
+            [(reorged, TxStatus::Mined), (replaced, TxStatus::Dropped)]
+        );
+    }
+
+    /// The error of a node answering with `message`, parsed like alloy
+    /// parses responses.
+    fn error_response(message: &str) -> anyhow::Error {
+        let body = serde_json::json!({ "code": -32000, "message": message }).to_string();
+        let err = serde_json::from_str::<u64>(&body).unwrap_err();
+        let e: TransportError = TransportError::deser_err(err, body);
+        e.into()
+    }
+
+    #[test]
+    fn only_error_responses_reject_a_broadcast() {
+        assert!(is_rejected(&error_response("nonce too low")));
+        assert!(is_rejected(&error_response(
+            "replacement transaction underpriced"
+        )));
+        assert!(!is_rejected(&error_response("already known")));
+        assert!(!is_rejected(&error_response("Known transaction: 0x01")));
+        assert!(!is_rejected(&TransportErrorKind::BackendGone.into()));
+        assert!(!is_rejected(&anyhow!("request timed out")));
+
+        assert!(is_nonce_used(&error_response("Nonce too low")));
+        assert!(!is_nonce_used(&error_response("insufficient funds")));
+        assert!(!is_nonce_used(&anyhow!("nonce too low")));
+    }
+
+    #[test]
+    fn reverts_a_rejected_replacement() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Mutex::new(WalletDatabase::new(dir.path().join("wallet.sqlite")).unwrap());
+        let id = track(&db, 0, 1);
+        let db = db.lock().unwrap();
+        let previous = db.get_transaction(id).unwrap().unwrap();
+        db.replace_transaction(id, &unsigned(0), &hash(2).to_string(), "0x02", true)
+            .unwrap();
+        db.revert_replacement(&previous, "0x01", &hash(2).to_string())
+            .unwrap();
+
+        let tx = db.get_transaction(id).unwrap().unwrap();
+        assert_eq!(tx.tx_hash, hash(1).to_string());
+        assert_eq!(tx.broadcasts, previous.broadcasts);
+        assert!(!tx.cancel_requested);
+        assert_eq!(db.get_transaction_raw(id).unwrap(), "0x01");
+        let attempts = db.get_transaction_attempts(id).unwrap();
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].tx_hash, hash(1).to_string());
+    }
+}